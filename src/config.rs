@@ -1,42 +1,228 @@
 use crate::consts::ID_ENV_VAR;
+use crate::stats::{CONTROLLERS, CgroupVersion, GpuCountChange};
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
+use tracing::warn;
 use uuid::Uuid;
 
 pub struct JsonlToStdoutConfig {
     pub prefix: String,
+    pub envelope: Option<Envelope>,
 }
 
 pub struct StatsDirConfig {
     pub dir: PathBuf,
     pub max_stats_entries: usize,
+    /// Also remove stat files older than this, going by the timestamp in their name
+    pub max_age: Option<Duration>,
+    pub file_mode: Option<u32>,
+    pub envelope: Option<Envelope>,
+    pub watch_fs_space: bool,
+    /// Gzip each stat file, as `stats-<timestamp>.json.gz`
+    pub compress: bool,
+    /// Append entries to `stats.jsonl` instead, rotating it once it would grow past this many bytes
+    pub jsonl_max_bytes: Option<u64>,
+}
+
+pub struct HttpPushConfig {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub timeout: Duration,
+    pub batch_size: usize,
+    pub flush_interval: Option<Duration>,
+    pub envelope: Option<Envelope>,
+}
+
+pub struct PrometheusConfig {
+    pub host: Option<IpAddr>, // `None` for all interfaces, IPv6 included where the host has it
+    pub port: u16,
+}
+
+pub struct StatsdConfig {
+    pub addr: String,   // `host:port`, resolved on every push
+    pub prefix: String, // what metric names start with, e.g. `acolyte.`
+}
+
+/// A JSON object to nest each entry in for the JSON outputs,
+/// e.g. `{"type": "acolyte_stats", "data": {...}}` with the key `data`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    pub key: String,                     // what the entry goes under
+    pub metadata: Vec<(String, String)>, // static fields next to it
+}
+
+#[cfg(feature = "remote-write")]
+pub struct RemoteWriteConfig {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub timeout: Duration,
+    pub batch_size: usize,
+    pub flush_interval: Option<Duration>,
+}
+
+#[cfg(feature = "sqlite")]
+pub struct SqliteConfig {
+    pub path: PathBuf,
+    pub max_stats_entries: usize,
+}
+
+#[cfg(feature = "binary-stream")]
+pub struct BinaryStreamConfig {
+    /// File or named pipe to write to; not standard output, which the logs go to
+    pub path: PathBuf,
+}
+
+/// What to do with the proc source, whose numbers are for the whole host rather than the container
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ProcScope {
+    /// Fall back to it, e.g. on a node that runs only the one container
+    Host,
+    /// Leave it out, as no numbers beat the host's on a node shared with others
+    Reject,
+}
+
+pub struct CpuStarvedConfig {
+    pub usage_threshold: f64,
+    pub throttled_threshold: f64,
+}
+
+pub struct PodModeConfig {
+    // the pod's cgroup, i.e. the parent of the per-container cgroups; `None` to derive it
+    // from the cgroup acolyte itself is in
+    pub pod_cgroup: Option<PathBuf>,
 }
 
 pub enum OutputMode {
     JsonlToStdout(JsonlToStdoutConfig),
     StatsDir(StatsDirConfig),
+    HttpPush(HttpPushConfig),
+    Prometheus(PrometheusConfig),
+    Statsd(StatsdConfig),
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqliteConfig),
+    #[cfg(feature = "remote-write")]
+    RemoteWrite(RemoteWriteConfig),
+    #[cfg(feature = "binary-stream")]
+    BinaryStream(BinaryStreamConfig),
 }
 pub struct Config {
     pub sentry_dsn: Option<String>,
     pub acolyte_id: Uuid,
     pub cpu_sample_interval: Duration,
+    pub background_cpu_sampler: bool,
     pub stat_interval: Duration,
+    pub sample_jitter: Duration,
+    pub startup_delay: Duration,
+    pub aggregate_window: Option<Duration>,
     pub cluster_name: String,
     pub output_mode: OutputMode,
+    pub output_fields: Option<Vec<String>>,
+    pub crash_dump_dir: Option<PathBuf>,
+    pub flush_interval: Duration,
+    pub cpu_starved: Option<CpuStarvedConfig>,
+    pub pod_mode: Option<PodModeConfig>,
+    pub time_precision: Option<u32>,
+    pub node_name: Option<String>,
+    pub container_name: Option<String>,
+    pub container_image: Option<String>,
+    pub collect_cpu_freq: bool,
+    pub collect_memory_fragmentation: bool,
+    pub collect_swap_activity: bool,
+    pub collect_per_core_usage: bool,
+    pub exclude_cpu_steal: bool,
+    pub target_pid: Option<u32>,
+    pub report_oom_score: bool,
+    pub weight_gpu_usage: bool,
+    pub report_gpu_memory_mib: bool,
+    pub gpu_count_change: GpuCountChange,
+    pub report_available_controllers: bool,
+    pub report_cpu_seconds: bool,
+    pub report_unlimited: bool,
+    pub report_monotonic_time: bool,
+    pub report_rfc3339_timestamp: bool,
+    pub inline_errors: bool,
+    pub write_node_info: bool,
+    pub write_run_summary: bool,
+    pub expected_cgroup: Option<CgroupVersion>,
+    pub proc_scope: ProcScope,
+    pub disabled_controllers: Vec<String>,
 }
 
 impl Config {
     pub fn from_env() -> anyhow::Result<Self> {
+        let output_mode = get_output_mode()?;
         Ok(Config {
             sentry_dsn: get_sentry_dsn(),
             acolyte_id: get_or_create_acolyte_id(),
             cpu_sample_interval: get_cpu_sample_interval(),
+            background_cpu_sampler: get_background_cpu_sampler(),
             stat_interval: get_stat_interval(),
-            output_mode: get_output_mode()?,
+            sample_jitter: get_sample_jitter(),
+            startup_delay: get_startup_delay(),
+            aggregate_window: get_aggregate_window(),
+            crash_dump_dir: get_crash_dump_dir(&output_mode),
+            flush_interval: get_flush_interval(),
+            cpu_starved: get_cpu_starved_config(),
+            pod_mode: get_pod_mode_config(),
+            time_precision: get_time_precision(),
+            output_mode,
+            output_fields: get_output_fields(),
             cluster_name: get_cluster_name(),
+            node_name: get_node_name(),
+            container_name: get_env_var_named_by(
+                process_env_var,
+                "ACOLYTE_CONTAINER_NAME_ENV",
+                "CONTAINER_NAME",
+            ),
+            container_image: get_env_var_named_by(
+                process_env_var,
+                "ACOLYTE_IMAGE_ENV",
+                "CONTAINER_IMAGE",
+            ),
+            collect_cpu_freq: get_collect_cpu_freq(),
+            collect_memory_fragmentation: get_collect_memory_fragmentation(),
+            collect_swap_activity: get_collect_swap_activity(),
+            collect_per_core_usage: get_collect_per_core_usage(),
+            exclude_cpu_steal: get_exclude_cpu_steal(),
+            target_pid: get_target_pid()?,
+            report_oom_score: get_report_oom_score(),
+            weight_gpu_usage: get_weight_gpu_usage(),
+            report_gpu_memory_mib: get_report_gpu_memory_mib(),
+            gpu_count_change: get_gpu_count_change()?,
+            report_available_controllers: get_report_available_controllers(),
+            report_cpu_seconds: get_report_cpu_seconds(),
+            report_unlimited: get_report_unlimited(),
+            report_monotonic_time: get_report_monotonic_time(),
+            report_rfc3339_timestamp: get_report_rfc3339_timestamp(),
+            inline_errors: get_inline_errors(),
+            write_node_info: get_write_node_info(),
+            write_run_summary: get_write_run_summary(),
+            expected_cgroup: get_expected_cgroup()?,
+            proc_scope: get_proc_scope()?,
+            disabled_controllers: get_disabled_controllers()?,
         })
     }
+
+    /// Log settings that work, but probably don't do what the operator wants.
+    pub fn warn_about_questionable_settings(&self) {
+        if is_cpu_sample_interval_too_long(self.cpu_sample_interval, self.stat_interval) {
+            warn!(
+                "ACOLYTE_CPU_SAMPLE_RATE_MS ({:?}) is over half of ACOLYTE_STAT_INTERVAL_MS ({:?}); \
+                 sampling CPU usage takes up most of each interval, consider a shorter sample window \
+                 or a longer stat interval",
+                self.cpu_sample_interval, self.stat_interval
+            );
+        }
+    }
+}
+
+/// The CPU usage is measured over a window at the start of each stat interval, so a window that's
+/// a large part of the interval makes the reported usage lag behind by a good chunk of it
+fn is_cpu_sample_interval_too_long(cpu_sample_interval: Duration, stat_interval: Duration) -> bool {
+    cpu_sample_interval > stat_interval / 2
 }
 
 fn get_output_mode() -> anyhow::Result<OutputMode> {
@@ -44,16 +230,186 @@ fn get_output_mode() -> anyhow::Result<OutputMode> {
     match output_mode.as_deref() {
         Some("stdout") => {
             let prefix = env::var("ACOLYTE_OUTPUT_PREFIX").unwrap_or_else(|_| "".to_string());
-            Ok(OutputMode::JsonlToStdout(JsonlToStdoutConfig { prefix }))
+            Ok(OutputMode::JsonlToStdout(JsonlToStdoutConfig {
+                prefix,
+                envelope: get_envelope()?,
+            }))
         }
         Some("dir") | None => Ok(OutputMode::StatsDir(StatsDirConfig {
             dir: get_stats_dir(),
             max_stats_entries: get_max_stats_entries(),
+            max_age: get_stats_max_age()?,
+            file_mode: get_file_mode()?,
+            envelope: get_envelope()?,
+            watch_fs_space: get_watch_stats_fs_space(),
+            compress: get_compress_stats(),
+            jsonl_max_bytes: get_stats_jsonl_max_bytes()?,
+        })),
+        Some("webhook") => {
+            let url = env::var("ACOLYTE_WEBHOOK_URL").map_err(|_| {
+                anyhow::anyhow!("ACOLYTE_WEBHOOK_URL is required for webhook output")
+            })?;
+            let headers = parse_headers(&env::var("ACOLYTE_WEBHOOK_HEADERS").unwrap_or_default())?;
+            let timeout_ms = env::var("ACOLYTE_WEBHOOK_TIMEOUT_MS")
+                .ok()
+                .and_then(|val| val.parse::<u64>().ok())
+                .unwrap_or(5000);
+            let batch_size = env::var("ACOLYTE_WEBHOOK_BATCH")
+                .ok()
+                .and_then(|val| val.parse::<usize>().ok())
+                .unwrap_or(1);
+            let flush_interval = env::var("ACOLYTE_WEBHOOK_FLUSH_SECS")
+                .ok()
+                .and_then(|val| val.parse::<u64>().ok())
+                .map(Duration::from_secs);
+            Ok(OutputMode::HttpPush(HttpPushConfig {
+                url,
+                headers,
+                timeout: Duration::from_millis(timeout_ms),
+                batch_size,
+                flush_interval,
+                envelope: get_envelope()?,
+            }))
+        }
+        Some("prometheus") => Ok(OutputMode::Prometheus(PrometheusConfig {
+            host: get_prometheus_host()?,
+            port: get_prometheus_port()?,
+        })),
+        Some("statsd") => Ok(OutputMode::Statsd(StatsdConfig {
+            addr: env::var("ACOLYTE_STATSD_ADDR").unwrap_or_else(|_| "127.0.0.1:8125".to_string()),
+            prefix: env::var("ACOLYTE_STATSD_PREFIX").unwrap_or_else(|_| "acolyte.".to_string()),
+        })),
+        #[cfg(feature = "sqlite")]
+        Some("sqlite") => Ok(OutputMode::Sqlite(SqliteConfig {
+            path: get_sqlite_path(),
+            max_stats_entries: get_max_stats_entries(),
         })),
+        #[cfg(not(feature = "sqlite"))]
+        Some("sqlite") => Err(anyhow::anyhow!(
+            "ACOLYTE_OUTPUT_MODE=sqlite needs acolyte built with the `sqlite` feature."
+        )),
+        #[cfg(feature = "remote-write")]
+        Some("remote_write") => {
+            let url = env::var("ACOLYTE_REMOTE_WRITE_URL").map_err(|_| {
+                anyhow::anyhow!("ACOLYTE_REMOTE_WRITE_URL is required for remote_write output")
+            })?;
+            let headers =
+                parse_headers(&env::var("ACOLYTE_REMOTE_WRITE_HEADERS").unwrap_or_default())?;
+            let timeout_ms = env::var("ACOLYTE_REMOTE_WRITE_TIMEOUT_MS")
+                .ok()
+                .and_then(|val| val.parse::<u64>().ok())
+                .unwrap_or(5000);
+            let batch_size = env::var("ACOLYTE_REMOTE_WRITE_BATCH")
+                .ok()
+                .and_then(|val| val.parse::<usize>().ok())
+                .unwrap_or(1);
+            let flush_interval = env::var("ACOLYTE_REMOTE_WRITE_FLUSH_SECS")
+                .ok()
+                .and_then(|val| val.parse::<u64>().ok())
+                .map(Duration::from_secs);
+            Ok(OutputMode::RemoteWrite(RemoteWriteConfig {
+                url,
+                headers,
+                timeout: Duration::from_millis(timeout_ms),
+                batch_size,
+                flush_interval,
+            }))
+        }
+        #[cfg(not(feature = "remote-write"))]
+        Some("remote_write") => Err(anyhow::anyhow!(
+            "ACOLYTE_OUTPUT_MODE=remote_write needs acolyte built with the `remote-write` feature."
+        )),
+        #[cfg(feature = "binary-stream")]
+        Some("binary") => {
+            let path = env::var("ACOLYTE_BINARY_PATH").map_err(|_| {
+                anyhow::anyhow!("ACOLYTE_BINARY_PATH is required for binary output")
+            })?;
+            Ok(OutputMode::BinaryStream(BinaryStreamConfig {
+                path: PathBuf::from(path),
+            }))
+        }
+        #[cfg(not(feature = "binary-stream"))]
+        Some("binary") => Err(anyhow::anyhow!(
+            "ACOLYTE_OUTPUT_MODE=binary needs acolyte built with the `binary-stream` feature."
+        )),
         Some(other) => Err(anyhow::anyhow!("Invalid ACOLYTE_OUTPUT_MODE: {other}.")),
     }
 }
 
+fn get_prometheus_host() -> anyhow::Result<Option<IpAddr>> {
+    match env::var("ACOLYTE_PROMETHEUS_HOST").ok().as_deref() {
+        None | Some("") => Ok(None),
+        Some(host) => parse_ip_addr(host)
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("Invalid ACOLYTE_PROMETHEUS_HOST: {host}.")),
+    }
+}
+
+/// Parse an IP address to listen on, an IPv6 one with or without the brackets of a URL
+fn parse_ip_addr(addr: &str) -> Option<IpAddr> {
+    let unbracketed = addr
+        .strip_prefix('[')
+        .and_then(|addr| addr.strip_suffix(']'));
+    unbracketed.unwrap_or(addr).parse().ok()
+}
+
+fn get_prometheus_port() -> anyhow::Result<u16> {
+    match env::var("ACOLYTE_PROMETHEUS_PORT").ok().as_deref() {
+        None | Some("") => Ok(9464),
+        Some(port) => port
+            .parse::<u16>()
+            .map_err(|_| anyhow::anyhow!("Invalid ACOLYTE_PROMETHEUS_PORT: {port}.")),
+    }
+}
+
+/// Parse headers in the format `Name: value; Other-Name: other value`
+fn parse_headers(headers: &str) -> anyhow::Result<Vec<(String, String)>> {
+    headers
+        .split(';')
+        .filter(|header| !header.trim().is_empty())
+        .map(|header| {
+            let (name, value) = header.split_once(':').ok_or_else(|| {
+                anyhow::anyhow!("Invalid header, expected `Name: value`: {header}")
+            })?;
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+fn get_envelope() -> anyhow::Result<Option<Envelope>> {
+    env::var("ACOLYTE_ENVELOPE")
+        .ok()
+        .filter(|envelope| !envelope.trim().is_empty())
+        .map(|envelope| parse_envelope(&envelope))
+        .transpose()
+}
+
+/// Parse an envelope like `data; type=acolyte_stats; source=ml-cluster`:
+/// the key to nest entries under, then any static fields
+fn parse_envelope(envelope: &str) -> anyhow::Result<Envelope> {
+    let mut parts = envelope.split(';').map(str::trim);
+    let key = parts.next().unwrap_or_default().to_string();
+    if key.is_empty() || key.contains('=') {
+        anyhow::bail!(
+            "Invalid ACOLYTE_ENVELOPE, expected the key to nest entries under first: {envelope}"
+        );
+    }
+    let mut metadata: Vec<(String, String)> = vec![];
+    for field in parts.filter(|field| !field.is_empty()) {
+        let (name, value) = field.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!("Invalid ACOLYTE_ENVELOPE field, expected `name=value`: {field}")
+        })?;
+        let name = name.trim();
+        if name.is_empty() || name == key || metadata.iter().any(|(existing, _)| existing == name) {
+            anyhow::bail!(
+                "Invalid ACOLYTE_ENVELOPE, field names must be unique and not the key: {name}"
+            );
+        }
+        metadata.push((name.to_string(), value.trim().to_string()));
+    }
+    Ok(Envelope { key, metadata })
+}
+
 fn get_sentry_dsn() -> Option<String> {
     env::var("SENTRY_DSN").ok()
 }
@@ -73,6 +429,35 @@ fn get_stat_interval() -> Duration {
     Duration::from_millis(secs)
 }
 
+fn get_sample_jitter() -> Duration {
+    let ms = env::var("ACOLYTE_SAMPLE_JITTER_MS")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .unwrap_or(0);
+    Duration::from_millis(ms)
+}
+
+fn get_startup_delay() -> Duration {
+    let ms = env::var("ACOLYTE_STARTUP_DELAY_MS")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .unwrap_or(0);
+    Duration::from_millis(ms)
+}
+
+fn get_background_cpu_sampler() -> bool {
+    let enabled = env::var("ACOLYTE_CPU_SAMPLER_THREAD").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_aggregate_window() -> Option<Duration> {
+    env::var("ACOLYTE_AGGREGATE_WINDOW_SECS")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs)
+}
+
 fn get_cpu_sample_interval() -> Duration {
     let ms = env::var("ACOLYTE_CPU_SAMPLE_RATE_MS")
         .ok()
@@ -82,12 +467,222 @@ fn get_cpu_sample_interval() -> Duration {
     Duration::from_millis(ms)
 }
 
+fn get_flush_interval() -> Duration {
+    let secs = env::var("ACOLYTE_FLUSH_INTERVAL_SECS")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .unwrap_or(30);
+    Duration::from_secs(secs)
+}
+
+fn get_collect_cpu_freq() -> bool {
+    let enabled = env::var("ACOLYTE_CPU_FREQ").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_collect_memory_fragmentation() -> bool {
+    let enabled = env::var("ACOLYTE_MEMORY_FRAGMENTATION").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_target_pid() -> anyhow::Result<Option<u32>> {
+    env::var("ACOLYTE_TARGET_PID")
+        .ok()
+        .map(|pid| {
+            pid.trim()
+                .parse::<u32>()
+                .map_err(|_| anyhow::anyhow!("Invalid ACOLYTE_TARGET_PID, expected a PID: {pid}"))
+        })
+        .transpose()
+}
+
+fn get_report_oom_score() -> bool {
+    let enabled = env::var("ACOLYTE_OOM_SCORE").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_collect_swap_activity() -> bool {
+    let enabled = env::var("ACOLYTE_SWAP_ACTIVITY").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_collect_per_core_usage() -> bool {
+    let enabled = env::var("ACOLYTE_PER_CORE").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_exclude_cpu_steal() -> bool {
+    let enabled = env::var("ACOLYTE_EXCLUDE_CPU_STEAL").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_weight_gpu_usage() -> bool {
+    let enabled = env::var("ACOLYTE_GPU_USAGE_WEIGHTED").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_report_gpu_memory_mib() -> bool {
+    let enabled = env::var("ACOLYTE_GPU_MEMORY_MIB").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_gpu_count_change() -> anyhow::Result<GpuCountChange> {
+    match env::var("ACOLYTE_GPU_COUNT_CHANGE").ok().as_deref() {
+        None | Some("") | Some("adapt") => Ok(GpuCountChange::Adapt),
+        Some("warn") => Ok(GpuCountChange::Warn),
+        Some("error") => Ok(GpuCountChange::Error),
+        Some(other) => Err(anyhow::anyhow!(
+            "Invalid ACOLYTE_GPU_COUNT_CHANGE: {other}, expected adapt, warn or error."
+        )),
+    }
+}
+
+fn get_write_node_info() -> bool {
+    let enabled = env::var("ACOLYTE_NODE_INFO").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_write_run_summary() -> bool {
+    let enabled = env::var("ACOLYTE_RUN_SUMMARY").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_inline_errors() -> bool {
+    let enabled = env::var("ACOLYTE_INLINE_ERRORS").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_report_cpu_seconds() -> bool {
+    let enabled = env::var("ACOLYTE_CPU_SECONDS").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_report_monotonic_time() -> bool {
+    let enabled = env::var("ACOLYTE_MONOTONIC_TIME").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_report_rfc3339_timestamp() -> bool {
+    let enabled = env::var("ACOLYTE_RFC3339_TIMESTAMP").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_report_unlimited() -> bool {
+    let enabled = env::var("ACOLYTE_REPORT_UNLIMITED").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_report_available_controllers() -> bool {
+    let enabled = env::var("ACOLYTE_AVAILABLE_CONTROLLERS").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_expected_cgroup() -> anyhow::Result<Option<CgroupVersion>> {
+    match env::var("ACOLYTE_EXPECT_CGROUP").ok().as_deref() {
+        None | Some("") => Ok(None),
+        Some(version) => parse_cgroup_version(version).map(Some),
+    }
+}
+
+fn parse_cgroup_version(version: &str) -> anyhow::Result<CgroupVersion> {
+    match version {
+        "v1" => Ok(CgroupVersion::V1),
+        "v2" => Ok(CgroupVersion::V2),
+        "hybrid" => Ok(CgroupVersion::V1AndV2),
+        _ => Err(anyhow::anyhow!(
+            "Invalid ACOLYTE_EXPECT_CGROUP: {version}, expected v1, v2 or hybrid."
+        )),
+    }
+}
+
+fn get_proc_scope() -> anyhow::Result<ProcScope> {
+    match env::var("ACOLYTE_PROC_SCOPE").ok().as_deref() {
+        None | Some("") | Some("host") => Ok(ProcScope::Host),
+        Some("reject") => Ok(ProcScope::Reject),
+        Some(other) => Err(anyhow::anyhow!(
+            "Invalid ACOLYTE_PROC_SCOPE: {other}, expected host or reject."
+        )),
+    }
+}
+
+fn get_disabled_controllers() -> anyhow::Result<Vec<String>> {
+    let value = env::var("ACOLYTE_DISABLE_CONTROLLERS").unwrap_or_default();
+    let mut controllers = vec![];
+    for controller in value.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        // a typo would otherwise leave the noisy controller read with nothing to show for it
+        if !CONTROLLERS.contains(&controller) {
+            return Err(anyhow::anyhow!(
+                "Invalid controller in ACOLYTE_DISABLE_CONTROLLERS: {controller}, expected one of {}.",
+                CONTROLLERS.join(", ")
+            ));
+        }
+        controllers.push(controller.to_string());
+    }
+    Ok(controllers)
+}
+
+fn get_time_precision() -> Option<u32> {
+    env::var("ACOLYTE_TIME_PRECISION")
+        .ok()
+        .and_then(|val| val.parse::<u32>().ok())
+        // an f64 timestamp doesn't have more than microsecond precision left anyway
+        .map(|decimals| decimals.min(6))
+}
+
+fn get_output_fields() -> Option<Vec<String>> {
+    let fields: Vec<String> = env::var("ACOLYTE_OUTPUT_FIELDS")
+        .ok()?
+        .split(',')
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect();
+    (!fields.is_empty()).then_some(fields)
+}
+
+fn get_cpu_starved_config() -> Option<CpuStarvedConfig> {
+    let enabled = env::var("ACOLYTE_DETECT_CPU_STARVED").ok();
+    if !matches!(enabled.as_deref(), Some("1" | "true" | "yes")) {
+        return None;
+    }
+    let usage_threshold = env::var("ACOLYTE_CPU_STARVED_USAGE_RATIO")
+        .ok()
+        .and_then(|val| val.parse::<f64>().ok())
+        .unwrap_or(0.9);
+    let throttled_threshold = env::var("ACOLYTE_CPU_STARVED_THROTTLED_RATIO")
+        .ok()
+        .and_then(|val| val.parse::<f64>().ok())
+        .unwrap_or(0.1);
+    Some(CpuStarvedConfig {
+        usage_threshold,
+        throttled_threshold,
+    })
+}
+
+fn get_pod_mode_config() -> Option<PodModeConfig> {
+    let enabled = env::var("ACOLYTE_POD_MODE").ok();
+    if !matches!(enabled.as_deref(), Some("1" | "true" | "yes")) {
+        return None;
+    }
+    let pod_cgroup = env::var("ACOLYTE_POD_CGROUP")
+        .ok()
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from);
+    Some(PodModeConfig { pod_cgroup })
+}
+
 fn get_stats_dir() -> PathBuf {
     env::var("ACOLYTE_STATS_DIR")
         .unwrap_or_else(|_| "/tmp/acolyte/stats".to_string())
         .into()
 }
 
+#[cfg(feature = "sqlite")]
+fn get_sqlite_path() -> PathBuf {
+    env::var("ACOLYTE_SQLITE_PATH")
+        .unwrap_or_else(|_| "/tmp/acolyte/stats.db".to_string())
+        .into()
+}
+
 fn get_max_stats_entries() -> usize {
     env::var("ACOLYTE_MAX_STATS_ENTRIES")
         .unwrap_or_else(|_| "12".to_string())
@@ -95,6 +690,239 @@ fn get_max_stats_entries() -> usize {
         .unwrap_or(12)
 }
 
+fn get_watch_stats_fs_space() -> bool {
+    let enabled = env::var("ACOLYTE_STATS_FS_SPACE").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_stats_max_age() -> anyhow::Result<Option<Duration>> {
+    match env::var("ACOLYTE_STATS_MAX_AGE_SECS").ok().as_deref() {
+        None | Some("") => Ok(None),
+        Some(secs) => match secs.parse::<u64>() {
+            Ok(secs) if secs > 0 => Ok(Some(Duration::from_secs(secs))),
+            _ => Err(anyhow::anyhow!(
+                "Invalid ACOLYTE_STATS_MAX_AGE_SECS: {secs}."
+            )),
+        },
+    }
+}
+
+fn get_compress_stats() -> bool {
+    let enabled = env::var("ACOLYTE_COMPRESS").ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+}
+
+fn get_stats_jsonl_max_bytes() -> anyhow::Result<Option<u64>> {
+    let enabled = env::var("ACOLYTE_STATS_JSONL").ok();
+    if !matches!(enabled.as_deref(), Some("1" | "true" | "yes")) {
+        return Ok(None);
+    }
+    match env::var("ACOLYTE_STATS_JSONL_MAX_MB").ok().as_deref() {
+        None | Some("") => Ok(Some(10 * 1024 * 1024)),
+        Some(max_mb) => match max_mb.parse::<u64>() {
+            Ok(max_mb) if max_mb > 0 => Ok(Some(max_mb * 1024 * 1024)),
+            _ => Err(anyhow::anyhow!(
+                "Invalid ACOLYTE_STATS_JSONL_MAX_MB: {max_mb}."
+            )),
+        },
+    }
+}
+
+fn get_file_mode() -> anyhow::Result<Option<u32>> {
+    // a typo'd mode shouldn't silently leave the files with permissions the operator didn't want
+    match env::var("ACOLYTE_FILE_MODE").ok().as_deref() {
+        None | Some("") => Ok(None),
+        Some(mode) => parse_file_mode(mode).map(Some),
+    }
+}
+
+/// Parse an octal unix file mode like `640`, `0640` or `0o640`
+fn parse_file_mode(mode: &str) -> anyhow::Result<u32> {
+    let digits = mode.strip_prefix("0o").unwrap_or(mode);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(anyhow::anyhow!("Invalid ACOLYTE_FILE_MODE: {mode}.")),
+    }
+}
+
+fn get_crash_dump_dir(output_mode: &OutputMode) -> Option<PathBuf> {
+    // crash dumps go next to the stats by default, but can be pointed elsewhere or disabled with "off"
+    match env::var("ACOLYTE_CRASH_DUMP_DIR").ok().as_deref() {
+        Some("off") => None,
+        Some(dir) if !dir.is_empty() => Some(dir.into()),
+        _ => match output_mode {
+            OutputMode::StatsDir(stats_dir_config) => Some(stats_dir_config.dir.clone()),
+            OutputMode::JsonlToStdout(_)
+            | OutputMode::HttpPush(_)
+            | OutputMode::Prometheus(_)
+            | OutputMode::Statsd(_) => None,
+            #[cfg(feature = "sqlite")]
+            OutputMode::Sqlite(_) => None,
+            #[cfg(feature = "remote-write")]
+            OutputMode::RemoteWrite(_) => None,
+            #[cfg(feature = "binary-stream")]
+            OutputMode::BinaryStream(_) => None,
+        },
+    }
+}
+
 fn get_cluster_name() -> String {
     env::var("CLUSTER_NAME").unwrap_or_else(|_| "Unknown".to_string())
 }
+
+fn get_node_name() -> Option<String> {
+    lookup_node_name(process_env_var, Path::new("/etc/nodename"))
+}
+
+/// The node name from the environment as looked up with `get_env`, or the file at `nodename_path`
+fn lookup_node_name(
+    get_env: impl Fn(&str) -> Option<String>,
+    nodename_path: &Path,
+) -> Option<String> {
+    // `NODE_NAME` is what the downward API `spec.nodeName` is conventionally exposed as,
+    // but some charts use another name for it
+    get_env_var_named_by(get_env, "ACOLYTE_NODE_NAME_ENV", "NODE_NAME").or_else(|| {
+        fs::read_to_string(nodename_path)
+            .ok()
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+    })
+}
+
+fn process_env_var(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+/// Read the environment variable whose name is in `name_env_var`, or `default_env_var` if that's not set,
+/// looking variables up with `get_env`.
+///
+/// There's no one way to get things like the container image into a container, so which variable
+/// the chart or runtime puts it in is configurable.
+fn get_env_var_named_by(
+    get_env: impl Fn(&str) -> Option<String>,
+    name_env_var: &str,
+    default_env_var: &str,
+) -> Option<String> {
+    let env_var = get_env(name_env_var).unwrap_or_else(|| default_env_var.to_string());
+    get_env(&env_var)
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_is_cpu_sample_interval_too_long() {
+        let ms = Duration::from_millis;
+        assert!(!is_cpu_sample_interval_too_long(ms(100), ms(5000)));
+        assert!(!is_cpu_sample_interval_too_long(ms(100), ms(200)));
+        assert!(is_cpu_sample_interval_too_long(ms(101), ms(200)));
+        assert!(is_cpu_sample_interval_too_long(ms(1000), ms(500)));
+    }
+
+    #[test]
+    fn test_parse_cgroup_version() {
+        assert_eq!(parse_cgroup_version("v2").unwrap(), CgroupVersion::V2);
+        assert_eq!(
+            parse_cgroup_version("hybrid").unwrap(),
+            CgroupVersion::V1AndV2
+        );
+        assert!(parse_cgroup_version("2").is_err());
+    }
+
+    #[test]
+    fn test_get_env_var_named_by() {
+        let env = HashMap::from([
+            ("ACOLYTE_IMAGE_ENV", "MY_IMAGE"),
+            ("MY_IMAGE", "python:3.12-slim\n"),
+            ("CONTAINER_IMAGE", "ubuntu:24.04"),
+            ("EMPTY_IMAGE", ""),
+        ]);
+        let get_env = |name: &str| env.get(name).map(|value| value.to_string());
+        assert_eq!(
+            get_env_var_named_by(get_env, "ACOLYTE_IMAGE_ENV", "CONTAINER_IMAGE"),
+            Some("python:3.12-slim".to_string())
+        );
+        assert_eq!(
+            get_env_var_named_by(get_env, "UNSET_ENV", "CONTAINER_IMAGE"),
+            Some("ubuntu:24.04".to_string())
+        );
+        assert_eq!(
+            get_env_var_named_by(get_env, "UNSET_ENV", "EMPTY_IMAGE"),
+            None
+        );
+        assert_eq!(
+            get_env_var_named_by(get_env, "UNSET_ENV", "UNSET_IMAGE"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_lookup_node_name() -> std::io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let nodename_path = temp_dir.path().join("nodename");
+        fs::write(&nodename_path, "node-from-file\n")?;
+        let lookup = |env: &[(&str, &str)]| {
+            let env: HashMap<&str, &str> = env.iter().copied().collect();
+            lookup_node_name(
+                |name| env.get(name).map(|value| value.to_string()),
+                &nodename_path,
+            )
+        };
+
+        assert_eq!(
+            lookup(&[
+                ("ACOLYTE_NODE_NAME_ENV", "K8S_NODE"),
+                ("K8S_NODE", "node-2"),
+                ("NODE_NAME", "node-1"),
+            ]),
+            Some("node-2".to_string())
+        );
+        assert_eq!(
+            lookup(&[("NODE_NAME", "node-1")]),
+            Some("node-1".to_string())
+        );
+        // falls back to the file, trimmed
+        assert_eq!(lookup(&[]), Some("node-from-file".to_string()));
+        assert_eq!(
+            lookup_node_name(|_| None, &temp_dir.path().join("missing")),
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_envelope() {
+        assert_eq!(
+            parse_envelope("data; type=acolyte_stats; source = ml-cluster").unwrap(),
+            Envelope {
+                key: "data".to_string(),
+                metadata: vec![
+                    ("type".to_string(), "acolyte_stats".to_string()),
+                    ("source".to_string(), "ml-cluster".to_string()),
+                ],
+            }
+        );
+        assert_eq!(parse_envelope("data").unwrap().metadata, vec![]);
+
+        assert!(parse_envelope("type=acolyte_stats").is_err()); // no key
+        assert!(parse_envelope("data; acolyte_stats").is_err());
+        assert!(parse_envelope("data; data=x").is_err());
+        assert!(parse_envelope("data; type=a; type=b").is_err());
+    }
+
+    #[test]
+    fn test_parse_ip_addr() {
+        assert_eq!(
+            parse_ip_addr("127.0.0.1"),
+            Some(IpAddr::from([127, 0, 0, 1]))
+        );
+        assert_eq!(parse_ip_addr("::1"), Some("::1".parse().unwrap()));
+        assert_eq!(parse_ip_addr("[::1]"), Some("::1".parse().unwrap()));
+        assert_eq!(parse_ip_addr("localhost"), None);
+        assert_eq!(parse_ip_addr("[127.0.0.1"), None);
+    }
+}
@@ -1,3 +1,10 @@
 pub const ID_ENV_VAR: &str = "ACOLYTE_ID";
 pub const MAX_RUN_ATTEMPTS: u8 = 5;
 pub const RESTART_DELAY_SECS: u64 = 10;
+// the delay doubles with every restart, up to this
+pub const MAX_RESTART_DELAY_SECS: u64 = 120;
+
+// distinct from the generic failure of 1, so deployment tooling can tell a misconfigured node apart
+pub const EXIT_CODE_UNEXPECTED_CGROUP: i32 = 3;
+// an output that can't be set up (e.g. a malformed webhook URL) is a configuration problem restarts won't fix
+pub const EXIT_CODE_OUTPUT_SETUP: i32 = 4;
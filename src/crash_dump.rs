@@ -0,0 +1,234 @@
+use crate::config::{Config, OutputMode};
+use crate::store::StatsEntry;
+use serde::Serialize;
+use std::backtrace::Backtrace;
+use std::fs;
+use std::io;
+use std::panic::{self, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+
+/// Crash dumps kept in the dump directory, so a crash-looping pod doesn't fill it up
+const MAX_CRASH_DUMPS: usize = 10;
+
+// state the panic hook reports; kept up to date by the collection loop
+static LAST_STATS_ENTRY: Mutex<Option<StatsEntry>> = Mutex::new(None);
+static SOURCE_NAMES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+#[derive(Serialize)]
+struct CrashDump<'a> {
+    time: f64,
+    message: String,
+    location: Option<String>,
+    config: Vec<(&'static str, String)>,
+    sources: Vec<&'static str>,
+    last_stats_entry: Option<&'a StatsEntry>,
+    backtrace: String,
+}
+
+/// Install a panic hook that writes a crash dump file before passing the panic on.
+///
+/// The previously installed hook (e.g. the one Sentry sets up) is called after the dump is
+/// written, so install this _after_ initializing Sentry to have both fire.
+pub fn install_panic_hook(config: &Config) {
+    let Some(dump_dir) = config.crash_dump_dir.clone() else {
+        info!("Crash dumps disabled");
+        return;
+    };
+    let config_summary = summarize_config(config);
+
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        // the hook runs in the panicking thread, so regular IO is fine here
+        let message = panic_message(panic_info);
+        let location = panic_info.location().map(|l| l.to_string());
+        let backtrace = Backtrace::force_capture().to_string();
+        match write_crash_dump(&dump_dir, message, location, &config_summary, backtrace) {
+            Ok(path) => error!("Wrote crash dump to {:?}", path),
+            Err(e) => error!("Failed to write crash dump: {}", e),
+        }
+        previous_hook(panic_info);
+    }));
+}
+
+pub fn record_stats_entry(entry: &StatsEntry) {
+    if let Ok(mut last) = LAST_STATS_ENTRY.lock() {
+        *last = Some(entry.clone());
+    }
+}
+
+pub fn record_source_names(names: Vec<&'static str>) {
+    if let Ok(mut sources) = SOURCE_NAMES.lock() {
+        *sources = names;
+    }
+}
+
+fn write_crash_dump(
+    dump_dir: &Path,
+    message: String,
+    location: Option<String>,
+    config_summary: &[(&'static str, String)],
+    backtrace: String,
+) -> io::Result<PathBuf> {
+    // the panic might've happened while the loop held these locks, so never block on them here
+    let last_entry = LAST_STATS_ENTRY.try_lock().ok();
+    let sources = SOURCE_NAMES
+        .try_lock()
+        .map(|sources| sources.clone())
+        .unwrap_or_default();
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let dump = CrashDump {
+        time: now.as_secs_f64(),
+        message,
+        location,
+        config: config_summary.to_vec(),
+        sources,
+        last_stats_entry: last_entry.as_ref().and_then(|entry| entry.as_ref()),
+        backtrace,
+    };
+
+    fs::create_dir_all(dump_dir)?;
+    let dump_path = dump_dir.join(format!("crash-{}.json", now.as_millis()));
+    fs::write(&dump_path, serde_json::to_string_pretty(&dump)?)?;
+    if let Err(e) = remove_old_crash_dumps(dump_dir, MAX_CRASH_DUMPS) {
+        error!("Failed to remove old crash dumps: {}", e);
+    }
+    Ok(dump_path)
+}
+
+/// Remove all but the newest `max_dumps` crash dumps, going by the timestamp in their name
+fn remove_old_crash_dumps(dump_dir: &Path, max_dumps: usize) -> io::Result<()> {
+    let mut dumps: Vec<(u128, PathBuf)> = fs::read_dir(dump_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let timestamp_ms = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("crash-")?
+                .strip_suffix(".json")?
+                .parse()
+                .ok()?;
+            Some((timestamp_ms, entry.path()))
+        })
+        .collect();
+    dumps.sort();
+
+    let over_count = dumps.len().saturating_sub(max_dumps);
+    for (_, path) in dumps.into_iter().take(over_count) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn panic_message(panic_info: &PanicHookInfo) -> String {
+    let payload = panic_info.payload();
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Unknown panic payload".to_string()
+    }
+}
+
+fn summarize_config(config: &Config) -> Vec<(&'static str, String)> {
+    // NB: leave out anything secret like the Sentry DSN
+    let output_mode = match &config.output_mode {
+        OutputMode::JsonlToStdout(_) => "stdout".to_string(),
+        OutputMode::StatsDir(dir_config) => format!(
+            "dir ({:?}, max {} entries)",
+            dir_config.dir, dir_config.max_stats_entries
+        ),
+        // the URL might have credentials in it, so only the fact that it's a webhook
+        OutputMode::HttpPush(_) => "webhook".to_string(),
+        OutputMode::Prometheus(prometheus_config) => match prometheus_config.host {
+            Some(host) => format!("prometheus ({host}, port {})", prometheus_config.port),
+            None => format!("prometheus (port {})", prometheus_config.port),
+        },
+        OutputMode::Statsd(statsd_config) => format!("statsd ({})", statsd_config.addr),
+        #[cfg(feature = "sqlite")]
+        OutputMode::Sqlite(sqlite_config) => format!(
+            "sqlite ({:?}, max {} entries)",
+            sqlite_config.path, sqlite_config.max_stats_entries
+        ),
+        #[cfg(feature = "remote-write")]
+        OutputMode::RemoteWrite(_) => "remote_write".to_string(),
+        #[cfg(feature = "binary-stream")]
+        OutputMode::BinaryStream(binary_stream_config) => {
+            format!("binary ({:?})", binary_stream_config.path)
+        }
+    };
+    vec![
+        ("acolyte_id", config.acolyte_id.to_string()),
+        ("cluster_name", config.cluster_name.clone()),
+        ("node_name", config.node_name.clone().unwrap_or_default()),
+        ("stat_interval", format!("{:?}", config.stat_interval)),
+        (
+            "cpu_sample_interval",
+            format!("{:?}", config.cpu_sample_interval),
+        ),
+        ("output_mode", output_mode),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_crash_dump() -> io::Result<()> {
+        let dump_dir = tempfile::tempdir()?;
+        record_source_names(vec!["cgroup_v2", "proc"]);
+        let mut entry = StatsEntry::new();
+        entry.num_cpus = Some(2.0);
+        record_stats_entry(&entry);
+
+        let dump_path = write_crash_dump(
+            dump_dir.path(),
+            "oh no".to_string(),
+            Some("src/lib.rs:1:1".to_string()),
+            &[("output_mode", "stdout".to_string())],
+            "disabled backtrace".to_string(),
+        )?;
+
+        let dump: serde_json::Value = serde_json::from_str(&fs::read_to_string(dump_path)?)?;
+        assert_eq!(dump["message"], "oh no");
+        assert_eq!(dump["location"], "src/lib.rs:1:1");
+        assert_eq!(dump["sources"], serde_json::json!(["cgroup_v2", "proc"]));
+        assert_eq!(dump["last_stats_entry"]["num_cpus"], 2.0);
+        assert_eq!(
+            dump["config"][0],
+            serde_json::json!(["output_mode", "stdout"])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_old_crash_dumps() -> io::Result<()> {
+        let dump_dir = tempfile::tempdir()?;
+        for timestamp_ms in [900, 1000, 2000, 3000] {
+            fs::write(
+                dump_dir.path().join(format!("crash-{timestamp_ms}.json")),
+                "{}",
+            )?;
+        }
+        fs::write(dump_dir.path().join("stats-500.json"), "{}")?;
+
+        remove_old_crash_dumps(dump_dir.path(), 2)?;
+
+        let mut names: Vec<String> = fs::read_dir(dump_dir.path())?
+            .map(|file| file.map(|file| file.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<_>>()?;
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["crash-2000.json", "crash-3000.json", "stats-500.json"]
+        );
+        Ok(())
+    }
+}
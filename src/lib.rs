@@ -1,69 +1,671 @@
 pub mod config;
 pub mod consts;
+pub mod crash_dump;
+pub mod pod;
+pub mod shutdown;
+pub mod sinks;
 pub mod stats;
 pub mod store;
 pub mod utils;
 
-use crate::config::Config;
+use crate::config::{Config, OutputMode, PodModeConfig, ProcScope};
+use crate::sinks::StatsSink;
 use crate::stats::cgroup_v1::CgroupV1Source;
 use crate::stats::cgroup_v2::CgroupV2Source;
+use crate::stats::env_limits::EnvLimitsSource;
 use crate::stats::proc::ProcSource;
 use crate::stats::{
-    SystemStatsSource, detect_cgroup_version, get_cgroup_v1_mount_points, get_cgroup_v2_mount_point,
+    ControllerFilter, CounterRate, CpuSampler, CpuStarvationDetector, CpuTimeReading,
+    GpuCountWatch, Metric, SourceKind, SummedCounterRates, SystemStatsSource,
+    detect_cgroup_version, find_first, find_first_limit, get_cgroup_v1_mount_points,
+    get_cgroup_v2_mount_point, get_cgroup_v2_self_path, reconcile_num_cpus,
 };
-use crate::store::StatsEntry;
+use crate::store::{PerGpuStats, RunSummary, StatsEntry};
+use std::io;
 use std::path::PathBuf;
-use std::thread;
-use tracing::{debug, error};
+use std::time::{Instant, SystemTime};
+use tracing::{debug, debug_span, error, info};
 
-pub fn run_acolyte(config: &Config) {
-    let sources = get_sources();
+pub fn run_acolyte(config: &Config, mut sink: Box<dyn StatsSink + '_>) {
+    let sources = get_sources(
+        config.proc_scope,
+        &config.disabled_controllers,
+        config.exclude_cpu_steal,
+    );
+    let available_controllers = log_sources(&sources, config);
 
-    loop {
+    let mut run_summary = config.write_run_summary.then(RunSummary::new);
+    // a cgroup's CPU time gives the usage over the whole interval between entries without any sampling,
+    // so the thread is only worth it for the host-wide fallback
+    let cpu_time_from_cgroup = sources
+        .iter()
+        .find_map(|source| source.get_cpu_time().ok().map(|_| source.kind()))
+        .is_some_and(|kind| kind.is_cgroup());
+    if config.background_cpu_sampler && cpu_time_from_cgroup {
+        info!("Not starting the CPU sampler thread, the cgroup's CPU time covers the interval");
+    }
+    let cpu_sampler = (config.background_cpu_sampler && !cpu_time_from_cgroup)
+        .then(|| {
+            let proc_scope = config.proc_scope;
+            let disabled_controllers = config.disabled_controllers.clone();
+            let exclude_cpu_steal = config.exclude_cpu_steal;
+            CpuSampler::spawn(config.cpu_sample_interval, move || {
+                get_sources(proc_scope, &disabled_controllers, exclude_cpu_steal)
+            })
+        })
+        .and_then(|spawned| {
+            // sampling in place for every entry instead, as without the thread
+            spawned
+                .inspect_err(|e| error!("Failed to start the CPU sampler thread: {e}"))
+                .ok()
+        });
+    let mut collector = Collector::new(config, available_controllers, cpu_sampler);
+
+    if !config.startup_delay.is_zero() {
+        info!("Waiting {:?} before the first sample", config.startup_delay);
+        // a shutdown during the wait skips the loop below altogether
+        shutdown::sleep(config.startup_delay);
+    }
+
+    while !shutdown::is_shutdown_requested() {
+        // a span per phase, so with debug logging each sample logs a timeline of where the time went
+        let sample_span = debug_span!("sample").entered();
+        let mut stats_entry = collector.collect_stats_entry(&sources, config);
+        stats_entry.acolyte_bytes_written_total = sink.bytes_written();
+
+        debug!("New stats entry: {:?}", stats_entry);
+        if let Some(run_summary) = run_summary.as_mut() {
+            run_summary.record(&stats_entry, collector.interval_cpu_seconds);
+        }
+        crash_dump::record_stats_entry(&stats_entry);
+        let write_span = debug_span!("write").entered();
+        reopen_if_requested(&mut sink);
+        if let Err(e) = sink.write(&stats_entry) {
+            error!("Failed to write stats entry: {}", e);
+        }
+        write_span.exit();
+        sample_span.exit();
+
+        shutdown::sleep(utils::jitter_interval(
+            config.stat_interval,
+            config.sample_jitter,
+            utils::random_u64(),
+        ));
+    }
+
+    info!("Shutting down");
+    if let Err(e) = sink.flush() {
+        error!("Failed to flush output on shutdown: {}", e);
+    }
+    if let Some(mut run_summary) = run_summary {
+        run_summary.finish(collector.started.elapsed());
+        match serde_json::to_string(&run_summary) {
+            Ok(as_json) => info!("Run summary: {as_json}"),
+            Err(e) => error!("Failed to serialize run summary: {}", e),
+        }
+        if let Err(e) = sink.write_run_summary(&run_summary) {
+            error!("Failed to write run summary: {}", e);
+        }
+    }
+}
+
+/// Reopen the output if SIGUSR1 asked for it since the previous entry, e.g. after it was rotated
+pub(crate) fn reopen_if_requested(sink: &mut impl StatsSink) {
+    if shutdown::take_reopen_request() {
+        info!("Reopening the output as requested");
+        if let Err(e) = sink.reopen() {
+            error!("Failed to reopen output: {}", e);
+        }
+    }
+}
+
+/// Collect and write a single stats entry, without the startup delay or the loop, for a quick look
+/// at what Acolyte sees
+pub fn run_once(config: &Config, mut sink: Box<dyn StatsSink + '_>) -> io::Result<()> {
+    let sources = get_sources(
+        config.proc_scope,
+        &config.disabled_controllers,
+        config.exclude_cpu_steal,
+    );
+    let available_controllers = log_sources(&sources, config);
+
+    // a background sampler wouldn't have a sample yet, so the CPU usage is always sampled in place
+    let mut collector = Collector::new(config, available_controllers, None);
+    let mut stats_entry = collector.collect_stats_entry(&sources, config);
+    stats_entry.acolyte_bytes_written_total = sink.bytes_written();
+    debug!("New stats entry: {:?}", stats_entry);
+    sink.write(&stats_entry)?;
+    sink.flush()
+}
+
+/// Log where each metric comes from and what there is to read it from, returning the available
+/// cgroup controllers
+fn log_sources(sources: &[Box<dyn SystemStatsSource>], config: &Config) -> Option<Vec<String>> {
+    for metric in Metric::ALL {
+        info!(
+            "Sources for {}: {}",
+            metric.name(),
+            stats::describe_fallback_chain(sources, metric)
+        );
+    }
+    // the most common reason for missing stats is a controller that isn't enabled for our cgroup
+    let available_controllers = sources
+        .iter()
+        .find_map(|source| source.get_available_controllers().ok());
+    match &available_controllers {
+        Some(controllers) => info!("Available cgroup controllers: {}", controllers.join(" ")),
+        None => info!("Available cgroup controllers: unknown"),
+    }
+    log_cpu_info(sources, config);
+    crash_dump::record_source_names(sources.iter().map(|source| source.kind().name()).collect());
+    available_controllers
+}
+
+/// What a sample carries over from the ones before it, and what's only looked up once per run
+struct Collector {
+    started: Instant,
+    available_controllers: Option<Vec<String>>,
+    pod: Option<pod::PodCollector>,
+    rootfs_readonly: Option<bool>,
+    cpu_sampler: Option<CpuSampler>,
+    previous_cpu_time: Option<(SourceKind, CpuTimeReading)>,
+    major_faults_rate: CounterRate,
+    context_switches_rate: CounterRate,
+    interrupts_rate: CounterRate,
+    swap_in_rate: CounterRate,
+    swap_out_rate: CounterRate,
+    disk_rates: SummedCounterRates<String>,
+    net_rates: SummedCounterRates<String>,
+    gpu_count_watch: GpuCountWatch,
+    cpu_starvation: Option<CpuStarvationDetector>,
+    /// The CPU seconds used between the last two samples, for the run summary
+    interval_cpu_seconds: Option<f64>,
+}
+
+impl Collector {
+    fn new(
+        config: &Config,
+        available_controllers: Option<Vec<String>>,
+        cpu_sampler: Option<CpuSampler>,
+    ) -> Self {
+        Self {
+            started: Instant::now(),
+            available_controllers,
+            pod: config
+                .pod_mode
+                .as_ref()
+                .and_then(get_pod_cgroup)
+                .map(pod::PodCollector::new),
+            // mount options don't change while we're running, so once is enough
+            rootfs_readonly: stats::is_rootfs_readonly("/proc/mounts").ok(),
+            cpu_sampler,
+            previous_cpu_time: None,
+            major_faults_rate: CounterRate::new(),
+            context_switches_rate: CounterRate::new(),
+            interrupts_rate: CounterRate::new(),
+            swap_in_rate: CounterRate::new(),
+            swap_out_rate: CounterRate::new(),
+            disk_rates: SummedCounterRates::new(),
+            net_rates: SummedCounterRates::new(),
+            gpu_count_watch: GpuCountWatch::new(config.gpu_count_change),
+            cpu_starvation: config.cpu_starved.as_ref().map(|starved_config| {
+                CpuStarvationDetector::new(
+                    starved_config.usage_threshold,
+                    starved_config.throttled_threshold,
+                )
+            }),
+            interval_cpu_seconds: None,
+        }
+    }
+
+    /// Read everything there is to read into a new stats entry; the output's own stats are left to the caller
+    fn collect_stats_entry(
+        &mut self,
+        sources: &[Box<dyn SystemStatsSource>],
+        config: &Config,
+    ) -> StatsEntry {
         let mut stats_entry = StatsEntry::new();
+        let mut errors = vec![];
+        if config.report_monotonic_time {
+            stats_entry.stamp_monotonic(self.started);
+        }
+        if config.report_rfc3339_timestamp {
+            stats_entry.stamp_rfc3339();
+        }
+        stats_entry.node_name = config.node_name.clone();
+        stats_entry.container_name = config.container_name.clone();
+        stats_entry.container_image = config.container_image.clone();
+        stats_entry.rootfs_readonly = self.rootfs_readonly;
+        if config.report_available_controllers {
+            stats_entry.available_controllers = self.available_controllers.clone();
+        }
 
-        if let Some(num_cpus) = sources.iter().find_map(|source| source.get_num_cpus().ok()) {
+        let cpu_span = debug_span!("cpu").entered();
+        let (num_cpus, cpu_unlimited) =
+            find_first_limit(sources, "num_cpus", &mut errors, |source| {
+                source.get_num_cpus()
+            });
+        if let Some((kind, num_cpus)) = num_cpus {
+            stats_entry.num_cpus = Some(num_cpus);
+            if kind.is_cgroup() {
+                stats_entry.cpu_quota_cores = Some(num_cpus);
+            }
+        }
+        if config.report_unlimited {
+            stats_entry.cpu_unlimited = cpu_unlimited;
+        }
+        // the cgroup's cpuset is the one that counts for the monitored container, our own affinity is the fallback
+        stats_entry.cpu_cpuset_count = find_first(sources, "cpuset", &mut errors, |source| {
+            source.get_cgroup_cpuset_cpu_count()
+        })
+        .map(|(_, cpuset_count)| cpuset_count)
+        .or_else(|| stats::get_cpuset_cpu_count().ok());
+        if let Some(num_cpus) =
+            reconcile_num_cpus(stats_entry.cpu_quota_cores, stats_entry.cpu_cpuset_count)
+        {
             stats_entry.num_cpus = Some(num_cpus);
         }
 
-        if let Some(cpu_usage) = sources
-            .iter()
-            .find_map(|source| source.get_cpu_usage(config.cpu_sample_interval).ok())
+        // measure against the previous entry's reading to cover the whole interval without sleeping;
+        // the first entry and sources without a CPU time counter take a short sample instead
+        let cpu_time = find_first(sources, "cpu_time", &mut errors, |source| {
+            source.get_cpu_time()
+        });
+        let mut interval_cpu_usage = None;
+        let mut interval_cpu_seconds = None;
+        if let (Some((previous_kind, previous)), Some((kind, current))) =
+            (&self.previous_cpu_time, &cpu_time)
+            && previous_kind == kind
+        {
+            // proc's CPU time is host-wide, so its usage is left to the sample below,
+            // which scales it by our number of CPUs instead
+            if kind.is_cgroup() {
+                interval_cpu_usage = current.usage_since(previous);
+            }
+            interval_cpu_seconds = Some(current.cpu_seconds_since(previous));
+            if config.report_cpu_seconds {
+                stats_entry.cpu_seconds = interval_cpu_seconds;
+            }
+        }
+        self.previous_cpu_time = cpu_time;
+        if let Some(cpu_usage) = interval_cpu_usage {
+            stats_entry.cpu_usage = Some(cpu_usage);
+        } else if let Some(cpu_usage) = self.cpu_sampler.as_ref().and_then(CpuSampler::latest) {
+            stats_entry.cpu_usage = cpu_usage.normalize(stats_entry.num_cpus);
+        } else if self
+            .cpu_sampler
+            .as_ref()
+            .is_some_and(CpuSampler::is_starting)
+        {
+            // no waiting for a sample here; before the sampler's first one, there's no usage to report
+        } else if let Some((_, cpu_usage)) =
+            find_first(sources, "cpu_usage", &mut errors, |source| {
+                source.get_cpu_usage(config.cpu_sample_interval)
+            })
         {
             stats_entry.cpu_usage = cpu_usage.normalize(stats_entry.num_cpus);
         }
 
-        if let Some(mem_usage_kb) = sources
-            .iter()
-            .find_map(|source| source.get_memory_usage_kb().ok())
+        if config.collect_per_core_usage
+            && let Some((_, per_core_usage)) =
+                find_first(sources, "per_core_cpu_usage", &mut errors, |source| {
+                    source.get_per_core_cpu_usage(config.cpu_sample_interval)
+                })
         {
-            stats_entry.memory_usage_kb = Some(mem_usage_kb);
+            stats_entry.per_core_usage = Some(per_core_usage);
         }
 
-        if let Some(mem_total_kb) = sources
-            .iter()
-            .find_map(|source| source.get_memory_total_kb().ok())
+        // cumulative, reported as they are
+        let throttling = find_first(sources, "cpu_throttling", &mut errors, |source| {
+            source.get_cpu_throttling_stats()
+        })
+        .map(|(_, throttling)| throttling);
+        if let Some(throttling) = throttling {
+            stats_entry.cpu_nr_throttled = Some(throttling.nr_throttled);
+            stats_entry.cpu_throttled_usec = throttling.throttled_usec;
+        }
+        if let Some(detector) = self.cpu_starvation.as_mut()
+            && let (Some(cpu_usage), Some(num_cpus)) = (stats_entry.cpu_usage, stats_entry.num_cpus)
+            && let Some(throttling) = throttling
         {
+            stats_entry.cpu_starved = detector.update(cpu_usage, num_cpus, throttling);
+        }
+
+        if config.collect_cpu_freq {
+            stats_entry.cpu_freq_mhz = stats::get_cpu_freq_mhz().ok();
+        }
+        cpu_span.exit();
+
+        let memory_span = debug_span!("memory").entered();
+        if let Some((_, mem_usage_kb)) =
+            find_first(sources, "memory_usage", &mut errors, |source| {
+                source.get_memory_usage_kb()
+            })
+        {
+            stats_entry.memory_usage_kb = Some(mem_usage_kb);
+        }
+
+        let (mem_total_kb, memory_unlimited) =
+            find_first_limit(sources, "memory_total", &mut errors, |source| {
+                source.get_memory_total_kb()
+            });
+        if let Some((_, mem_total_kb)) = mem_total_kb {
             stats_entry.memory_total_kb = Some(mem_total_kb);
         }
+        if config.report_unlimited {
+            stats_entry.memory_unlimited = memory_unlimited;
+        }
+        // with swap accounting on, the combined limit is what the OOM killer goes by
+        if let Some((_, memory_swap)) = find_first(sources, "memory_swap", &mut errors, |source| {
+            source.get_memory_swap_stats()
+        }) {
+            stats_entry.memory_swap_usage_kb = Some(memory_swap.usage_kb);
+            stats_entry.memory_swap_limit_kb = memory_swap.limit_kb;
+            stats_entry.memory_swap_ratio = memory_swap.ratio();
+        }
+        // the page cache counts towards the usage, but it's mostly reclaimable; anon is closer to the working set
+        if let Some((_, breakdown)) =
+            find_first(sources, "memory_breakdown", &mut errors, |source| {
+                source.get_memory_breakdown()
+            })
+        {
+            stats_entry.memory_anon_kb = Some(breakdown.anon_kb);
+            stats_entry.memory_file_kb = Some(breakdown.file_kb);
+        }
+        if let Some((_, swap)) = find_first(sources, "swap_usage", &mut errors, |source| {
+            source.get_swap_usage()
+        }) {
+            stats_entry.swap_usage_kb = Some(swap.usage_kb);
+            stats_entry.swap_total_kb = swap.limit_kb;
+        }
+        // stalls on memory are the early warning before an OOM kill; left out on kernels without PSI
+        if let Some((_, memory_pressure)) =
+            find_first(sources, "memory_pressure", &mut errors, |source| {
+                source.get_memory_pressure()
+            })
+        {
+            stats_entry.memory_pressure_some_avg10 = Some(memory_pressure.some_avg10);
+            stats_entry.memory_pressure_full_avg10 = Some(memory_pressure.full_avg10);
+        }
+        // cumulative, so a kill shows up as a step in it
+        if let Some((_, oom_kills)) = find_first(sources, "oom_kills", &mut errors, |source| {
+            source.get_oom_kill_count()
+        }) {
+            stats_entry.oom_kill_count = Some(oom_kills);
+        }
+        // flagged rather than clamped, so a spike over the limit can be told apart from a real reading
+        stats_entry.memory_over_limit = stats_entry.is_memory_over_limit();
+
+        if config.collect_memory_fragmentation {
+            stats_entry.memory_max_free_order = stats::get_memory_max_free_order().ok();
+        }
+        memory_span.exit();
+
+        let extras_span = debug_span!("extras").entered();
+        if let Some((_, burst_stats)) = find_first(sources, "cpu_burst", &mut errors, |source| {
+            source.get_cpu_burst_stats()
+        }) {
+            stats_entry.cpu_burst_count = Some(burst_stats.nr_bursts);
+            stats_entry.cpu_burst_usec = burst_stats.burst_usec;
+        }
+
+        // the cgroup's pressure ahead of the host's; older kernels have no PSI, which leaves it out
+        // rather than failing the entry
+        if let Some((_, cpu_pressure)) =
+            find_first(sources, "cpu_pressure", &mut errors, |source| {
+                source.get_cpu_pressure_some_avg10()
+            })
+        {
+            stats_entry.cpu_pressure_some_avg10 = Some(cpu_pressure);
+        }
+
+        if let Some((_, io_latency_ms)) =
+            find_first(sources, "disk_io_latency", &mut errors, |source| {
+                source.get_disk_io_latency_ms()
+            })
+        {
+            stats_entry.disk_io_latency_ms = Some(io_latency_ms);
+        }
+
+        // all tasks waiting on storage tells a job starved of IO apart from one starved of CPU
+        if let Some((_, io_pressure)) = find_first(sources, "io_pressure", &mut errors, |source| {
+            source.get_io_pressure_full_avg10()
+        }) {
+            stats_entry.io_pressure_full_avg10 = Some(io_pressure);
+        }
+
+        // over the whole interval since the previous entry, so the first entry has none
+        if let Some((_, disk_bytes)) =
+            find_first(sources, "disk_throughput", &mut errors, |source| {
+                source.get_disk_bytes()
+            })
+        {
+            // by device, so one that comes or goes in between doesn't throw the sum off
+            if let Some((read_bytes_per_sec, write_bytes_per_sec)) =
+                self.disk_rates.update(Instant::now(), disk_bytes)
+            {
+                stats_entry.disk_read_kb_per_sec = Some(read_bytes_per_sec / 1024.0);
+                stats_entry.disk_write_kb_per_sec = Some(write_bytes_per_sec / 1024.0);
+            }
+        }
 
-        if let Some(gpu_stats) = stats::get_gpu_stats() {
+        // likewise
+        if let Some((_, network_bytes)) =
+            find_first(sources, "network_throughput", &mut errors, |source| {
+                source.get_network_bytes()
+            })
+        {
+            // by interface, e.g. a veth that comes or goes in between doesn't throw the sum off
+            if let Some((rx_bytes_per_sec, tx_bytes_per_sec)) =
+                self.net_rates.update(Instant::now(), network_bytes)
+            {
+                stats_entry.net_rx_kb_per_sec = Some(rx_bytes_per_sec / 1024.0);
+                stats_entry.net_tx_kb_per_sec = Some(tx_bytes_per_sec / 1024.0);
+            }
+        }
+
+        if let Some((_, major_faults)) =
+            find_first(sources, "major_faults", &mut errors, |source| {
+                source.get_major_faults()
+            })
+        {
+            stats_entry.major_faults_per_sec =
+                self.major_faults_rate.update(Instant::now(), major_faults);
+        }
+
+        if let Some((_, counters)) =
+            find_first(sources, "scheduler_counters", &mut errors, |source| {
+                source.get_scheduler_counters()
+            })
+        {
+            let now = Instant::now();
+            stats_entry.context_switches_per_sec = self
+                .context_switches_rate
+                .update(now, counters.context_switches);
+            stats_entry.interrupts_per_sec = self.interrupts_rate.update(now, counters.interrupts);
+        }
+
+        // host-wide, only /proc has them
+        if let Some((_, load_average)) =
+            find_first(sources, "load_average", &mut errors, |source| {
+                source.get_load_average()
+            })
+        {
+            stats_entry.load_avg_1 = Some(load_average.one);
+            stats_entry.load_avg_5 = Some(load_average.five);
+            stats_entry.load_avg_15 = Some(load_average.fifteen);
+        }
+
+        if let Some((_, uptime_secs)) = find_first(sources, "uptime", &mut errors, |source| {
+            source.get_uptime_secs()
+        }) {
+            stats_entry.host_uptime_secs = Some(uptime_secs);
+        }
+
+        if config.collect_swap_activity
+            && let Some((_, counters)) = find_first(sources, "swap", &mut errors, |source| {
+                source.get_swap_counters()
+            })
+        {
+            let now = Instant::now();
+            stats_entry.swap_in_per_sec = self.swap_in_rate.update(now, counters.pages_in);
+            stats_entry.swap_out_per_sec = self.swap_out_rate.update(now, counters.pages_out);
+        }
+
+        if let Some((_, pids_stats)) = find_first(sources, "pids", &mut errors, |source| {
+            source.get_pids_stats()
+        }) {
+            stats_entry.pids_current = Some(pids_stats.current);
+            stats_entry.pids_max = pids_stats.max;
+            stats_entry.pids_ratio = pids_stats.ratio();
+        }
+
+        // without a target, our own process is the next best thing, as it's in the same cgroup as the workload
+        let pid = config.target_pid.unwrap_or_else(std::process::id);
+        stats_entry.thread_count = find_first(sources, "thread_count", &mut errors, |source| {
+            source.get_thread_count(pid)
+        })
+        .map(|(_, thread_count)| thread_count);
+
+        if config.report_oom_score
+            && let Some((_, oom_score)) = find_first(sources, "oom_score", &mut errors, |source| {
+                source.get_oom_score(pid)
+            })
+        {
+            stats_entry.oom_score = Some(oom_score.score);
+            stats_entry.oom_score_adj = Some(oom_score.adj);
+        }
+
+        if let Some((_, created_at)) =
+            find_first(sources, "cgroup_created_at", &mut errors, |source| {
+                source.get_cgroup_created_at()
+            })
+        {
+            // a clock that's gone backwards past the creation time leaves the age unknown
+            stats_entry.cgroup_age_secs = SystemTime::now()
+                .duration_since(created_at)
+                .ok()
+                .map(|age| age.as_secs_f64());
+        }
+        extras_span.exit();
+
+        let gpu_span = debug_span!("gpu").entered();
+        let gpu_stats = stats::get_gpu_stats().filter(|gpu_stats| {
+            let checked = self.gpu_count_watch.check(gpu_stats.num_gpus);
+            if let Err(e) = &checked {
+                error!("{e}, leaving out GPU stats");
+                errors.push(format!("gpu: {e}"));
+            }
+            checked.is_ok()
+        });
+        if let Some(gpu_stats) = gpu_stats {
+            // the totals are short of these, so say so rather than have them look like low usage
+            if gpu_stats.unparsed_fields > 0 {
+                let e = format!(
+                    "{} GPU fields were empty or malformed and left out of the totals",
+                    gpu_stats.unparsed_fields
+                );
+                debug!("{e}");
+                errors.push(format!("gpu: {e}"));
+            }
             stats_entry.num_gpus = Some(gpu_stats.num_gpus);
             stats_entry.gpu_usage = Some(gpu_stats.gpu_usage);
             stats_entry.gpu_memory_usage_kb = Some(gpu_stats.memory_usage_kb);
             stats_entry.gpu_memory_total_kb = Some(gpu_stats.memory_total_kb);
+            stats_entry.gpu_temperature_c = gpu_stats.temperature_c;
+            stats_entry.gpu_power_watts = gpu_stats.power_watts;
+            stats_entry.gpus = Some(
+                gpu_stats
+                    .gpus
+                    .iter()
+                    .map(|gpu| PerGpuStats {
+                        index: gpu.index,
+                        mig_index: gpu.mig_index,
+                        usage: gpu.usage,
+                        memory_usage_kb: gpu.memory_usage_kb,
+                        memory_total_kb: gpu.memory_total_kb,
+                    })
+                    .collect(),
+            );
+            if config.report_gpu_memory_mib {
+                stats_entry.gpu_memory_usage_mib = Some(gpu_stats.memory_usage_mib);
+                stats_entry.gpu_memory_total_mib = Some(gpu_stats.memory_total_mib);
+            }
+            if config.weight_gpu_usage {
+                stats_entry.gpu_usage_weighted = stats::get_gpu_usage_weighted(&gpu_stats);
+            }
         }
+        gpu_span.exit();
 
-        debug!("New stats entry: {:?}", stats_entry);
-        if let Err(e) = store::write_stats_entry(stats_entry, config) {
-            error!("Failed to write stats entry: {}", e);
+        if let Some(pod) = self.pod.as_mut() {
+            let _containers_span = debug_span!("containers").entered();
+            match pod.collect_container_stats() {
+                Ok(containers) => stats_entry.containers = Some(containers),
+                Err(e) => {
+                    error!(
+                        "Failed to collect container stats from {:?}: {e}",
+                        pod.pod_cgroup()
+                    );
+                    errors.push(format!("containers: {e}"));
+                }
+            }
+        }
+
+        self.interval_cpu_seconds = interval_cpu_seconds;
+        if config.inline_errors && !errors.is_empty() {
+            stats_entry.errors = Some(errors);
         }
+        stats_entry
+    }
+}
+
+/// Log the host's CPU model and topology once, and write it to the stats directory if asked to
+fn log_cpu_info(sources: &[Box<dyn SystemStatsSource>], config: &Config) {
+    let Some(cpu_info) = sources.iter().find_map(|source| source.get_cpu_info().ok()) else {
+        info!("CPU model: unknown");
+        return;
+    };
+    info!(
+        "CPU model: {}, {} logical cores, {} physical cores",
+        cpu_info.model_name.as_deref().unwrap_or("unknown"),
+        cpu_info.logical_cores,
+        cpu_info
+            .physical_cores
+            .map_or("unknown".to_string(), |cores| cores.to_string())
+    );
+    if config.write_node_info
+        && let OutputMode::StatsDir(stats_dir_config) = &config.output_mode
+        && let Err(e) = store::write_node_info(&cpu_info, stats_dir_config)
+    {
+        error!("Failed to write node info: {}", e);
+    }
+}
 
-        thread::sleep(config.stat_interval);
+fn get_pod_cgroup(pod_mode: &PodModeConfig) -> Option<PathBuf> {
+    if let Some(pod_cgroup) = &pod_mode.pod_cgroup {
+        return Some(pod_cgroup.clone());
     }
+    // without a cgroup namespace, our own cgroup is one of the containers in the pod;
+    // with one (the usual case), our cgroup is `/` and there is no visible parent
+    let mount_point = get_cgroup_v2_mount_point("/proc/mounts").ok();
+    let self_path = get_cgroup_v2_self_path("/proc/self/cgroup").ok();
+    let pod_cgroup = mount_point
+        .zip(self_path)
+        .and_then(|(mount_point, self_path)| {
+            let parent = self_path.parent()?;
+            Some(mount_point.join(parent.strip_prefix("/").unwrap_or(parent)))
+        });
+    if pod_cgroup.is_none() {
+        error!("Could not determine the pod's cgroup, set ACOLYTE_POD_CGROUP; disabling pod mode");
+    }
+    pod_cgroup
 }
 
-fn get_sources() -> Vec<Box<dyn SystemStatsSource>> {
+fn get_sources(
+    proc_scope: ProcScope,
+    disabled_controllers: &[String],
+    exclude_cpu_steal: bool,
+) -> Vec<Box<dyn SystemStatsSource>> {
     let mut sources: Vec<Box<dyn SystemStatsSource>> = vec![];
     let cgroup_version = detect_cgroup_version("/proc/self/cgroup").ok();
 
@@ -85,8 +687,46 @@ fn get_sources() -> Vec<Box<dyn SystemStatsSource>> {
             v1_mount_points,
         )));
     }
-    sources.push(Box::new(ProcSource::with_filesystem_reader_at(
-        PathBuf::from("/proc"),
-    )));
+    if !disabled_controllers.is_empty() {
+        info!(
+            "Not reading the disabled cgroup controllers: {}",
+            disabled_controllers.join(", ")
+        );
+        sources = sources
+            .into_iter()
+            .map(|source| -> Box<dyn SystemStatsSource> {
+                Box::new(ControllerFilter::new(source, disabled_controllers.to_vec()))
+            })
+            .collect();
+    }
+    if let Some(env_limits_source) = EnvLimitsSource::from_env() {
+        sources.push(Box::new(env_limits_source));
+    }
+    match proc_scope {
+        ProcScope::Host => sources.push(Box::new(
+            ProcSource::with_filesystem_reader_at(PathBuf::from("/proc"))
+                .excluding_steal(exclude_cpu_steal),
+        )),
+        ProcScope::Reject => info!("Leaving out the host-wide proc source as configured"),
+    }
     sources
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::SourceKind;
+
+    #[test]
+    fn test_proc_scope_reject_excludes_proc() {
+        let kinds = |proc_scope| -> Vec<SourceKind> {
+            get_sources(proc_scope, &[], false)
+                .iter()
+                .map(|source| source.kind())
+                .collect()
+        };
+
+        assert!(kinds(ProcScope::Host).contains(&SourceKind::Proc));
+        assert!(!kinds(ProcScope::Reject).contains(&SourceKind::Proc));
+    }
+}
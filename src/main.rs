@@ -1,21 +1,35 @@
 use acolyte::config::Config;
-use acolyte::consts::{ID_ENV_VAR, MAX_RUN_ATTEMPTS, RESTART_DELAY_SECS};
+use acolyte::consts::{
+    EXIT_CODE_OUTPUT_SETUP, EXIT_CODE_UNEXPECTED_CGROUP, ID_ENV_VAR, MAX_RESTART_DELAY_SECS,
+    MAX_RUN_ATTEMPTS, RESTART_DELAY_SECS,
+};
+use acolyte::sinks::{self, StatsSink};
+use acolyte::stats::{CgroupVersion, detect_cgroup_version};
 use anyhow::Context;
 use libc::{SIG_IGN, SIGHUP};
 use std::time::Duration;
 use std::{env, os::unix::process::CommandExt, panic, process, thread};
 use tracing::{error, info, warn};
 use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::prelude::*;
 
 const NO_RESTART_ENV_VAR: &str = "ACOLYTE_NO_RESTART";
 const RESTART_ENV_VAR: &str = "ACOLYTE_RESTART";
+const ONCE_ENV_VAR: &str = "ACOLYTE_ONCE";
+const ONCE_ARG: &str = "--once";
 fn is_no_restart() -> bool {
     env::var(NO_RESTART_ENV_VAR)
         .map(|v| !v.is_empty())
         .unwrap_or(false)
 }
 
+fn is_once() -> bool {
+    let enabled = env::var(ONCE_ENV_VAR).ok();
+    matches!(enabled.as_deref(), Some("1" | "true" | "yes"))
+        || env::args().skip(1).any(|arg| arg == ONCE_ARG)
+}
+
 fn get_restart_count() -> u8 {
     env::var(RESTART_ENV_VAR)
         .ok()
@@ -23,38 +37,88 @@ fn get_restart_count() -> u8 {
         .unwrap_or(0)
 }
 
+/// How long to wait before the `restart_count`th restart; doubling each time, a transient problem
+/// gets a quick retry while a persistent one isn't retried in a tight loop
+fn restart_delay(restart_count: u8) -> Duration {
+    let doublings = u32::from(restart_count.saturating_sub(1));
+    let delay_secs = 2u64
+        .checked_pow(doublings)
+        .and_then(|factor| RESTART_DELAY_SECS.checked_mul(factor))
+        .unwrap_or(MAX_RESTART_DELAY_SECS);
+    Duration::from_secs(delay_secs.min(MAX_RESTART_DELAY_SECS))
+}
+
 fn main() {
     nohup();
+    acolyte::shutdown::install_signal_handlers();
 
     init_logging();
 
     let config = Config::from_env().context("Failed to load config").unwrap();
+    config.warn_about_questionable_settings();
+    acolyte::store::set_time_precision(config.time_precision);
     let sentry_guard = init_sentry(&config);
     if sentry_guard.is_some() {
         info!("Sentry initialized");
     } else {
         warn!("Sentry NOT initialized");
     }
+    // after Sentry so that its panic handler still gets called after ours
+    acolyte::crash_dump::install_panic_hook(&config);
 
-    if is_no_restart() {
+    if let Some(expected) = &config.expected_cgroup {
+        check_cgroup_version(expected);
+    }
+
+    // once, up front: it's where a misconfigured output shows, and restarting wouldn't fix that
+    let sink = match sinks::create_sink(&config) {
+        Ok(sink) => sink,
+        Err(e) => {
+            error!("Failed to set up output: {}", e);
+            process::exit(EXIT_CODE_OUTPUT_SETUP);
+        }
+    };
+
+    if is_once() {
+        info!("Run-once mode enabled; writing a single stats entry");
+        if let Err(e) = acolyte::run_once(&config, sink) {
+            error!("Failed to write stats entry: {}", e);
+            process::exit(1);
+        }
+        process::exit(0);
+    } else if is_no_restart() {
         info!("No-restart mode enabled; running Acolyte without restart logic");
-        acolyte::run_acolyte(&config);
+        acolyte::run_acolyte(&config, sink);
         process::exit(0);
     } else {
-        run_with_restart(&config);
+        run_with_restart(&config, sink);
     }
 }
 
-fn run_with_restart(config: &Config) {
+/// Exit if the node doesn't have the cgroup version it's expected to have; restarting won't change that
+fn check_cgroup_version(expected: &CgroupVersion) {
+    let detected = detect_cgroup_version("/proc/self/cgroup").ok();
+    if detected.as_ref() != Some(expected) {
+        error!(
+            "Expected cgroup {} (ACOLYTE_EXPECT_CGROUP) but detected {}, exiting",
+            expected.name(),
+            detected.as_ref().map_or("none", |version| version.name())
+        );
+        process::exit(EXIT_CODE_UNEXPECTED_CGROUP);
+    }
+}
+
+fn run_with_restart(config: &Config, sink: Box<dyn StatsSink + '_>) {
     let restart_count = get_restart_count();
     if restart_count > 0 {
+        let delay = restart_delay(restart_count);
         info!(
             "Restarting Acolyte - waiting {} seconds (attempt {}/{})",
-            RESTART_DELAY_SECS,
+            delay.as_secs(),
             restart_count + 1,
             MAX_RUN_ATTEMPTS
         );
-        thread::sleep(Duration::from_secs(RESTART_DELAY_SECS));
+        thread::sleep(delay);
     }
 
     info!(
@@ -64,7 +128,10 @@ fn run_with_restart(config: &Config) {
         MAX_RUN_ATTEMPTS
     );
 
-    let run_result = panic::catch_unwind(|| acolyte::run_acolyte(config));
+    // a panic execs a new process below, so the sink is never used again after one
+    let run_result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        acolyte::run_acolyte(config, sink)
+    }));
     if run_result.is_ok() {
         process::exit(0);
     } else {
@@ -103,7 +170,8 @@ fn init_logging() {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
     tracing_subscriber::registry()
         .with(filter)
-        .with(tracing_subscriber::fmt::layer())
+        // with debug logging, the sample loop's phase spans log how long they took when they close
+        .with(tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE))
         .with(sentry_tracing::layer())
         .init();
 }
@@ -126,3 +194,18 @@ fn init_sentry(config: &Config) -> Option<sentry::ClientInitGuard> {
 
     Some(guard)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_delay_doubles_up_to_max() {
+        let delay_secs = |restart_count| restart_delay(restart_count).as_secs();
+        assert_eq!(delay_secs(1), RESTART_DELAY_SECS);
+        assert_eq!(delay_secs(2), RESTART_DELAY_SECS * 2);
+        assert_eq!(delay_secs(3), RESTART_DELAY_SECS * 4);
+        assert_eq!(delay_secs(10), MAX_RESTART_DELAY_SECS);
+        assert_eq!(delay_secs(u8::MAX), MAX_RESTART_DELAY_SECS);
+    }
+}
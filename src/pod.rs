@@ -0,0 +1,166 @@
+use crate::stats::cgroup_v2::{CgroupV2FilesystemReader, CgroupV2Source};
+use crate::stats::{CpuTimeReading, SystemStatsSource};
+use crate::store::ContainerStats;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Collects stats for every container cgroup directly under the pod's cgroup.
+///
+/// Containers come and go between samples, so the children are listed anew every time and
+/// a container whose cgroup disappears mid-read is simply left out of this round. Each container's
+/// source is kept across samples, so its CPU usage covers the whole interval since the previous
+/// sample without waiting; a container's first sample has no usage yet.
+pub struct PodCollector {
+    pod_cgroup: PathBuf,
+    containers: HashMap<String, Container>,
+}
+
+struct Container {
+    source: CgroupV2Source<CgroupV2FilesystemReader>,
+    previous_cpu_time: Option<CpuTimeReading>,
+}
+
+impl PodCollector {
+    pub fn new(pod_cgroup: PathBuf) -> Self {
+        Self {
+            pod_cgroup,
+            containers: HashMap::new(),
+        }
+    }
+
+    pub fn pod_cgroup(&self) -> &Path {
+        &self.pod_cgroup
+    }
+
+    pub fn collect_container_stats(&mut self) -> io::Result<Vec<ContainerStats>> {
+        let children = list_child_cgroups(&self.pod_cgroup)?;
+        // forget the containers that are gone, so a recreated one starts afresh
+        self.containers
+            .retain(|name, _| children.iter().any(|(child, _)| child == name));
+
+        let mut containers = vec![];
+        for (name, path) in children {
+            let container = self
+                .containers
+                .entry(name.clone())
+                .or_insert_with(|| Container {
+                    source: CgroupV2Source::with_filesystem_reader_at(path.clone()),
+                    previous_cpu_time: None,
+                });
+            let cpu_time = container.source.get_cpu_time().ok();
+            let cpu_usage = container
+                .previous_cpu_time
+                .as_ref()
+                .zip(cpu_time.as_ref())
+                .and_then(|(previous, current)| current.usage_since(previous));
+            container.previous_cpu_time = cpu_time;
+            let stats = ContainerStats {
+                name,
+                num_cpus: container.source.get_num_cpus().ok(),
+                cpu_usage,
+                memory_usage_kb: container.source.get_memory_usage_kb().ok(),
+                memory_total_kb: container.source.get_memory_total_kb().ok(),
+            };
+            if !path.exists() {
+                debug!("Container cgroup {path:?} went away while sampling, skipping");
+                continue;
+            }
+            containers.push(stats);
+        }
+        Ok(containers)
+    }
+}
+
+fn list_child_cgroups(pod_cgroup: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut children: Vec<(String, PathBuf)> = fs::read_dir(pod_cgroup)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| {
+            (
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+            )
+        })
+        .collect();
+    // keep the output stable between entries
+    children.sort();
+    Ok(children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_container_cgroup(pod_cgroup: &Path, name: &str, memory_current: &str) {
+        let path = pod_cgroup.join(name);
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("cgroup.type"), "domain\n").unwrap();
+        fs::write(path.join("cpu.max"), "200000 100000\n").unwrap();
+        fs::write(path.join("cpu.stat"), "usage_usec 1000\n").unwrap();
+        fs::write(path.join("memory.current"), memory_current).unwrap();
+        fs::write(path.join("memory.max"), "max\n").unwrap();
+    }
+
+    #[test]
+    fn test_collect_container_stats() -> io::Result<()> {
+        let pod_cgroup = tempfile::tempdir()?;
+        create_container_cgroup(pod_cgroup.path(), "cri-containerd-bbbb.scope", "2048\n");
+        create_container_cgroup(pod_cgroup.path(), "cri-containerd-aaaa.scope", "1024\n");
+        // the pod's own interface files sit next to the container directories
+        fs::write(pod_cgroup.path().join("cgroup.procs"), "")?;
+
+        let containers =
+            PodCollector::new(pod_cgroup.path().to_path_buf()).collect_container_stats()?;
+
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers[0].name, "cri-containerd-aaaa.scope");
+        assert_eq!(containers[0].num_cpus, Some(2.0));
+        assert_eq!(containers[0].memory_usage_kb, Some(1));
+        assert_eq!(containers[1].name, "cri-containerd-bbbb.scope");
+        assert_eq!(containers[1].memory_usage_kb, Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_container_stats_for_stopped_container() -> io::Result<()> {
+        let pod_cgroup = tempfile::tempdir()?;
+        create_container_cgroup(pod_cgroup.path(), "running", "1024\n");
+        // an empty directory is what's briefly left of a container that's being torn down
+        fs::create_dir(pod_cgroup.path().join("stopping"))?;
+
+        let containers =
+            PodCollector::new(pod_cgroup.path().to_path_buf()).collect_container_stats()?;
+
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers[1].name, "stopping");
+        assert_eq!(containers[1].memory_usage_kb, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_container_cpu_usage_between_samples() -> io::Result<()> {
+        let pod_cgroup = tempfile::tempdir()?;
+        create_container_cgroup(pod_cgroup.path(), "app", "1024\n");
+        let mut collector = PodCollector::new(pod_cgroup.path().to_path_buf());
+
+        // nothing to measure against yet
+        assert_eq!(collector.collect_container_stats()?[0].cpu_usage, None);
+
+        fs::write(
+            pod_cgroup.path().join("app/cpu.stat"),
+            "usage_usec 1000000000\n",
+        )?;
+        let cpu_usage = collector.collect_container_stats()?[0].cpu_usage;
+        assert!(cpu_usage.is_some_and(|usage| usage > 0.0));
+
+        // a container that's gone is forgotten, so it starts over if it comes back
+        fs::remove_dir_all(pod_cgroup.path().join("app"))?;
+        assert!(collector.collect_container_stats()?.is_empty());
+        create_container_cgroup(pod_cgroup.path(), "app", "1024\n");
+        assert_eq!(collector.collect_container_stats()?[0].cpu_usage, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use libc::{SIGINT, SIGTERM, SIGUSR1, c_int};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+static REOPEN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// how often a sleep checks whether it should stop early
+const SLEEP_SLICE: Duration = Duration::from_millis(100);
+
+/// Make SIGTERM and SIGINT request a graceful shutdown instead of killing the process outright,
+/// and SIGUSR1 request reopening the output, e.g. after log rotation.
+pub fn install_signal_handlers() {
+    unsafe {
+        libc::signal(SIGTERM, handle_signal as *const () as libc::sighandler_t);
+        libc::signal(SIGINT, handle_signal as *const () as libc::sighandler_t);
+    }
+    install_reopen_signal_handler();
+}
+
+/// Make SIGUSR1 request reopening the output, see `take_reopen_request`
+pub fn install_reopen_signal_handler() {
+    unsafe {
+        libc::signal(
+            SIGUSR1,
+            handle_reopen_signal as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+extern "C" fn handle_signal(_signal: c_int) {
+    // only async-signal-safe things in here; an atomic store is fine
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn handle_reopen_signal(_signal: c_int) {
+    REOPEN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Whether the output should be reopened, clearing the request so it's acted on once
+pub fn take_reopen_request() -> bool {
+    REOPEN_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Sleep for the given duration, or less if a shutdown is requested in the meantime.
+///
+/// Returns `false` if the sleep was cut short.
+pub fn sleep(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if is_shutdown_requested() {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        thread::sleep(remaining.min(SLEEP_SLICE));
+    }
+}
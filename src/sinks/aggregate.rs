@@ -0,0 +1,240 @@
+use crate::sinks::StatsSink;
+use crate::store::{RunSummary, StatsEntry};
+use std::io;
+use std::time::Duration;
+use tracing::debug;
+
+/// Fields averaged over the window; the rest are limits and names, so the latest value stands
+const MEAN_FIELDS: &[&str] = &[
+    "cpu_usage",
+    "cpu_freq_mhz",
+    "memory_usage_kb",
+    "memory_swap_usage_kb",
+    "memory_swap_ratio",
+    "memory_anon_kb",
+    "memory_file_kb",
+    "swap_usage_kb",
+    "memory_pressure_some_avg10",
+    "memory_pressure_full_avg10",
+    "cpu_pressure_some_avg10",
+    "gpu_usage",
+    "gpu_usage_weighted",
+    "gpu_memory_usage_kb",
+    "gpu_temperature_c",
+    "gpu_power_watts",
+    "disk_io_latency_ms",
+    "io_pressure_full_avg10",
+    "disk_read_kb_per_sec",
+    "disk_write_kb_per_sec",
+    "net_rx_kb_per_sec",
+    "net_tx_kb_per_sec",
+    "major_faults_per_sec",
+    "context_switches_per_sec",
+    "interrupts_per_sec",
+    "swap_in_per_sec",
+    "swap_out_per_sec",
+    "pids_current",
+    "pids_ratio",
+    "thread_count",
+];
+
+/// Pass on one entry per `window`, aggregated from the entries sampled within it.
+///
+/// Windows go by the entries' own `time`: an entry that falls past the end of the window closes it.
+pub struct Aggregate<S: StatsSink> {
+    inner: S,
+    window: Duration,
+    pending: Vec<StatsEntry>,
+}
+
+impl<S: StatsSink> Aggregate<S> {
+    pub fn new(inner: S, window: Duration) -> Self {
+        Self {
+            inner,
+            window,
+            pending: vec![],
+        }
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let entries = std::mem::take(&mut self.pending);
+        debug!("Aggregating {} entries", entries.len());
+        self.inner.write(&aggregate_entries(&entries)?)
+    }
+}
+
+impl<S: StatsSink> StatsSink for Aggregate<S> {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        if let Some(first) = self.pending.first()
+            && entry.time - first.time >= self.window.as_secs_f64()
+        {
+            self.write_pending()?;
+        }
+        self.pending.push(entry.clone());
+        Ok(())
+    }
+
+    /// Aggregate what there is of the current window, as this is only called when shutting down
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.inner.flush()
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        self.inner.bytes_written()
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        self.inner.reopen()
+    }
+
+    fn write_run_summary(&mut self, summary: &RunSummary) -> io::Result<()> {
+        self.inner.write_run_summary(summary)
+    }
+}
+
+/// Combine the entries into one: the mean for usage, the peak in `cpu_usage_max` and `memory_usage_max_kb`,
+/// the total for `cpu_seconds`, whether any entry was flagged for the flags, and the last value for the rest.
+fn aggregate_entries(entries: &[StatsEntry]) -> io::Result<StatsEntry> {
+    let Some(last) = entries.last() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No entries to aggregate",
+        ));
+    };
+
+    let serde_json::Value::Object(mut aggregated) = serde_json::to_value(last)? else {
+        return Err(io::Error::other(
+            "Stats entry didn't serialize to an object",
+        ));
+    };
+    let all_values: Vec<serde_json::Value> = entries
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()?;
+    for field in MEAN_FIELDS {
+        let values: Vec<&serde_json::Value> = all_values
+            .iter()
+            .filter_map(|value| value.get(*field))
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+        let mean = values
+            .iter()
+            .filter_map(|value| value.as_f64())
+            .sum::<f64>()
+            / values.len() as f64;
+        // counts stay whole numbers, or the field won't read back in
+        let mean = if values.iter().all(|value| value.is_u64()) {
+            serde_json::Value::from(mean.round() as u64)
+        } else {
+            serde_json::Value::from(mean)
+        };
+        aggregated.insert(field.to_string(), mean);
+    }
+    let mut aggregated: StatsEntry = serde_json::from_value(aggregated.into())?;
+
+    aggregated.cpu_usage_max = entries
+        .iter()
+        .filter_map(|entry| entry.cpu_usage)
+        .reduce(f64::max);
+    aggregated.memory_usage_max_kb = entries
+        .iter()
+        .filter_map(|entry| entry.memory_usage_kb)
+        .max();
+    aggregated.cpu_seconds = entries
+        .iter()
+        .filter_map(|entry| entry.cpu_seconds)
+        .reduce(|total, cpu_seconds| total + cpu_seconds);
+    aggregated.cpu_starved = entries
+        .iter()
+        .filter_map(|entry| entry.cpu_starved)
+        .reduce(|any, starved| any || starved);
+    aggregated.memory_over_limit = entries
+        .iter()
+        .filter_map(|entry| entry.memory_over_limit)
+        .reduce(|any, over_limit| any || over_limit);
+    Ok(aggregated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn entry_at(time: f64, cpu_usage: f64, memory_usage_kb: u64) -> StatsEntry {
+        let mut entry = StatsEntry::new();
+        entry.time = time;
+        entry.cpu_usage = Some(cpu_usage);
+        entry.memory_usage_kb = Some(memory_usage_kb);
+        entry.memory_total_kb = Some(4096);
+        entry
+    }
+
+    #[test]
+    fn test_aggregate_entries() -> io::Result<()> {
+        let mut entries = vec![
+            entry_at(100.0, 0.5, 1000),
+            entry_at(101.0, 1.5, 3001),
+            entry_at(102.0, 1.0, 2000),
+        ];
+        entries[0].cpu_seconds = Some(0.5);
+        entries[1].cpu_seconds = Some(1.5);
+        entries[1].cpu_starved = Some(true);
+        entries[2].cpu_starved = Some(false);
+        entries[2].memory_total_kb = Some(8192);
+        entries[2].pids_current = Some(7);
+
+        let aggregated = aggregate_entries(&entries)?;
+        assert_eq!(aggregated.time, 102.0);
+        assert_eq!(aggregated.cpu_usage, Some(1.0));
+        assert_eq!(aggregated.cpu_usage_max, Some(1.5));
+        assert_eq!(aggregated.memory_usage_kb, Some(2000)); // 2000.33 rounded
+        assert_eq!(aggregated.memory_usage_max_kb, Some(3001));
+        assert_eq!(aggregated.memory_total_kb, Some(8192));
+        assert_eq!(aggregated.cpu_seconds, Some(2.0));
+        assert_eq!(aggregated.cpu_starved, Some(true));
+        // averaged over the entries that had it
+        assert_eq!(aggregated.pids_current, Some(7));
+        Ok(())
+    }
+
+    /// A sink that keeps what it's given
+    struct RecordingSink {
+        written: Rc<RefCell<Vec<StatsEntry>>>,
+    }
+
+    impl StatsSink for RecordingSink {
+        fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+            self.written.borrow_mut().push(entry.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_one_entry_per_window() -> io::Result<()> {
+        let written = Rc::new(RefCell::new(vec![]));
+        let recording_sink = RecordingSink {
+            written: written.clone(),
+        };
+        let mut sink = Aggregate::new(recording_sink, Duration::from_secs(3));
+
+        for i in 0..7 {
+            sink.write(&entry_at(100.0 + i as f64, i as f64, 1000))?;
+        }
+        // 100-102 and 103-105 are complete, 106 waits for the rest of its window
+        assert_eq!(written.borrow().len(), 2);
+        assert_eq!(written.borrow()[0].cpu_usage, Some(1.0));
+        assert_eq!(written.borrow()[1].cpu_usage, Some(4.0));
+
+        sink.flush()?;
+        assert_eq!(written.borrow().len(), 3);
+        assert_eq!(written.borrow()[2].cpu_usage, Some(6.0));
+        Ok(())
+    }
+}
@@ -0,0 +1,528 @@
+use crate::config::{Envelope, HttpPushConfig};
+use crate::sinks::StatsSink;
+use crate::store::{Enveloped, RunSummary, StatsEntry, TaggedRunSummary};
+use reqwest::Url;
+use reqwest::blocking::Client;
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Request bodies waiting to be pushed; past this, the oldest is dropped to make room for the newest
+const MAX_QUEUED: usize = 10;
+/// How long shutting down waits for what's still queued to be pushed
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// POSTs request bodies to a URL from a thread of its own, retrying a few times before giving up.
+///
+/// Posting only queues the body, so a slow or unreachable receiver doesn't hold up collecting stats.
+pub(crate) struct HttpPoster {
+    queue: Arc<PushQueue>,
+    finished: mpsc::Receiver<()>, // disconnected once the pushing thread is done
+    what: &'static str,           // what's being pushed to, for the logs
+}
+
+impl HttpPoster {
+    pub fn new(
+        what: &'static str,
+        url: &str,
+        mut headers: HeaderMap,
+        extra_headers: &[(String, String)],
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        // catch a malformed URL (e.g. an IPv6 address without the brackets) at startup rather than
+        // on every push; host names are resolved anew whenever a connection is made
+        let url = Url::parse(url).map_err(invalid_input)?;
+        for (name, value) in extra_headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(invalid_input)?;
+            let value = HeaderValue::from_str(value).map_err(invalid_input)?;
+            headers.insert(name, value);
+        }
+
+        let client = Client::builder()
+            .timeout(timeout)
+            .default_headers(headers)
+            .build()
+            .map_err(io::Error::other)?;
+
+        let queue = Arc::new(PushQueue::new());
+        let thread_queue = queue.clone();
+        let (finished_sender, finished) = mpsc::channel();
+        thread::Builder::new()
+            .name("http-push".to_string())
+            .spawn(move || {
+                let _finished = finished_sender;
+                while let Some(body) = thread_queue.pop() {
+                    push(&client, &url, what, body);
+                }
+            })?;
+
+        Ok(Self {
+            queue,
+            finished,
+            what,
+        })
+    }
+
+    pub fn post(&self, body: Vec<u8>) {
+        if self.queue.push(body) {
+            warn!(
+                "Dropping the oldest stats waiting for the {} while it's not taking them",
+                self.what
+            );
+        }
+    }
+}
+
+impl Drop for HttpPoster {
+    /// Push what's still queued, e.g. the last batch on shutdown, but not for long if the receiver is down
+    fn drop(&mut self) {
+        self.queue.close();
+        if let Err(mpsc::RecvTimeoutError::Timeout) = self.finished.recv_timeout(SHUTDOWN_GRACE) {
+            warn!(
+                "Gave up on pushing the last stats to the {} on shutdown",
+                self.what
+            );
+        }
+    }
+}
+
+fn push(client: &Client, url: &Url, what: &str, body: Vec<u8>) {
+    let mut last_error = None;
+    for attempt in 1..=MAX_ATTEMPTS {
+        if attempt > 1 {
+            thread::sleep(RETRY_DELAY);
+        }
+        match client
+            .post(url.clone())
+            .body(body.clone())
+            .send()
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => return,
+            Err(e) => {
+                debug!("Push to {what} attempt {attempt}/{MAX_ATTEMPTS} failed: {e}");
+                last_error = Some(e);
+            }
+        }
+    }
+
+    // dropping the entry here; retrying forever would just pile them up while the receiver is down
+    warn!(
+        "Dropping stats after {MAX_ATTEMPTS} failed {what} push attempts: {}",
+        last_error.map(|e| e.to_string()).unwrap_or_default()
+    );
+}
+
+/// Request bodies waiting for the pushing thread, oldest first
+struct PushQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+}
+
+struct QueueState {
+    bodies: VecDeque<Vec<u8>>,
+    closed: bool,
+}
+
+impl PushQueue {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                bodies: VecDeque::new(),
+                closed: false,
+            }),
+            ready: Condvar::new(),
+        }
+    }
+
+    /// Queue a body, returning whether the oldest one had to be dropped to make room for it
+    fn push(&self, body: Vec<u8>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let dropped = state.bodies.len() >= MAX_QUEUED && state.bodies.pop_front().is_some();
+        state.bodies.push_back(body);
+        self.ready.notify_one();
+        dropped
+    }
+
+    /// Wait for the next body to push; `None` once the queue is closed and empty
+    fn pop(&self) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(body) = state.bodies.pop_front() {
+                return Some(body);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.ready.wait(state).unwrap();
+        }
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_one();
+    }
+}
+
+/// POSTs stats entries as JSON to a webhook, with the acolyte's ID in an `X-Acolyte-Id` header.
+///
+/// With a batch size of 1, each entry is sent as a JSON object as soon as it's written,
+/// otherwise entries are collected and sent as a JSON array once the batch is full or flushed.
+pub struct HttpPushSink {
+    poster: HttpPoster,
+    batch_size: usize,
+    batch: Vec<StatsEntry>,
+    envelope: Option<Envelope>,
+}
+
+impl HttpPushSink {
+    pub fn new(config: &HttpPushConfig, acolyte_id: Uuid) -> io::Result<Self> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        // lets the receiver tell the senders apart without looking inside the entries
+        headers.insert(
+            "X-Acolyte-Id",
+            HeaderValue::from_str(&acolyte_id.to_string()).map_err(invalid_input)?,
+        );
+        let poster = HttpPoster::new(
+            "webhook",
+            &config.url,
+            headers,
+            &config.headers,
+            config.timeout,
+        )?;
+
+        Ok(Self {
+            poster,
+            batch_size: config.batch_size.max(1),
+            batch: vec![],
+            envelope: config.envelope.clone(),
+        })
+    }
+
+    fn post(&self, body: String) -> io::Result<()> {
+        self.poster.post(body.into_bytes());
+        Ok(())
+    }
+}
+
+impl StatsSink for HttpPushSink {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        if self.batch_size == 1 {
+            return self.post(serde_json::to_string(&Enveloped::new(
+                entry,
+                self.envelope.as_ref(),
+            ))?);
+        }
+
+        self.batch.push(entry.clone());
+        if self.batch.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        // the batch is taken out before sending, so it's either delivered or dropped as a whole;
+        // a later flush never sends (parts of) an already sent batch again
+        let batch = std::mem::take(&mut self.batch);
+        debug!("Pushing a batch of {} stats entries", batch.len());
+        let batch: Vec<Enveloped> = batch
+            .iter()
+            .map(|entry| Enveloped::new(entry, self.envelope.as_ref()))
+            .collect();
+        self.post(serde_json::to_string(&batch)?)
+    }
+
+    /// Pushed on its own, after the last batch
+    fn write_run_summary(&mut self, summary: &RunSummary) -> io::Result<()> {
+        self.post(serde_json::to_string(&TaggedRunSummary {
+            run_summary: summary,
+        })?)
+    }
+}
+
+fn invalid_input(e: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sinks::PeriodicFlush;
+    use crate::sinks::tests::ManualClock;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    pub struct ReceivedRequest {
+        pub headers: Vec<String>,
+        pub body: String,
+    }
+
+    /// Start a server that answers each request with the next status code, and reports what it got.
+    pub fn start_mock_server(statuses: Vec<u16>) -> (String, mpsc::Receiver<ReceivedRequest>) {
+        start_mock_server_at("127.0.0.1:0", statuses)
+    }
+
+    /// Like `start_mock_server`, but listening on the given address
+    pub fn start_mock_server_at(
+        address: &str,
+        statuses: Vec<u16>,
+    ) -> (String, mpsc::Receiver<ReceivedRequest>) {
+        let listener = TcpListener::bind(address).unwrap();
+        // `SocketAddr` formats IPv6 addresses with the brackets a URL needs
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut headers = vec![];
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end().to_string();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    headers.push(line);
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let response = format!(
+                    "HTTP/1.1 {status} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                sender
+                    .send(ReceivedRequest {
+                        headers,
+                        body: String::from_utf8(body).unwrap(),
+                    })
+                    .unwrap();
+            }
+        });
+
+        (url, receiver)
+    }
+
+    fn push_config(url: String) -> HttpPushConfig {
+        HttpPushConfig {
+            url,
+            headers: vec![("Authorization".to_string(), "Bearer hunter2".to_string())],
+            timeout: Duration::from_secs(5),
+            batch_size: 1,
+            flush_interval: None,
+            envelope: None,
+        }
+    }
+
+    #[test]
+    fn test_pushes_entry_as_json() -> io::Result<()> {
+        let (url, received) = start_mock_server(vec![200]);
+        let acolyte_id = Uuid::new_v4();
+        let mut sink = HttpPushSink::new(&push_config(url), acolyte_id)?;
+
+        let mut entry = StatsEntry::new();
+        entry.cpu_usage = Some(1.5);
+        sink.write(&entry)?;
+
+        let request = received.recv().unwrap();
+        let body: serde_json::Value = serde_json::from_str(&request.body)?;
+        assert_eq!(body["cpu_usage"], 1.5);
+        assert!(request.headers[0].starts_with("POST /hook "));
+        assert!(
+            request
+                .headers
+                .iter()
+                .any(|h| h.eq_ignore_ascii_case("authorization: Bearer hunter2"))
+        );
+        assert!(
+            request
+                .headers
+                .iter()
+                .any(|h| h.eq_ignore_ascii_case(&format!("x-acolyte-id: {acolyte_id}")))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pushes_to_ipv6_address() -> io::Result<()> {
+        // some CI hosts have no IPv6 at all
+        if TcpListener::bind("[::1]:0").is_err() {
+            return Ok(());
+        }
+        let (url, received) = start_mock_server_at("[::1]:0", vec![200]);
+        assert!(url.starts_with("http://[::1]:"));
+        let mut sink = HttpPushSink::new(&push_config(url), Uuid::nil())?;
+
+        sink.write(&StatsEntry::new())?;
+        assert!(received.recv().is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pushes_to_host_name() -> io::Result<()> {
+        let (url, received) = start_mock_server(vec![200]);
+        let url = url.replace("127.0.0.1", "localhost");
+        let mut sink = HttpPushSink::new(&push_config(url), Uuid::nil())?;
+
+        sink.write(&StatsEntry::new())?;
+        assert!(received.recv().is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_malformed_url() {
+        for url in ["http://::1:8080/hook", "not a url"] {
+            let result = HttpPushSink::new(&push_config(url.to_string()), Uuid::nil());
+            assert_eq!(
+                result.err().map(|e| e.kind()),
+                Some(io::ErrorKind::InvalidInput),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_retries_failed_push() -> io::Result<()> {
+        let (url, received) = start_mock_server(vec![500, 200]);
+        let mut sink = HttpPushSink::new(&push_config(url), Uuid::nil())?;
+
+        sink.write(&StatsEntry::new())?;
+        assert_eq!(received.iter().take(2).count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_gives_up_after_max_attempts() -> io::Result<()> {
+        let (url, received) = start_mock_server(vec![503; MAX_ATTEMPTS as usize]);
+        let mut sink = HttpPushSink::new(&push_config(url), Uuid::nil())?;
+
+        sink.write(&StatsEntry::new())?;
+        assert_eq!(received.iter().count(), MAX_ATTEMPTS as usize);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_does_not_wait_for_receiver_that_is_down() -> io::Result<()> {
+        // nothing's listening once the listener is dropped
+        let url = format!(
+            "http://{}/hook",
+            TcpListener::bind("127.0.0.1:0")?.local_addr()?
+        );
+        let mut sink = HttpPushSink::new(&push_config(url), Uuid::nil())?;
+
+        let started = Instant::now();
+        sink.write(&StatsEntry::new())?;
+        assert!(started.elapsed() < RETRY_DELAY); // the retries are left to the pushing thread
+        Ok(())
+    }
+
+    #[test]
+    fn test_queue_drops_oldest_when_full() {
+        let queue = PushQueue::new();
+        for body in 0..MAX_QUEUED as u8 {
+            assert!(!queue.push(vec![body]));
+        }
+        assert!(queue.push(vec![MAX_QUEUED as u8]));
+        queue.close();
+
+        let bodies: Vec<Vec<u8>> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(bodies.len(), MAX_QUEUED);
+        assert_eq!(bodies[0], vec![1]);
+        assert_eq!(bodies[MAX_QUEUED - 1], vec![MAX_QUEUED as u8]);
+    }
+
+    #[test]
+    fn test_pushes_full_batches_as_arrays() -> io::Result<()> {
+        let (url, received) = start_mock_server(vec![200, 200]);
+        let mut sink = HttpPushSink::new(
+            &HttpPushConfig {
+                batch_size: 3,
+                ..push_config(url)
+            },
+            Uuid::nil(),
+        )?;
+
+        for _ in 0..2 {
+            sink.write(&StatsEntry::new())?;
+        }
+        assert!(received.try_recv().is_err()); // batch isn't full yet
+
+        sink.write(&StatsEntry::new())?;
+        let body: serde_json::Value = serde_json::from_str(&received.recv().unwrap().body)?;
+        assert_eq!(body.as_array().unwrap().len(), 3);
+
+        sink.write(&StatsEntry::new())?;
+        sink.flush()?; // e.g. on shutdown
+        let body: serde_json::Value = serde_json::from_str(&received.recv().unwrap().body)?;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pushes_partial_batch_after_flush_interval() -> io::Result<()> {
+        let (url, received) = start_mock_server(vec![200]);
+        let clock = ManualClock::new();
+        let push_sink = HttpPushSink::new(
+            &HttpPushConfig {
+                batch_size: 10,
+                ..push_config(url)
+            },
+            Uuid::nil(),
+        )?;
+        let mut sink = PeriodicFlush::new(push_sink, clock.clone(), Duration::from_secs(60));
+
+        sink.write(&StatsEntry::new())?;
+        clock.advance(Duration::from_secs(30));
+        sink.write(&StatsEntry::new())?;
+        assert!(received.try_recv().is_err());
+
+        clock.advance(Duration::from_secs(30));
+        sink.write(&StatsEntry::new())?;
+        let body: serde_json::Value = serde_json::from_str(&received.recv().unwrap().body)?;
+        assert_eq!(body.as_array().unwrap().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_batch_is_not_sent_again() -> io::Result<()> {
+        let mut statuses = vec![500; MAX_ATTEMPTS as usize];
+        statuses.push(200);
+        let (url, received) = start_mock_server(statuses);
+        let mut sink = HttpPushSink::new(
+            &HttpPushConfig {
+                batch_size: 2,
+                ..push_config(url)
+            },
+            Uuid::nil(),
+        )?;
+
+        sink.write(&StatsEntry::new())?;
+        sink.write(&StatsEntry::new())?;
+        sink.write(&StatsEntry::new())?;
+        sink.flush()?;
+
+        let last_request = received.iter().nth(MAX_ATTEMPTS as usize).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&last_request.body)?;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,638 @@
+mod aggregate;
+#[cfg(feature = "binary-stream")]
+mod binary_stream;
+mod http_push;
+mod prometheus;
+#[cfg(feature = "remote-write")]
+mod remote_write;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod statsd;
+
+use crate::config::{Config, JsonlToStdoutConfig, OutputMode, StatsDirConfig};
+use crate::sinks::aggregate::Aggregate;
+use crate::sinks::http_push::HttpPushSink;
+use crate::store::{self, FsSpace, RunSummary, StatsEntry};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Something that stats entries are written to.
+pub trait StatsSink {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()>;
+
+    /// Write out anything the sink is holding on to; a no-op for sinks that don't buffer.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Total number of bytes written out so far, for sinks that write to somewhere that fills up.
+    fn bytes_written(&self) -> Option<u64> {
+        None
+    }
+
+    /// Reopen whatever the sink keeps open, e.g. a file that's been rotated away from under it;
+    /// a no-op for sinks that don't hold on to one.
+    fn reopen(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Write out the summary of the run on shutdown, next to the entries; a no-op for sinks that have
+    /// nowhere to put it, as it's logged either way.
+    fn write_run_summary(&mut self, _summary: &RunSummary) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Create the sink for the configured output mode.
+pub fn create_sink(config: &Config) -> io::Result<Box<dyn StatsSink + '_>> {
+    let sink: Box<dyn StatsSink> = match &config.output_mode {
+        OutputMode::JsonlToStdout(jsonl_config) => Box::new(StdoutSink {
+            jsonl_config,
+            bytes_written: 0,
+        }),
+        OutputMode::StatsDir(stats_dir_config) => Box::new(StatsDirSink {
+            stats_dir_config,
+            bytes_written: 0,
+            stat_fs: store::stat_fs,
+            write_entry: store::write_stats_dir_entry,
+            low_on_space: false,
+        }),
+        OutputMode::HttpPush(http_push_config) => {
+            Box::new(HttpPushSink::new(http_push_config, config.acolyte_id)?)
+        }
+        OutputMode::Prometheus(prometheus_config) => Box::new(prometheus::PrometheusSink::bind(
+            prometheus_config,
+            config.acolyte_id,
+            &config.cluster_name,
+        )?),
+        OutputMode::Statsd(statsd_config) => Box::new(statsd::StatsdSink::new(statsd_config)?),
+        #[cfg(feature = "sqlite")]
+        OutputMode::Sqlite(sqlite_config) => Box::new(sqlite::SqliteSink::open(sqlite_config)?),
+        #[cfg(feature = "remote-write")]
+        OutputMode::RemoteWrite(remote_write_config) => Box::new(
+            remote_write::RemoteWriteSink::new(remote_write_config, &config.cluster_name)?,
+        ),
+        #[cfg(feature = "binary-stream")]
+        OutputMode::BinaryStream(binary_stream_config) => {
+            Box::new(binary_stream::BinaryStreamSink::open(binary_stream_config)?)
+        }
+    };
+    let sink = match &config.output_fields {
+        Some(fields) => Box::new(FieldFilter::new(sink, fields.clone())),
+        None => sink,
+    };
+    let flush_interval = match &config.output_mode {
+        OutputMode::HttpPush(http_push_config) => http_push_config.flush_interval,
+        #[cfg(feature = "remote-write")]
+        OutputMode::RemoteWrite(remote_write_config) => remote_write_config.flush_interval,
+        _ => None,
+    };
+    let sink = Box::new(PeriodicFlush::new(
+        sink,
+        SystemClock,
+        flush_interval.unwrap_or(config.flush_interval),
+    ));
+    // outermost, so the periodic flushes don't cut windows short
+    Ok(match config.aggregate_window {
+        Some(window) => Box::new(Aggregate::new(sink, window)),
+        None => sink,
+    })
+}
+
+impl<S: StatsSink + ?Sized> StatsSink for Box<S> {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        (**self).write(entry)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        (**self).bytes_written()
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        (**self).reopen()
+    }
+
+    fn write_run_summary(&mut self, summary: &RunSummary) -> io::Result<()> {
+        (**self).write_run_summary(summary)
+    }
+}
+
+struct StdoutSink<'a> {
+    jsonl_config: &'a JsonlToStdoutConfig,
+    bytes_written: u64,
+}
+
+impl StatsSink for StdoutSink<'_> {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        self.bytes_written +=
+            store::write_jsonl_entry(&mut io::stdout().lock(), entry, self.jsonl_config)? as u64;
+        Ok(())
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes_written)
+    }
+
+    fn write_run_summary(&mut self, summary: &RunSummary) -> io::Result<()> {
+        self.bytes_written +=
+            store::write_jsonl_run_summary(&mut io::stdout().lock(), summary, self.jsonl_config)?
+                as u64;
+        Ok(())
+    }
+}
+
+struct StatsDirSink<'a> {
+    stats_dir_config: &'a StatsDirConfig,
+    bytes_written: u64,
+    stat_fs: fn(&Path) -> io::Result<FsSpace>,
+    write_entry: fn(&StatsEntry, &StatsDirConfig, usize, Option<FsSpace>) -> io::Result<usize>,
+    low_on_space: bool,
+}
+
+impl StatsDirSink<'_> {
+    /// The space left on the stats directory's filesystem, if it's being watched and can be had
+    fn fs_space(&self) -> Option<FsSpace> {
+        if !self.stats_dir_config.watch_fs_space {
+            return None;
+        }
+        match (self.stat_fs)(&self.stats_dir_config.dir) {
+            Ok(space) => Some(space),
+            // e.g. the directory isn't there yet, it's created by the write
+            Err(e) => {
+                debug!(
+                    "Failed to get the free space of {:?}: {e}",
+                    self.stats_dir_config.dir
+                );
+                None
+            }
+        }
+    }
+}
+
+impl StatsSink for StatsDirSink<'_> {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        let mut max_entries = self.stats_dir_config.max_stats_entries;
+        let fs_space = self.fs_space();
+        if let Some(space) = &fs_space {
+            max_entries = store::stats_entries_to_keep(max_entries, space);
+            // once when it gets low and once when it's better again, not for every entry in between
+            let low_on_space = max_entries < self.stats_dir_config.max_stats_entries;
+            let keeping = match self.stats_dir_config.jsonl_max_bytes {
+                Some(max_bytes) => format!(
+                    "rotating stats.jsonl at {} bytes",
+                    store::jsonl_bytes_to_keep(max_bytes, space)
+                ),
+                None => format!("keeping {max_entries} stat files"),
+            };
+            if low_on_space && !self.low_on_space {
+                warn!(
+                    "Only {} KB of {} KB free for stats, {keeping}",
+                    space.free_kb, space.total_kb
+                );
+            } else if !low_on_space && self.low_on_space {
+                info!(
+                    "{} KB of {} KB free for stats again, {keeping}",
+                    space.free_kb, space.total_kb
+                );
+            }
+            self.low_on_space = low_on_space;
+        }
+        let write = || (self.write_entry)(entry, self.stats_dir_config, max_entries, fs_space);
+        let written = match write() {
+            // the directory was deleted from under us after it was checked for, e.g. by a cleanup job;
+            // another go recreates it
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                warn!(
+                    "Stats directory {:?} disappeared while writing, recreating it",
+                    self.stats_dir_config.dir
+                );
+                write()?
+            }
+            result => result?,
+        };
+        self.bytes_written += written as u64;
+        Ok(())
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes_written)
+    }
+
+    fn write_run_summary(&mut self, summary: &RunSummary) -> io::Result<()> {
+        store::write_run_summary(summary, self.stats_dir_config)
+    }
+}
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Flush the wrapped sink at least every `interval`, regardless of how full its buffers are.
+///
+/// The check happens whenever an entry is written, not on a timer of its own, so there's at most one flush
+/// per entry: data that could be lost on an ungraceful kill is bounded by the larger of the flush interval and
+/// the time between entries, i.e. the stat interval or the aggregate window.
+pub struct PeriodicFlush<S: StatsSink, C: Clock> {
+    inner: S,
+    clock: C,
+    interval: Duration,
+    last_flush: Instant,
+}
+
+impl<S: StatsSink, C: Clock> PeriodicFlush<S, C> {
+    pub fn new(inner: S, clock: C, interval: Duration) -> Self {
+        let last_flush = clock.now();
+        Self {
+            inner,
+            clock,
+            interval,
+            last_flush,
+        }
+    }
+}
+
+impl<S: StatsSink, C: Clock> StatsSink for PeriodicFlush<S, C> {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        self.inner.write(entry)?;
+
+        let now = self.clock.now();
+        if now.duration_since(self.last_flush) >= self.interval {
+            debug!("Flush interval of {:?} elapsed, flushing", self.interval);
+            self.last_flush = now;
+            self.inner.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = self.clock.now();
+        self.inner.flush()
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        self.inner.bytes_written()
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        self.inner.reopen()
+    }
+
+    fn write_run_summary(&mut self, summary: &RunSummary) -> io::Result<()> {
+        self.inner.write_run_summary(summary)
+    }
+}
+
+/// Pass only the chosen fields of each entry on to the wrapped sink, `time` always included.
+///
+/// Each sink is wrapped separately, so every sink can get a different set of fields.
+pub struct FieldFilter<S: StatsSink> {
+    inner: S,
+    fields: Vec<String>,
+}
+
+impl<S: StatsSink> FieldFilter<S> {
+    pub fn new(inner: S, fields: Vec<String>) -> Self {
+        Self { inner, fields }
+    }
+}
+
+impl<S: StatsSink> StatsSink for FieldFilter<S> {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        self.inner.write(&entry.with_only_fields(&self.fields)?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        self.inner.bytes_written()
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        self.inner.reopen()
+    }
+
+    fn write_run_summary(&mut self, summary: &RunSummary) -> io::Result<()> {
+        self.inner.write_run_summary(summary)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    /// A clock that only moves when told to
+    #[derive(Clone)]
+    pub struct ManualClock {
+        now: Rc<Cell<Instant>>,
+    }
+
+    impl ManualClock {
+        pub fn new() -> Self {
+            Self {
+                now: Rc::new(Cell::new(Instant::now())),
+            }
+        }
+
+        pub fn advance(&self, by: Duration) {
+            self.now.set(self.now.get() + by);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+    }
+
+    /// A sink that flushes only when it has `batch_size` entries, unless told to flush
+    struct BatchingSink {
+        batch_size: usize,
+        buffered: Vec<f64>,
+        flushed: Rc<Cell<usize>>,
+    }
+
+    impl StatsSink for BatchingSink {
+        fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+            self.buffered.push(entry.time);
+            if self.buffered.len() >= self.batch_size {
+                self.flush()?;
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed.set(self.flushed.get() + self.buffered.len());
+            self.buffered.clear();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_partial_batch_flushes_after_interval() -> io::Result<()> {
+        let clock = ManualClock::new();
+        let flushed = Rc::new(Cell::new(0));
+        let batching_sink = BatchingSink {
+            batch_size: 10,
+            buffered: vec![],
+            flushed: flushed.clone(),
+        };
+        let mut sink = PeriodicFlush::new(batching_sink, clock.clone(), Duration::from_secs(30));
+
+        sink.write(&StatsEntry::new())?;
+        clock.advance(Duration::from_secs(10));
+        sink.write(&StatsEntry::new())?;
+        assert_eq!(flushed.get(), 0); // batch isn't full and the interval hasn't elapsed
+
+        clock.advance(Duration::from_secs(20));
+        sink.write(&StatsEntry::new())?;
+        assert_eq!(flushed.get(), 3); // 30 seconds in, the partial batch gets flushed
+
+        clock.advance(Duration::from_secs(10));
+        sink.write(&StatsEntry::new())?;
+        assert_eq!(flushed.get(), 3); // and the interval starts over
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_summary_goes_through_the_wrappers() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let stats_dir_config = StatsDirConfig {
+            dir: temp_dir.path().to_path_buf(),
+            max_stats_entries: 12,
+            max_age: None,
+            file_mode: None,
+            envelope: None,
+            watch_fs_space: false,
+            compress: false,
+            jsonl_max_bytes: None,
+        };
+        let sink = StatsDirSink {
+            stats_dir_config: &stats_dir_config,
+            bytes_written: 0,
+            stat_fs: store::stat_fs,
+            write_entry: store::write_stats_dir_entry,
+            low_on_space: false,
+        };
+        let sink = FieldFilter::new(sink, vec!["cpu_usage".to_string()]);
+        let mut sink: Box<dyn StatsSink> = Box::new(Aggregate::new(sink, Duration::from_secs(60)));
+
+        sink.write_run_summary(&RunSummary::new())?;
+
+        let names: Vec<_> = std::fs::read_dir(temp_dir.path())?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<_>>()?;
+        assert_eq!(names.len(), 1);
+        assert!(names[0].to_string_lossy().starts_with("stats-summary-"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_dir_sink_counts_bytes_written() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let stats_dir_config = StatsDirConfig {
+            dir: temp_dir.path().to_path_buf(),
+            max_stats_entries: 12,
+            max_age: None,
+            file_mode: None,
+            envelope: None,
+            watch_fs_space: false,
+            compress: false,
+            jsonl_max_bytes: None,
+        };
+        let mut sink = StatsDirSink {
+            stats_dir_config: &stats_dir_config,
+            bytes_written: 0,
+            stat_fs: store::stat_fs,
+            write_entry: store::write_stats_dir_entry,
+            low_on_space: false,
+        };
+        assert_eq!(sink.bytes_written(), Some(0));
+
+        let entry = StatsEntry::new();
+        let entry_size = serde_json::to_string_pretty(&entry)?.len() as u64;
+        sink.write(&entry)?;
+        assert_eq!(sink.bytes_written(), Some(entry_size));
+
+        let mut entry = StatsEntry::new();
+        entry.time += 1.0; // a file of its own
+        entry.cpu_usage = Some(0.5);
+        let next_entry_size = serde_json::to_string_pretty(&entry)?.len() as u64;
+        sink.write(&entry)?;
+        assert_eq!(sink.bytes_written(), Some(entry_size + next_entry_size));
+        Ok(())
+    }
+
+    thread_local! {
+        static WRITE_ATTEMPTS: Cell<u32> = const { Cell::new(0) };
+    }
+
+    /// Writes like the stats dir sink does, except that the first attempt finds the directory deleted
+    /// from under it after it was checked for
+    fn write_after_dir_deleted(
+        entry: &StatsEntry,
+        stats_dir_config: &StatsDirConfig,
+        max_entries: usize,
+        fs_space: Option<FsSpace>,
+    ) -> io::Result<usize> {
+        let attempt = WRITE_ATTEMPTS.with(|attempts| {
+            attempts.set(attempts.get() + 1);
+            attempts.get()
+        });
+        if attempt == 1 {
+            std::fs::remove_dir_all(&stats_dir_config.dir)?;
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        store::write_stats_dir_entry(entry, stats_dir_config, max_entries, fs_space)
+    }
+
+    #[test]
+    fn test_stats_dir_sink_recreates_deleted_dir() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let stats_dir = temp_dir.path().join("stats");
+        std::fs::create_dir(&stats_dir)?;
+        let stats_dir_config = StatsDirConfig {
+            dir: stats_dir.clone(),
+            max_stats_entries: 12,
+            max_age: None,
+            file_mode: None,
+            envelope: None,
+            watch_fs_space: false,
+            compress: false,
+            jsonl_max_bytes: None,
+        };
+        let mut sink = StatsDirSink {
+            stats_dir_config: &stats_dir_config,
+            bytes_written: 0,
+            stat_fs: store::stat_fs,
+            write_entry: write_after_dir_deleted,
+            low_on_space: false,
+        };
+        sink.write(&StatsEntry::new())?;
+
+        assert_eq!(WRITE_ATTEMPTS.with(Cell::get), 2); // retried once
+        let files: Vec<_> = std::fs::read_dir(&stats_dir)?.collect();
+        assert_eq!(files.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_dir_sink_keeps_fewer_entries_when_low_on_space() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let stats_dir_config = StatsDirConfig {
+            dir: temp_dir.path().to_path_buf(),
+            max_stats_entries: 12,
+            max_age: None,
+            file_mode: None,
+            envelope: None,
+            watch_fs_space: true,
+            compress: false,
+            jsonl_max_bytes: None,
+        };
+        let mut sink = StatsDirSink {
+            stats_dir_config: &stats_dir_config,
+            bytes_written: 0,
+            // 2.5% free, a quarter of the way to the low space mark
+            stat_fs: |_| {
+                Ok(FsSpace {
+                    free_kb: 25,
+                    total_kb: 1000,
+                })
+            },
+            write_entry: store::write_stats_dir_entry,
+            low_on_space: false,
+        };
+        for i in 0..5 {
+            let mut entry = StatsEntry::new();
+            entry.time += i as f64;
+            sink.write(&entry)?;
+        }
+
+        let mut files: Vec<_> = std::fs::read_dir(temp_dir.path())?
+            .map(|file| file.map(|file| file.path()))
+            .collect::<io::Result<_>>()?;
+        assert_eq!(files.len(), 3);
+        files.sort();
+        let newest: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(files.last().unwrap())?)?;
+        assert_eq!(newest["stats_fs_free_kb"], 25);
+        assert_eq!(newest["stats_fs_total_kb"], 1000);
+        Ok(())
+    }
+
+    /// A sink that keeps what it's given as JSON
+    struct RecordingSink {
+        written: Rc<RefCell<Vec<serde_json::Value>>>,
+    }
+
+    impl StatsSink for RecordingSink {
+        fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+            self.written.borrow_mut().push(serde_json::to_value(entry)?);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_field_filter_per_sink() -> io::Result<()> {
+        let full = Rc::new(RefCell::new(vec![]));
+        let cpu_and_memory = Rc::new(RefCell::new(vec![]));
+        let mut sinks: Vec<Box<dyn StatsSink>> = vec![
+            Box::new(RecordingSink {
+                written: full.clone(),
+            }),
+            Box::new(FieldFilter::new(
+                RecordingSink {
+                    written: cpu_and_memory.clone(),
+                },
+                vec!["cpu_usage".to_string(), "memory_usage_kb".to_string()],
+            )),
+        ];
+
+        let mut entry = StatsEntry::new();
+        entry.time = 1708345562.5;
+        entry.node_name = Some("node-1".to_string());
+        entry.cpu_usage = Some(1.5);
+        entry.memory_usage_kb = Some(2048);
+        entry.pids_current = Some(12);
+        for sink in &mut sinks {
+            sink.write(&entry)?;
+        }
+
+        assert_eq!(
+            full.borrow()[0],
+            serde_json::json!({
+                "time": 1708345562.5,
+                "node_name": "node-1",
+                "cpu_usage": 1.5,
+                "memory_usage_kb": 2048,
+                "pids_current": 12,
+            })
+        );
+        assert_eq!(
+            cpu_and_memory.borrow()[0],
+            serde_json::json!({
+                "time": 1708345562.5,
+                "cpu_usage": 1.5,
+                "memory_usage_kb": 2048,
+            })
+        );
+        Ok(())
+    }
+}
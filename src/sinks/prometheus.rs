@@ -0,0 +1,225 @@
+use crate::config::PrometheusConfig;
+use crate::sinks::StatsSink;
+use crate::store::StatsEntry;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+/// How long a scraper gets to send its request, so a stuck one doesn't keep its thread around
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the latest stats entry on `/metrics` in the Prometheus text exposition format.
+///
+/// Every numeric field of the entry becomes an `acolyte_<field>` gauge, e.g. `acolyte_cpu_usage`,
+/// labeled with `acolyte_id` and `cluster_name`. Writing an entry only replaces what's served;
+/// the requests are handled on threads of their own, one per connection so a slow scraper can't hold
+/// up the others.
+pub struct PrometheusSink {
+    latest: Arc<Mutex<String>>,
+    labels: String,
+}
+
+impl PrometheusSink {
+    pub fn bind(
+        config: &PrometheusConfig,
+        acolyte_id: Uuid,
+        cluster_name: &str,
+    ) -> io::Result<Self> {
+        let listener = match config.host {
+            Some(host) => TcpListener::bind((host, config.port))?,
+            // `[::]` takes IPv4 scrapes too, but fails on a host with IPv6 turned off altogether
+            None => TcpListener::bind((Ipv6Addr::UNSPECIFIED, config.port))
+                .or_else(|_| TcpListener::bind((Ipv4Addr::UNSPECIFIED, config.port)))?,
+        };
+        info!(
+            "Serving Prometheus metrics on {}/metrics",
+            listener.local_addr()?
+        );
+        Self::serve(listener, acolyte_id, cluster_name)
+    }
+
+    fn serve(listener: TcpListener, acolyte_id: Uuid, cluster_name: &str) -> io::Result<Self> {
+        let latest = Arc::new(Mutex::new(String::new()));
+        let thread_latest = latest.clone();
+        thread::Builder::new()
+            .name("prometheus".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let latest = thread_latest.clone();
+                    let result = stream.and_then(|stream| {
+                        thread::Builder::new()
+                            .name("prometheus-scrape".to_string())
+                            .spawn(move || {
+                                if let Err(e) = respond(stream, &latest) {
+                                    debug!("Failed to serve a Prometheus scrape: {e}");
+                                }
+                            })
+                    });
+                    if let Err(e) = result {
+                        debug!("Failed to accept a Prometheus scrape: {e}");
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            latest,
+            labels: format!(
+                "acolyte_id=\"{}\",cluster_name=\"{}\"",
+                acolyte_id,
+                escape_label_value(cluster_name)
+            ),
+        })
+    }
+}
+
+impl StatsSink for PrometheusSink {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        let rendered = render_entry(entry, &self.labels)?;
+        *self.latest.lock().unwrap() = rendered;
+        Ok(())
+    }
+}
+
+/// Answer a single request, `/metrics` with the latest entry and anything else with a 404
+fn respond(mut stream: TcpStream, latest: &Mutex<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers don't matter, but closing with them unread would reset the connection, cutting off
+    // the response before the scraper gets it all
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+
+    // e.g. `GET /metrics HTTP/1.1`
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next(), parts.next());
+    let path = path.map(|path| path.split('?').next().unwrap_or(path));
+    let response = if method == Some("GET") && path == Some("/metrics") {
+        let body = latest.lock().unwrap().clone();
+        format!(
+            "HTTP/1.1 200 OK\r\n\
+             Content-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{body}",
+            body.len()
+        )
+    } else {
+        debug!(
+            "Unexpected Prometheus request: {:?}",
+            request_line.trim_end()
+        );
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+    stream.write_all(response.as_bytes())
+}
+
+/// Render the entry's numeric and boolean fields as gauges, `time` left out as Prometheus keeps its own
+fn render_entry(entry: &StatsEntry, labels: &str) -> io::Result<String> {
+    let mut rendered = String::new();
+    for (field, value) in entry.numeric_fields()? {
+        // writing to a String can't fail
+        let _ = writeln!(rendered, "# TYPE acolyte_{field} gauge");
+        let _ = writeln!(rendered, "acolyte_{field}{{{labels}}} {value}");
+    }
+    Ok(rendered)
+}
+
+/// Escape a label value as the text format requires: backslashes, double quotes and line feeds
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(addr: std::net::SocketAddr, path: &str) -> io::Result<String> {
+        let mut stream = TcpStream::connect(addr)?;
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    }
+
+    #[test]
+    fn test_render_entry() -> io::Result<()> {
+        let mut entry = StatsEntry::new();
+        entry.time = 1708345562.5;
+        entry.node_name = Some("node-1".to_string());
+        entry.cpu_usage = Some(1.5);
+        entry.memory_usage_kb = Some(2048);
+        entry.cpu_starved = Some(true);
+
+        let rendered = render_entry(&entry, "cluster_name=\"prod\"")?;
+        assert_eq!(
+            rendered,
+            "# TYPE acolyte_cpu_starved gauge\n\
+             acolyte_cpu_starved{cluster_name=\"prod\"} 1\n\
+             # TYPE acolyte_cpu_usage gauge\n\
+             acolyte_cpu_usage{cluster_name=\"prod\"} 1.5\n\
+             # TYPE acolyte_memory_usage_kb gauge\n\
+             acolyte_memory_usage_kb{cluster_name=\"prod\"} 2048\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_label_value() {
+        assert_eq!(escape_label_value("prod"), "prod");
+        assert_eq!(
+            escape_label_value("a \"b\" c\\d\ne"),
+            "a \\\"b\\\" c\\\\d\\ne"
+        );
+    }
+
+    #[test]
+    fn test_serves_latest_entry() -> io::Result<()> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let addr = listener.local_addr()?;
+        let acolyte_id = Uuid::new_v4();
+        let mut sink = PrometheusSink::serve(listener, acolyte_id, "prod")?;
+
+        // nothing to serve before the first entry
+        assert!(get(addr, "/metrics")?.ends_with("\r\n\r\n"));
+
+        for cpu_usage in [0.5, 1.5] {
+            let mut entry = StatsEntry::new();
+            entry.cpu_usage = Some(cpu_usage);
+            sink.write(&entry)?;
+        }
+        let response = get(addr, "/metrics")?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&format!(
+            "acolyte_cpu_usage{{acolyte_id=\"{acolyte_id}\",cluster_name=\"prod\"}} 1.5\n"
+        )));
+
+        assert!(get(addr, "/")?.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_slow_scraper_does_not_hold_up_others() -> io::Result<()> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let addr = listener.local_addr()?;
+        let _sink = PrometheusSink::serve(listener, Uuid::new_v4(), "prod")?;
+
+        // connected, but yet to send its request
+        let _slow = TcpStream::connect(addr)?;
+        let started = std::time::Instant::now();
+        assert!(get(addr, "/metrics")?.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(started.elapsed() < REQUEST_TIMEOUT);
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use crate::config::RemoteWriteConfig;
+use crate::sinks::StatsSink;
+use crate::sinks::http_push::HttpPoster;
+use crate::store::StatsEntry;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE, HeaderMap, HeaderValue};
+use std::collections::BTreeMap;
+use std::io;
+use tracing::debug;
+
+// the subset of the remote write protobuf schema we need, see
+// https://prometheus.io/docs/specs/prw/remote_write_spec/#protocol
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    #[prost(int64, tag = "2")]
+    timestamp: i64, // milliseconds since the epoch
+}
+
+/// Pushes stats entries to a Prometheus remote write endpoint.
+///
+/// Every numeric field of an entry becomes a sample of an `acolyte_<field>` series, e.g. `acolyte_cpu_usage`,
+/// labeled with the cluster, and the node and container when known.
+pub struct RemoteWriteSink {
+    poster: HttpPoster,
+    cluster_name: String,
+    batch_size: usize,
+    batch: Vec<StatsEntry>,
+}
+
+impl RemoteWriteSink {
+    pub fn new(config: &RemoteWriteConfig, cluster_name: &str) -> io::Result<Self> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-protobuf"),
+        );
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("snappy"));
+        headers.insert(
+            "X-Prometheus-Remote-Write-Version",
+            HeaderValue::from_static("0.1.0"),
+        );
+        let poster = HttpPoster::new(
+            "remote write",
+            &config.url,
+            headers,
+            &config.headers,
+            config.timeout,
+        )?;
+
+        Ok(Self {
+            poster,
+            cluster_name: cluster_name.to_string(),
+            batch_size: config.batch_size.max(1),
+            batch: vec![],
+        })
+    }
+}
+
+impl StatsSink for RemoteWriteSink {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        self.batch.push(entry.clone());
+        if self.batch.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        // like with the webhook, a batch is either delivered or dropped as a whole
+        let batch = std::mem::take(&mut self.batch);
+        debug!("Pushing a batch of {} stats entries", batch.len());
+        self.poster
+            .post(encode_write_request(&batch, &self.cluster_name)?);
+        Ok(())
+    }
+}
+
+/// Encode the entries as a snappy-compressed `WriteRequest`
+fn encode_write_request(entries: &[StatsEntry], cluster_name: &str) -> io::Result<Vec<u8>> {
+    // each series has to have its samples in time order, and may only appear once per request
+    let mut series: BTreeMap<Vec<(String, String)>, Vec<Sample>> = BTreeMap::new();
+    for entry in entries {
+        let timestamp = (entry.time * 1000.0) as i64;
+        for (field, value) in entry.numeric_fields()? {
+            series
+                .entry(series_labels(&field, entry, cluster_name))
+                .or_default()
+                .push(Sample { value, timestamp });
+        }
+    }
+
+    let write_request = WriteRequest {
+        timeseries: series
+            .into_iter()
+            .map(|(labels, samples)| TimeSeries {
+                labels: labels
+                    .into_iter()
+                    .map(|(name, value)| Label { name, value })
+                    .collect(),
+                samples,
+            })
+            .collect(),
+    };
+    snap::raw::Encoder::new()
+        .compress_vec(&prost::Message::encode_to_vec(&write_request))
+        .map_err(io::Error::other)
+}
+
+/// The labels for a field's series, sorted by name as remote write requires
+fn series_labels(field: &str, entry: &StatsEntry, cluster_name: &str) -> Vec<(String, String)> {
+    let mut labels = vec![
+        ("__name__".to_string(), format!("acolyte_{field}")),
+        ("cluster".to_string(), cluster_name.to_string()),
+    ];
+    for (name, value) in [
+        ("container", &entry.container_name),
+        ("image", &entry.container_image),
+        ("node", &entry.node_name),
+    ] {
+        if let Some(value) = value {
+            labels.push((name.to_string(), value.clone()));
+        }
+    }
+    labels.sort();
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_write_request(body: &[u8]) -> WriteRequest {
+        let decompressed = snap::raw::Decoder::new().decompress_vec(body).unwrap();
+        prost::Message::decode(decompressed.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_encode_write_request() -> io::Result<()> {
+        let mut entries = vec![StatsEntry::new(), StatsEntry::new()];
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.time = 1708345562.5 + i as f64 * 5.0;
+            entry.node_name = Some("node-1".to_string());
+            entry.cpu_usage = Some(1.5 + i as f64);
+            entry.cpu_starved = Some(i == 1);
+        }
+
+        let write_request = decode_write_request(&encode_write_request(&entries, "prod")?);
+
+        let names: Vec<&str> = write_request
+            .timeseries
+            .iter()
+            .map(|series| series.labels[0].value.as_str())
+            .collect();
+        assert_eq!(names, vec!["acolyte_cpu_starved", "acolyte_cpu_usage"]);
+
+        let cpu_usage = &write_request.timeseries[1];
+        assert_eq!(
+            cpu_usage.labels,
+            vec![
+                Label {
+                    name: "__name__".to_string(),
+                    value: "acolyte_cpu_usage".to_string()
+                },
+                Label {
+                    name: "cluster".to_string(),
+                    value: "prod".to_string()
+                },
+                Label {
+                    name: "node".to_string(),
+                    value: "node-1".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            cpu_usage.samples,
+            vec![
+                Sample {
+                    value: 1.5,
+                    timestamp: 1708345562500
+                },
+                Sample {
+                    value: 2.5,
+                    timestamp: 1708345567500
+                },
+            ]
+        );
+        let cpu_starved = &write_request.timeseries[0];
+        assert_eq!(cpu_starved.samples[0].value, 0.0);
+        assert_eq!(cpu_starved.samples[1].value, 1.0);
+        Ok(())
+    }
+}
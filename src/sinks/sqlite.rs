@@ -0,0 +1,170 @@
+use crate::config::SqliteConfig;
+use crate::sinks::StatsSink;
+use crate::store::StatsEntry;
+use rusqlite::{Connection, MAIN_DB, params};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Writes stats entries as rows of a `stats` table in an SQLite database.
+///
+/// Each row has the entry's `time` and the whole entry as JSON, so fields can be
+/// queried with e.g. `SELECT time, entry ->> 'cpu_usage' FROM stats ORDER BY time`.
+pub struct SqliteSink {
+    connection: Connection,
+    path: PathBuf,
+    max_stats_entries: usize,
+}
+
+impl SqliteSink {
+    pub fn open(config: &SqliteConfig) -> io::Result<Self> {
+        let connection = connect(&config.path)?;
+        debug!("Writing stats to SQLite database {:?}", config.path);
+        Ok(Self {
+            connection,
+            path: config.path.clone(),
+            max_stats_entries: config.max_stats_entries,
+        })
+    }
+}
+
+/// Open the database at `path`, creating it and the `stats` table if they're not there yet
+fn connect(path: &Path) -> io::Result<Connection> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let connection = Connection::open(path).map_err(sqlite_error)?;
+    // SQLite quietly falls back to read-only when it can't write the file; failing here stops
+    // acolyte at startup with an error rather than have every single write fail later
+    if connection.is_readonly(MAIN_DB).map_err(sqlite_error)? {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("SQLite database {path:?} is read-only"),
+        ));
+    }
+    connection
+        .execute_batch(
+            "CREATE TABLE IF NOT EXISTS stats (
+                id INTEGER PRIMARY KEY,
+                time REAL NOT NULL,
+                entry TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS stats_time ON stats (time);",
+        )
+        .map_err(sqlite_error)?;
+    Ok(connection)
+}
+
+impl StatsSink for SqliteSink {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        let as_json = serde_json::to_string(entry)?;
+        let transaction = self.connection.transaction().map_err(sqlite_error)?;
+        transaction
+            .execute(
+                "INSERT INTO stats (time, entry) VALUES (?1, ?2)",
+                params![entry.time, as_json],
+            )
+            .map_err(sqlite_error)?;
+        transaction
+            .execute(
+                "DELETE FROM stats WHERE id NOT IN (
+                    SELECT id FROM stats ORDER BY time DESC LIMIT ?1
+                )",
+                params![self.max_stats_entries as i64],
+            )
+            .map_err(sqlite_error)?;
+        transaction.commit().map_err(sqlite_error)
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        // a database moved away keeps the rows written so far, the next ones go to a new one at the path
+        self.connection = connect(&self.path)?;
+        Ok(())
+    }
+}
+
+fn sqlite_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_write_and_query_entries() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config = SqliteConfig {
+            path: temp_dir.path().join("stats.db"),
+            max_stats_entries: 2,
+        };
+        let mut sink = SqliteSink::open(&config)?;
+
+        for (time, cpu_usage) in [(1.0, 0.25), (2.0, 0.5), (3.0, 0.75)] {
+            let mut entry = StatsEntry::new();
+            entry.time = time;
+            entry.cpu_usage = Some(cpu_usage);
+            sink.write(&entry)?;
+        }
+
+        // only the newest entries are kept
+        let rows: Vec<(f64, f64)> = sink
+            .connection
+            .prepare("SELECT time, entry ->> 'cpu_usage' FROM stats ORDER BY time")
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect()
+            })
+            .map_err(sqlite_error)?;
+        assert_eq!(rows, vec![(2.0, 0.5), (3.0, 0.75)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reopen_after_move() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config = SqliteConfig {
+            path: temp_dir.path().join("stats.db"),
+            max_stats_entries: 2,
+        };
+        let mut sink = SqliteSink::open(&config)?;
+        sink.write(&StatsEntry::new())?;
+
+        fs::rename(&config.path, temp_dir.path().join("stats.db.1"))?;
+        sink.reopen()?;
+        sink.write(&StatsEntry::new())?;
+
+        let count: i64 = Connection::open(&config.path)
+            .and_then(|connection| {
+                connection.query_row("SELECT COUNT(*) FROM stats", [], |row| row.get(0))
+            })
+            .map_err(sqlite_error)?;
+        assert_eq!(count, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_refuses_read_only_database() -> io::Result<()> {
+        // root can write to the file whatever its permissions say
+        if unsafe { libc::geteuid() } == 0 {
+            return Ok(());
+        }
+        let temp_dir = tempfile::tempdir()?;
+        let config = SqliteConfig {
+            path: temp_dir.path().join("stats.db"),
+            max_stats_entries: 2,
+        };
+        drop(SqliteSink::open(&config)?);
+        fs::set_permissions(&config.path, fs::Permissions::from_mode(0o444))?;
+
+        let result = SqliteSink::open(&config);
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(io::ErrorKind::PermissionDenied)
+        );
+        Ok(())
+    }
+}
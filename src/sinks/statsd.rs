@@ -0,0 +1,194 @@
+use crate::config::StatsdConfig;
+use crate::sinks::StatsSink;
+use crate::store::StatsEntry;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use tracing::debug;
+
+/// Keeps a packet within the usual Ethernet MTU once the IP and UDP headers are added
+const MAX_PACKET_BYTES: usize = 1432;
+
+/// Pushes stats entries to a StatsD server over UDP as gauges, e.g. `acolyte.cpu_usage:1.5|g`.
+///
+/// Fields without a value aren't sent; many gauges share a packet, one per line.
+pub struct StatsdSink {
+    socket: Option<UdpSocket>, // bound for the address family the server was last resolved to
+    server: Option<SocketAddr>, // resolved from `addr` once, and again after failing to send
+    addr: String,
+    prefix: String,
+}
+
+impl StatsdSink {
+    pub fn new(config: &StatsdConfig) -> io::Result<Self> {
+        Ok(Self {
+            socket: None,
+            server: None,
+            addr: config.addr.clone(),
+            prefix: config.prefix.clone(),
+        })
+    }
+
+    /// A socket that can send to `server`, i.e. an IPv6 one for an IPv6 address and IPv4 for IPv4
+    fn socket_for(&mut self, server: SocketAddr) -> io::Result<&UdpSocket> {
+        if let Some(socket) = self.socket.take_if(|socket| {
+            socket
+                .local_addr()
+                .is_ok_and(|local| local.is_ipv6() == server.is_ipv6())
+        }) {
+            return Ok(self.socket.insert(socket));
+        }
+        let unspecified = match server {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        Ok(self.socket.insert(UdpSocket::bind((unspecified, 0))?))
+    }
+}
+
+impl StatsSink for StatsdSink {
+    fn write(&mut self, entry: &StatsEntry) -> io::Result<()> {
+        let packets = encode_packets(entry, &self.prefix)?;
+        let server = match self.server {
+            Some(server) => server,
+            None => *self.server.insert(resolve(&self.addr)?),
+        };
+        let socket = self.socket_for(server)?;
+        debug!("Sending {} StatsD packets to {server}", packets.len());
+        let sent = packets
+            .iter()
+            .try_for_each(|packet| socket.send_to(packet.as_bytes(), server).map(|_| ()));
+        if sent.is_err() {
+            // looked up again for the next entry, in case e.g. a restarted agent came back at another IP
+            self.server = None;
+        }
+        sent
+    }
+}
+
+/// Resolve `addr` to the first address it has; a blocking DNS lookup for a host name
+fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No address found for {addr}"),
+        )
+    })
+}
+
+/// Encode the entry's numeric fields as gauges, packed into as few packets as fit them
+fn encode_packets(entry: &StatsEntry, prefix: &str) -> io::Result<Vec<String>> {
+    let mut packets = vec![];
+    let mut packet = String::new();
+    for (field, value) in entry.numeric_fields()? {
+        // a signed value changes a gauge by that much rather than setting it, so a negative one is set
+        // from 0, in the same packet so the two arrive in order
+        let gauge = if value < 0.0 {
+            format!("{prefix}{field}:0|g\n{prefix}{field}:{value}|g")
+        } else {
+            format!("{prefix}{field}:{value}|g")
+        };
+        if !packet.is_empty() && packet.len() + 1 + gauge.len() > MAX_PACKET_BYTES {
+            packets.push(std::mem::take(&mut packet));
+        }
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(&gauge);
+    }
+    if !packet.is_empty() {
+        packets.push(packet);
+    }
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_packets() -> io::Result<()> {
+        let mut entry = StatsEntry::new();
+        entry.node_name = Some("node-1".to_string());
+        entry.cpu_usage = Some(1.5);
+        entry.memory_usage_kb = Some(2048);
+        entry.cpu_starved = Some(false);
+
+        let packets = encode_packets(&entry, "acolyte.")?;
+        assert_eq!(
+            packets,
+            vec![
+                "acolyte.cpu_starved:0|g\nacolyte.cpu_usage:1.5|g\nacolyte.memory_usage_kb:2048|g"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_packets_with_negative_value() -> io::Result<()> {
+        let mut entry = StatsEntry::new();
+        entry.oom_score_adj = Some(-998);
+
+        let packets = encode_packets(&entry, "acolyte.")?;
+        assert_eq!(
+            packets,
+            vec!["acolyte.oom_score_adj:0|g\nacolyte.oom_score_adj:-998|g"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_packets_splits_at_mtu() -> io::Result<()> {
+        let mut entry = StatsEntry::new();
+        entry.cpu_usage = Some(1.5);
+        entry.memory_usage_kb = Some(2048);
+        let long_prefix = "x".repeat(MAX_PACKET_BYTES / 2);
+
+        let packets = encode_packets(&entry, &long_prefix)?;
+        assert_eq!(packets.len(), 2);
+        assert!(
+            packets
+                .iter()
+                .all(|packet| packet.len() <= MAX_PACKET_BYTES)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sends_gauges() -> io::Result<()> {
+        let server = UdpSocket::bind(("127.0.0.1", 0))?;
+        let mut sink = StatsdSink::new(&StatsdConfig {
+            addr: server.local_addr()?.to_string(),
+            prefix: "acolyte.".to_string(),
+        })?;
+
+        let mut entry = StatsEntry::new();
+        entry.cpu_usage = Some(0.5);
+        sink.write(&entry)?;
+
+        let mut buf = [0; MAX_PACKET_BYTES];
+        let len = server.recv(&mut buf)?;
+        assert_eq!(&buf[..len], b"acolyte.cpu_usage:0.5|g");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sends_gauges_over_ipv6() -> io::Result<()> {
+        // not every host running the tests has IPv6
+        let Ok(server) = UdpSocket::bind(("::1", 0)) else {
+            return Ok(());
+        };
+        let mut sink = StatsdSink::new(&StatsdConfig {
+            addr: server.local_addr()?.to_string(),
+            prefix: "acolyte.".to_string(),
+        })?;
+
+        let mut entry = StatsEntry::new();
+        entry.cpu_usage = Some(0.5);
+        sink.write(&entry)?;
+
+        let mut buf = [0; MAX_PACKET_BYTES];
+        let len = server.recv(&mut buf)?;
+        assert_eq!(&buf[..len], b"acolyte.cpu_usage:0.5|g");
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use std::io;
+use std::mem;
+
+/// Get the number of CPUs this process is allowed to run on (`cpuset` pinning, `taskset` etc.)
+pub fn get_cpuset_cpu_count() -> io::Result<f64> {
+    // SAFETY: `cpu_set_t` is a plain bitmask, zeroed is a valid (empty) value for it,
+    // and `sched_getaffinity` writes at most `size_of::<cpu_set_t>()` bytes into it
+    let count = unsafe {
+        let mut cpu_set: libc::cpu_set_t = mem::zeroed();
+        if libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut cpu_set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::CPU_COUNT(&cpu_set)
+    };
+
+    if count <= 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "sched_getaffinity reported no CPUs",
+        ));
+    }
+    Ok(count as f64)
+}
+
+/// Resolve the real parallelism available when both a CFS quota and `cpuset` pinning are in play.
+///
+/// e.g. a quota of 4 cores while pinned to 2 CPUs can never use more than 2 cores, and vice versa.
+pub fn reconcile_num_cpus(quota_cores: Option<f64>, cpuset_count: Option<f64>) -> Option<f64> {
+    match (quota_cores, cpuset_count) {
+        (Some(quota_cores), Some(cpuset_count)) => Some(quota_cores.min(cpuset_count)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_cpuset_cpu_count() {
+        // whatever machine we're on, we should be allowed to run on at least one CPU
+        assert!(get_cpuset_cpu_count().unwrap() >= 1.0);
+    }
+
+    #[test]
+    fn test_reconcile_num_cpus_picks_minimum() {
+        assert_eq!(reconcile_num_cpus(Some(4.0), Some(2.0)), Some(2.0));
+        assert_eq!(reconcile_num_cpus(Some(1.5), Some(8.0)), Some(1.5));
+        assert_eq!(reconcile_num_cpus(Some(2.0), Some(2.0)), Some(2.0));
+    }
+
+    #[test]
+    fn test_reconcile_num_cpus_needs_both() {
+        assert_eq!(reconcile_num_cpus(Some(4.0), None), None);
+        assert_eq!(reconcile_num_cpus(None, Some(2.0)), None);
+        assert_eq!(reconcile_num_cpus(None, None), None);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use tracing::debug;
+
+const BUDDYINFO_PATH: &str = "/proc/buddyinfo";
+
+/// Get the order of the largest free block of contiguous pages in any memory zone,
+/// e.g. 10 when there's at least one free block of 2^10 pages (4 MiB with 4 KiB pages)
+pub fn get_memory_max_free_order() -> io::Result<u32> {
+    read_max_free_order(BUDDYINFO_PATH)
+}
+
+fn read_max_free_order<P: AsRef<Path>>(buddyinfo_path: P) -> io::Result<u32> {
+    let content = fs::read_to_string(buddyinfo_path)?;
+    let max_free_order = parse_max_free_order(&content).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "No free blocks found in buddyinfo")
+    })?;
+    debug!("Using /proc/buddyinfo for memory fragmentation");
+    Ok(max_free_order)
+}
+
+/// Parse `/proc/buddyinfo`, where each line has the number of free blocks of each order for one zone:
+/// `Node 0, zone   Normal   1046    527    128     36     17      5     26     40     13     16     94`
+fn parse_max_free_order(content: &str) -> Option<u32> {
+    content
+        .lines()
+        .filter_map(|line| {
+            // the counts start after the zone name
+            let (_, counts) = line.split_once("zone")?;
+            counts
+                .split_whitespace()
+                .skip(1)
+                .map(|count| count.parse::<u64>().unwrap_or(0))
+                .enumerate()
+                .filter(|(_, count)| *count > 0)
+                .map(|(order, _)| order as u32)
+                .last()
+        })
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_max_free_order() {
+        let buddyinfo = "\
+Node 0, zone      DMA      1      1      1      0      2      1      1      0      1      1      3
+Node 0, zone    DMA32      5      4      6      3      4      4      2      3      2      0      0
+Node 0, zone   Normal   1046    527    128     36     17      5      0      0      0      0      0
+";
+        // only the DMA zone has blocks of the largest order left
+        assert_eq!(parse_max_free_order(buddyinfo), Some(10));
+
+        let fragmented = "\
+Node 0, zone    DMA32      5      4      6      3      4      4      2      3      2      0      0
+Node 0, zone   Normal   1046    527    128     36     17      5      0      0      0      0      0
+Node 1, zone   Normal    913    244     19      2      0      0      0      0      0      0      0
+";
+        assert_eq!(parse_max_free_order(fragmented), Some(8));
+    }
+
+    #[test]
+    fn test_parse_max_free_order_without_free_blocks() {
+        let buddyinfo = "Node 0, zone   Normal      0      0      0      0      0      0      0      0      0      0      0\n";
+        assert_eq!(parse_max_free_order(buddyinfo), None);
+        assert_eq!(parse_max_free_order(""), None);
+    }
+
+    #[test]
+    fn test_read_max_free_order() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let buddyinfo_path = temp_dir.path().join("buddyinfo");
+        fs::write(
+            &buddyinfo_path,
+            "Node 0, zone   Normal     12      7      3      1      0      0      0      0      0      0      0\n",
+        )?;
+
+        assert_eq!(read_max_free_order(&buddyinfo_path)?, 3);
+        Ok(())
+    }
+}
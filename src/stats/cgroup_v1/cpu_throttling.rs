@@ -0,0 +1,72 @@
+use crate::stats::CpuThrottlingStats;
+use crate::stats::cgroup_v1::CgroupV1Provider;
+use crate::utils::find_stat_value;
+use std::io;
+use tracing::debug;
+
+/// Get CPU throttling counters from the cgroup v1 `cpu.stat`
+pub fn get_cpu_throttling_stats<P: CgroupV1Provider>(
+    provider: &P,
+) -> io::Result<CpuThrottlingStats> {
+    let lines = provider.get_cgroup_v1_cpu_stat()?;
+
+    let (Some(nr_periods), Some(nr_throttled)) = (
+        find_stat_value(&lines, "nr_periods"),
+        find_stat_value(&lines, "nr_throttled"),
+    ) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Could not find throttling stats in v1 cgroup/cpu.stat",
+        ));
+    };
+
+    debug!("Using cgroup v1 for CPU throttling stats");
+    Ok(CpuThrottlingStats {
+        nr_periods,
+        nr_throttled,
+        // in nanoseconds on v1
+        throttled_usec: find_stat_value(&lines, "throttled_time").map(|nanos| nanos / 1000),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v1::MockCgroupV1Provider;
+
+    #[test]
+    fn test_get_cpu_throttling_stats() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider.expect_get_cgroup_v1_cpu_stat().returning(|| {
+            Ok(vec![
+                "nr_periods 200".to_string(),
+                "nr_throttled 20".to_string(),
+                "throttled_time 123456789".to_string(),
+            ])
+        });
+
+        let throttling_stats = get_cpu_throttling_stats(&mock_provider)?;
+        assert_eq!(throttling_stats.nr_periods, 200);
+        assert_eq!(throttling_stats.nr_throttled, 20);
+        assert_eq!(throttling_stats.throttled_usec, Some(123456));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_cpu_throttling_stats_in_any_order() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider.expect_get_cgroup_v1_cpu_stat().returning(|| {
+            Ok(vec![
+                "throttled_time 123456789".to_string(),
+                "nr_throttled 20".to_string(),
+                "nr_bursts 0".to_string(),
+                "nr_periods 200".to_string(),
+            ])
+        });
+
+        let throttling_stats = get_cpu_throttling_stats(&mock_provider)?;
+        assert_eq!(throttling_stats.nr_periods, 200);
+        assert_eq!(throttling_stats.nr_throttled, 20);
+        Ok(())
+    }
+}
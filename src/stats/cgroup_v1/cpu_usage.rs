@@ -1,37 +1,32 @@
-use crate::stats::CpuUsageValue;
 use crate::stats::cgroup_v1::CgroupV1Provider;
+use crate::stats::{CpuTimeReading, CpuUsageValue};
 use std::io;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tracing::debug;
 
-/// Get normalized CPU usage from cgroup v1
+/// Get normalized CPU usage from cgroup v1, measured over `sample_interval` from now
 pub fn get_cpu_usage<P: CgroupV1Provider>(
     provider: &P,
     sample_interval: Duration,
 ) -> io::Result<CpuUsageValue> {
-    let start_time = Instant::now();
-
-    // NB: cgroup v1 reports these cpu times in nanoseconds, unlike cgroup v2's microseconds
-    let initial = get_cpu_usage_ns(provider)?;
+    let initial = get_cpu_time(provider)?;
     std::thread::sleep(sample_interval);
-    let current = get_cpu_usage_ns(provider)?;
-
-    // wall-clock time between the two readings
-    let elapsed_ns = start_time.elapsed().as_nanos() as f64;
-    if elapsed_ns <= 0.0 {
-        return Err(io::Error::other(
-            "Elapsed time between CPU measurements was zero or negative",
-        ));
-    }
+    let current = get_cpu_time(provider)?;
 
-    // CPU time consumed between the two readings
-    let delta_usage_ns = current.saturating_sub(initial) as f64;
-
-    let normalized_usage = delta_usage_ns / elapsed_ns;
+    let normalized_usage = current.usage_since(&initial).ok_or_else(|| {
+        io::Error::other("Elapsed time between CPU measurements was zero or negative")
+    })?;
     debug!("Using cgroup v1 for CPU usage");
     Ok(CpuUsageValue::FromCgroupV1(normalized_usage))
 }
 
+/// Read the cumulative CPU time from cgroup v1 to compute usage against a later reading
+pub fn get_cpu_time<P: CgroupV1Provider>(provider: &P) -> io::Result<CpuTimeReading> {
+    // NB: cgroup v1 reports these cpu times in nanoseconds, unlike cgroup v2's microseconds
+    let usage_ns = get_cpu_usage_ns(provider)?;
+    Ok(CpuTimeReading::now(Duration::from_nanos(usage_ns)))
+}
+
 fn get_cpu_usage_ns<P: CgroupV1Provider>(provider: &P) -> io::Result<u64> {
     let cpuacct_usage_text = provider.get_cgroup_v1_cpuacct_usage()?;
     match cpuacct_usage_text.trim().parse::<u64>() {
@@ -69,4 +64,23 @@ mod tests {
         let result = get_cpu_usage_ns(&mock_provider);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_cpu_usage_since_prior_reading() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_cpuacct_usage()
+            .returning(|| Ok("2500000000\n".to_string()));
+
+        let current = get_cpu_time(&mock_provider)?;
+        assert_eq!(current.cpu_time, Duration::from_millis(2500));
+
+        // half a second of CPU time over a 5 second interval
+        let previous = CpuTimeReading {
+            time: current.time - Duration::from_secs(5),
+            cpu_time: Duration::from_secs(2),
+        };
+        assert_eq!(current.usage_since(&previous), Some(0.1));
+        Ok(())
+    }
 }
@@ -0,0 +1,40 @@
+use crate::stats::cgroup_v1::CgroupV1Provider;
+use crate::stats::{parse_cpu_list_count, unsupported};
+use std::io;
+use tracing::debug;
+
+/// Get the number of CPUs the cgroup may run on from the cgroup v1 `cpuset.cpus`, e.g. `0-3,8`
+pub fn get_cpuset_cpu_count<P: CgroupV1Provider>(provider: &P) -> io::Result<f64> {
+    let cpus_text = provider
+        .get_cgroup_v1_cpuset_cpus()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => unsupported("Cpuset without the cpuset controller"),
+            _ => e,
+        })?;
+    let count = parse_cpu_list_count(&cpus_text).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid cpuset.cpus format: {cpus_text:?}"),
+        )
+    })?;
+
+    debug!("Using cgroup v1 for cpuset");
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v1::MockCgroupV1Provider;
+
+    #[test]
+    fn test_get_cpuset_cpu_count() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_cpuset_cpus()
+            .returning(|| Ok("2-3\n".to_string()));
+
+        assert_eq!(get_cpuset_cpu_count(&mock_provider)?, 2.0);
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::stats::cgroup_v1::CgroupV1Provider;
+use crate::stats::unlimited;
 use std::io;
 use tracing::debug;
 
@@ -32,7 +33,7 @@ pub fn get_memory_max_kb<P: CgroupV1Provider>(provider: &P) -> io::Result<u64> {
         )
     })?;
     if memory_limit >= get_no_limit_value() {
-        return Err(io::Error::new(
+        return Err(unlimited(
             io::ErrorKind::InvalidData,
             "`memory.limit_in_bytes` indicates no limit, cannot determine the actual memory limit",
         ));
@@ -48,17 +49,17 @@ fn get_hierarchical_memory_limit<P: CgroupV1Provider>(provider: &P) -> io::Resul
 
     for line in lines {
         if line.starts_with("hierarchical_memory_limit ") {
-            if let Some(value_str) = line.split_whitespace().nth(1) {
-                if let Ok(value) = value_str.parse::<u64>() {
-                    if value >= get_no_limit_value() {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            "hierarchical_memory_limit indicates no limit, cannot determine the actual memory limit",
-                        ));
-                    }
-
-                    return Ok(value);
+            if let Some(value_str) = line.split_whitespace().nth(1)
+                && let Ok(value) = value_str.parse::<u64>()
+            {
+                if value >= get_no_limit_value() {
+                    return Err(unlimited(
+                        io::ErrorKind::InvalidData,
+                        "hierarchical_memory_limit indicates no limit, cannot determine the actual memory limit",
+                    ));
                 }
+
+                return Ok(value);
             }
 
             return Err(io::Error::new(
@@ -74,18 +75,27 @@ fn get_hierarchical_memory_limit<P: CgroupV1Provider>(provider: &P) -> io::Resul
     ))
 }
 
-fn get_no_limit_value() -> u64 {
-    // https://unix.stackexchange.com/questions/420906/what-is-the-value-for-the-cgroups-limit-in-bytes-if-the-memory-is-not-restricted
-    // https://github.com/torvalds/linux/blob/76b6905c11fd3c6dc4562aefc3e8c4429fefae1e/include/linux/page_counter.h#L44-L48
-    // In cgroup v1, the value "9223372036854771712" indicates "no limit" on 64-bit systems.
-    // TODO: implement for 32-bit systems when needed
-    9223372036854771712
+/// The limit cgroup v1 reports for "no limit", e.g. `9223372036854771712` on 64-bit hosts
+pub fn get_no_limit_value() -> u64 {
+    // SAFETY: sysconf has no preconditions, it only returns -1 for unknown names
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    let page_size = u64::try_from(page_size).unwrap_or(4096);
+    no_limit_value(libc::c_long::MAX as u64, page_size)
+}
+
+/// The kernel's `PAGE_COUNTER_MAX` in bytes: as many whole pages as fit in a `long`.
+///
+/// https://unix.stackexchange.com/questions/420906/what-is-the-value-for-the-cgroups-limit-in-bytes-if-the-memory-is-not-restricted
+/// https://github.com/torvalds/linux/blob/76b6905c11fd3c6dc4562aefc3e8c4429fefae1e/include/linux/page_counter.h#L44-L48
+fn no_limit_value(long_max: u64, page_size: u64) -> u64 {
+    long_max / page_size * page_size
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::stats::cgroup_v1::MockCgroupV1Provider;
+    use crate::stats::is_unlimited;
 
     #[test]
     fn test_prefers_hierarchical_memory_limit() -> io::Result<()> {
@@ -189,8 +199,33 @@ mod tests {
             .expect_get_cgroup_v1_memory_limit_in_bytes()
             .returning(|| Ok("9223372036854771712\n".to_string()));
 
-        let result = get_memory_max_kb(&mock_provider);
-        assert!(result.is_err());
+        let error = get_memory_max_kb(&mock_provider).unwrap_err();
+        assert!(is_unlimited(&error));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "32")]
+    fn test_unlimited_memory_limit_on_32_bit_is_error() {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_memory_stat()
+            .returning(|| Err(io::Error::new(io::ErrorKind::NotFound, "File not found")));
+        mock_provider
+            .expect_get_cgroup_v1_memory_limit_in_bytes()
+            .returning(|| Ok("2147479552\n".to_string()));
+
+        let error = get_memory_max_kb(&mock_provider).unwrap_err();
+        assert!(is_unlimited(&error));
+    }
+
+    #[test]
+    fn test_no_limit_value() {
+        // 64-bit and 32-bit hosts with 4 KiB pages
+        assert_eq!(no_limit_value(i64::MAX as u64, 4096), 9223372036854771712);
+        assert_eq!(no_limit_value(i32::MAX as u64, 4096), 2147479552);
+        // 64 KiB pages, e.g. some arm64 kernels
+        assert_eq!(no_limit_value(i64::MAX as u64, 65536), 9223372036854710272);
+        assert_eq!(get_no_limit_value() % 4096, 0);
     }
 
     #[test]
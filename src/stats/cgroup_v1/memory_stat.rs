@@ -0,0 +1,56 @@
+use crate::stats::cgroup_v1::CgroupV1Provider;
+use crate::utils::find_stat_value;
+use std::io;
+use tracing::debug;
+
+/// Get the cumulative number of major page faults from the cgroup v1 `memory.stat`
+pub fn get_major_faults<P: CgroupV1Provider>(provider: &P) -> io::Result<u64> {
+    let lines = provider.get_cgroup_v1_memory_stat()?;
+
+    // `total_` prefixed values include the descendant cgroups like `hierarchical_memory_limit` does
+    let major_faults = find_stat_value(&lines, "total_pgmajfault")
+        .or_else(|| find_stat_value(&lines, "pgmajfault"))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Could not find pgmajfault in v1 cgroup/memory.stat",
+            )
+        })?;
+    debug!("Using cgroup v1 for major page faults");
+    Ok(major_faults)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v1::MockCgroupV1Provider;
+
+    #[test]
+    fn test_prefers_total_pgmajfault() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_memory_stat()
+            .returning(|| {
+                Ok(vec![
+                    "cache 123456".to_string(),
+                    "pgmajfault 10".to_string(),
+                    "hierarchical_memory_limit 4194304".to_string(),
+                    "total_pgmajfault 15".to_string(),
+                ])
+            });
+
+        assert_eq!(get_major_faults(&mock_provider)?, 15);
+        Ok(())
+    }
+
+    #[test]
+    fn test_falls_back_to_pgmajfault() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_memory_stat()
+            .returning(|| Ok(vec!["pgmajfault 10".to_string()]));
+
+        assert_eq!(get_major_faults(&mock_provider)?, 10);
+        Ok(())
+    }
+}
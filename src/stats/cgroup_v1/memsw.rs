@@ -0,0 +1,105 @@
+use crate::stats::cgroup_v1::CgroupV1Provider;
+use crate::stats::cgroup_v1::memory_max::get_no_limit_value;
+use crate::stats::{MemorySwapStats, unsupported};
+use std::io;
+use tracing::debug;
+
+/// Get the combined memory and swap usage and limit from the cgroup v1 `memory.memsw.*` files.
+///
+/// With swap accounting on, the combined limit is what the OOM killer goes by rather than `memory.limit_in_bytes`;
+/// with it off, the files aren't there at all.
+pub fn get_memory_swap_stats<P: CgroupV1Provider>(provider: &P) -> io::Result<MemorySwapStats> {
+    let usage_text = provider
+        .get_cgroup_v1_memsw_usage_in_bytes()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => unsupported("Memory+swap without swap accounting"),
+            _ => e,
+        })?;
+    let usage = usage_text.trim().parse::<u64>().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid memory.memsw.usage_in_bytes format: {e}"),
+        )
+    })?;
+    let limit_text = provider.get_cgroup_v1_memsw_limit_in_bytes()?;
+    let limit = limit_text.trim().parse::<u64>().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid memory.memsw.limit_in_bytes format: {e}"),
+        )
+    })?;
+    // cgroup v1 has no `max`, the stand-in for no limit is a huge number
+    let limit = Some(limit).filter(|limit| *limit < get_no_limit_value());
+
+    debug!("Using cgroup v1 for memory+swap");
+    Ok(MemorySwapStats {
+        usage_kb: usage / 1024,
+        limit_kb: limit.map(|limit| limit / 1024),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v1::MockCgroupV1Provider;
+
+    #[test]
+    fn test_combined_limit() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_memsw_usage_in_bytes()
+            .returning(|| Ok("3145728\n".to_string()));
+        mock_provider
+            .expect_get_cgroup_v1_memsw_limit_in_bytes()
+            .returning(|| Ok("4194304\n".to_string()));
+
+        let stats = get_memory_swap_stats(&mock_provider)?;
+        assert_eq!(stats.usage_kb, 3072);
+        assert_eq!(stats.limit_kb, Some(4096));
+        assert_eq!(stats.ratio(), Some(0.75));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unlimited_combined_limit() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_memsw_usage_in_bytes()
+            .returning(|| Ok("3145728\n".to_string()));
+        mock_provider
+            .expect_get_cgroup_v1_memsw_limit_in_bytes()
+            .returning(|| Ok("9223372036854771712\n".to_string()));
+
+        let stats = get_memory_swap_stats(&mock_provider)?;
+        assert_eq!(stats.limit_kb, None);
+        assert_eq!(stats.ratio(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_swap_accounting_off() {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_memsw_usage_in_bytes()
+            .returning(|| Err(io::Error::new(io::ErrorKind::NotFound, "File not found")));
+
+        let error = get_memory_swap_stats(&mock_provider).unwrap_err();
+        // not an error worth reporting, there's just nothing to read
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_unreadable_combined_limit() {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_memsw_usage_in_bytes()
+            .returning(|| Ok("3145728\n".to_string()));
+        mock_provider
+            .expect_get_cgroup_v1_memsw_limit_in_bytes()
+            .returning(|| Err(io::Error::from(io::ErrorKind::PermissionDenied)));
+
+        // not to be mistaken for no limit
+        let error = get_memory_swap_stats(&mock_provider).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
@@ -1,29 +1,48 @@
-use crate::stats::{CpuUsageValue, SystemStatsSource};
+use crate::stats::{
+    CpuThrottlingStats, CpuTimeReading, CpuUsageValue, MemorySwapStats, Metric, PidsStats,
+    SourceKind, SystemStatsSource,
+};
+mod cpu_throttling;
 mod cpu_usage;
+mod cpuset;
 mod memory_current;
 mod memory_max;
+mod memory_stat;
+mod memsw;
 mod num_cpus;
+mod oom_control;
+mod pids;
 use crate::utils::{get_path_or_croak, read_all_lines, read_first_line};
 #[cfg(test)]
 use mockall::automock;
+use std::fs;
 use std::io::{self};
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 #[derive(Default, Clone)]
 pub struct CgroupV1MountPoints {
     // base paths
     cpu: Option<PathBuf>,
     cpuacct: Option<PathBuf>,
+    cpuset: Option<PathBuf>,
     memory: Option<PathBuf>,
+    pids: Option<PathBuf>,
 
     // derived paths
     cpu_quota_path: Option<PathBuf>,
     cpu_period_path: Option<PathBuf>,
+    cpu_stat_path: Option<PathBuf>,
     cpu_usage_path: Option<PathBuf>,
+    cpuset_cpus_path: Option<PathBuf>,
     memory_usage_path: Option<PathBuf>,
     memory_limit_path: Option<PathBuf>,
     memory_stat_path: Option<PathBuf>,
+    memory_oom_control_path: Option<PathBuf>,
+    memsw_usage_path: Option<PathBuf>,
+    memsw_limit_path: Option<PathBuf>,
+    pids_current_path: Option<PathBuf>,
+    pids_max_path: Option<PathBuf>,
 }
 
 impl CgroupV1MountPoints {
@@ -43,13 +62,22 @@ impl CgroupV1MountPoints {
         &self.cpuacct
     }
 
+    pub fn cpuset(&self) -> &Option<PathBuf> {
+        &self.cpuset
+    }
+
     pub fn memory(&self) -> &Option<PathBuf> {
         &self.memory
     }
 
+    pub fn pids(&self) -> &Option<PathBuf> {
+        &self.pids
+    }
+
     pub fn set_cpu(&mut self, cpu: Option<PathBuf>) {
         self.cpu_quota_path = cpu.as_ref().map(|pb| pb.join("cpu.cfs_quota_us"));
         self.cpu_period_path = cpu.as_ref().map(|pb| pb.join("cpu.cfs_period_us"));
+        self.cpu_stat_path = cpu.as_ref().map(|pb| pb.join("cpu.stat"));
         self.cpu = cpu;
     }
 
@@ -58,12 +86,45 @@ impl CgroupV1MountPoints {
         self.cpuacct = cpuacct;
     }
 
+    pub fn set_cpuset(&mut self, cpuset: Option<PathBuf>) {
+        self.cpuset_cpus_path = cpuset.as_ref().map(|pb| pb.join("cpuset.cpus"));
+        self.cpuset = cpuset;
+    }
+
     pub fn set_memory(&mut self, memory: Option<PathBuf>) {
         self.memory_usage_path = memory.as_ref().map(|pb| pb.join("memory.usage_in_bytes"));
         self.memory_limit_path = memory.as_ref().map(|pb| pb.join("memory.limit_in_bytes"));
         self.memory_stat_path = memory.as_ref().map(|pb| pb.join("memory.stat"));
+        self.memory_oom_control_path = memory.as_ref().map(|pb| pb.join("memory.oom_control"));
+        self.memsw_usage_path = memory
+            .as_ref()
+            .map(|pb| pb.join("memory.memsw.usage_in_bytes"));
+        self.memsw_limit_path = memory
+            .as_ref()
+            .map(|pb| pb.join("memory.memsw.limit_in_bytes"));
         self.memory = memory;
     }
+
+    /// Names of the controllers we found a mount point for
+    pub fn controllers(&self) -> Vec<String> {
+        [
+            ("cpu", &self.cpu),
+            ("cpuacct", &self.cpuacct),
+            ("cpuset", &self.cpuset),
+            ("memory", &self.memory),
+            ("pids", &self.pids),
+        ]
+        .into_iter()
+        .filter(|(_, mount_point)| mount_point.is_some())
+        .map(|(name, _)| name.to_string())
+        .collect()
+    }
+
+    pub fn set_pids(&mut self, pids: Option<PathBuf>) {
+        self.pids_current_path = pids.as_ref().map(|pb| pb.join("pids.current"));
+        self.pids_max_path = pids.as_ref().map(|pb| pb.join("pids.max"));
+        self.pids = pids;
+    }
 }
 
 pub struct CgroupV1Source<P: CgroupV1Provider> {
@@ -83,6 +144,32 @@ impl CgroupV1Source<CgroupV1FilesystemReader> {
 }
 
 impl<P: CgroupV1Provider> SystemStatsSource for CgroupV1Source<P> {
+    fn kind(&self) -> SourceKind {
+        SourceKind::CgroupV1
+    }
+
+    fn supports(&self, metric: Metric) -> bool {
+        match metric {
+            Metric::NumCpus | Metric::CpuUsage | Metric::MemoryUsage | Metric::MemoryTotal => true,
+            Metric::CpuThrottling | Metric::MajorFaults | Metric::Pids | Metric::MemorySwap => true,
+            Metric::Cpuset | Metric::OomKills => true,
+            Metric::CpuBurst
+            | Metric::DiskIoLatency
+            | Metric::SchedulerCounters
+            | Metric::SwapActivity
+            | Metric::CpuPressure
+            | Metric::MemoryPressure
+            | Metric::IoPressure
+            | Metric::DiskThroughput
+            | Metric::SwapUsage
+            | Metric::MemoryBreakdown
+            | Metric::LoadAverage
+            | Metric::Uptime
+            | Metric::PerCoreCpuUsage
+            | Metric::NetworkThroughput => false,
+        }
+    }
+
     fn get_num_cpus(&self) -> io::Result<f64> {
         num_cpus::get_num_cpus(&self.provider)
     }
@@ -91,6 +178,18 @@ impl<P: CgroupV1Provider> SystemStatsSource for CgroupV1Source<P> {
         cpu_usage::get_cpu_usage(&self.provider, sample_interval)
     }
 
+    fn get_cpu_time(&self) -> io::Result<CpuTimeReading> {
+        cpu_usage::get_cpu_time(&self.provider)
+    }
+
+    fn get_cpu_throttling_stats(&self) -> io::Result<CpuThrottlingStats> {
+        cpu_throttling::get_cpu_throttling_stats(&self.provider)
+    }
+
+    fn get_cgroup_cpuset_cpu_count(&self) -> io::Result<f64> {
+        cpuset::get_cpuset_cpu_count(&self.provider)
+    }
+
     fn get_memory_usage_kb(&self) -> io::Result<u64> {
         memory_current::get_memory_usage_kb(&self.provider)
     }
@@ -98,6 +197,30 @@ impl<P: CgroupV1Provider> SystemStatsSource for CgroupV1Source<P> {
     fn get_memory_total_kb(&self) -> io::Result<u64> {
         memory_max::get_memory_max_kb(&self.provider)
     }
+
+    fn get_oom_kill_count(&self) -> io::Result<u64> {
+        oom_control::get_oom_kill_count(&self.provider)
+    }
+
+    fn get_memory_swap_stats(&self) -> io::Result<MemorySwapStats> {
+        memsw::get_memory_swap_stats(&self.provider)
+    }
+
+    fn get_major_faults(&self) -> io::Result<u64> {
+        memory_stat::get_major_faults(&self.provider)
+    }
+
+    fn get_pids_stats(&self) -> io::Result<PidsStats> {
+        pids::get_pids_stats(&self.provider)
+    }
+
+    fn get_available_controllers(&self) -> io::Result<Vec<String>> {
+        Ok(self.provider.get_cgroup_v1_controllers())
+    }
+
+    fn get_cgroup_created_at(&self) -> io::Result<SystemTime> {
+        self.provider.get_cgroup_v1_created_at()
+    }
 }
 
 pub struct CgroupV1FilesystemReader {
@@ -112,15 +235,28 @@ impl CgroupV1FilesystemReader {
 
 #[cfg_attr(test, automock)]
 pub trait CgroupV1Provider {
+    fn get_cgroup_v1_controllers(&self) -> Vec<String>;
     fn get_cgroup_v1_cpu_cfs_quota(&self) -> io::Result<String>;
     fn get_cgroup_v1_cpu_cfs_period(&self) -> io::Result<String>;
+    fn get_cgroup_v1_cpu_stat(&self) -> io::Result<Vec<String>>;
     fn get_cgroup_v1_cpuacct_usage(&self) -> io::Result<String>;
+    fn get_cgroup_v1_cpuset_cpus(&self) -> io::Result<String>;
     fn get_cgroup_v1_memory_usage_in_bytes(&self) -> io::Result<String>;
     fn get_cgroup_v1_memory_limit_in_bytes(&self) -> io::Result<String>;
     fn get_cgroup_v1_memory_stat(&self) -> io::Result<Vec<String>>;
+    fn get_cgroup_v1_memory_oom_control(&self) -> io::Result<Vec<String>>;
+    fn get_cgroup_v1_memsw_usage_in_bytes(&self) -> io::Result<String>;
+    fn get_cgroup_v1_memsw_limit_in_bytes(&self) -> io::Result<String>;
+    fn get_cgroup_v1_pids_current(&self) -> io::Result<String>;
+    fn get_cgroup_v1_pids_max(&self) -> io::Result<String>;
+    fn get_cgroup_v1_created_at(&self) -> io::Result<SystemTime>;
 }
 
 impl CgroupV1Provider for CgroupV1FilesystemReader {
+    fn get_cgroup_v1_controllers(&self) -> Vec<String> {
+        self.mount_points.controllers()
+    }
+
     fn get_cgroup_v1_cpu_cfs_quota(&self) -> io::Result<String> {
         read_first_line(get_path_or_croak(
             &self.mount_points.cpu_quota_path,
@@ -135,6 +271,13 @@ impl CgroupV1Provider for CgroupV1FilesystemReader {
         )?)
     }
 
+    fn get_cgroup_v1_cpu_stat(&self) -> io::Result<Vec<String>> {
+        read_all_lines(get_path_or_croak(
+            &self.mount_points.cpu_stat_path,
+            "cpu.stat",
+        )?)
+    }
+
     fn get_cgroup_v1_cpuacct_usage(&self) -> io::Result<String> {
         read_first_line(get_path_or_croak(
             &self.mount_points.cpu_usage_path,
@@ -142,6 +285,13 @@ impl CgroupV1Provider for CgroupV1FilesystemReader {
         )?)
     }
 
+    fn get_cgroup_v1_cpuset_cpus(&self) -> io::Result<String> {
+        read_first_line(get_path_or_croak(
+            &self.mount_points.cpuset_cpus_path,
+            "cpuset.cpus",
+        )?)
+    }
+
     fn get_cgroup_v1_memory_usage_in_bytes(&self) -> io::Result<String> {
         read_first_line(get_path_or_croak(
             &self.mount_points.memory_usage_path,
@@ -162,4 +312,44 @@ impl CgroupV1Provider for CgroupV1FilesystemReader {
             "memory.stat",
         )?)
     }
+
+    fn get_cgroup_v1_memory_oom_control(&self) -> io::Result<Vec<String>> {
+        read_all_lines(get_path_or_croak(
+            &self.mount_points.memory_oom_control_path,
+            "memory.oom_control",
+        )?)
+    }
+
+    fn get_cgroup_v1_memsw_usage_in_bytes(&self) -> io::Result<String> {
+        read_first_line(get_path_or_croak(
+            &self.mount_points.memsw_usage_path,
+            "memory.memsw.usage_in_bytes",
+        )?)
+    }
+
+    fn get_cgroup_v1_memsw_limit_in_bytes(&self) -> io::Result<String> {
+        read_first_line(get_path_or_croak(
+            &self.mount_points.memsw_limit_path,
+            "memory.memsw.limit_in_bytes",
+        )?)
+    }
+
+    fn get_cgroup_v1_pids_current(&self) -> io::Result<String> {
+        read_first_line(get_path_or_croak(
+            &self.mount_points.pids_current_path,
+            "pids.current",
+        )?)
+    }
+
+    fn get_cgroup_v1_pids_max(&self) -> io::Result<String> {
+        read_first_line(get_path_or_croak(
+            &self.mount_points.pids_max_path,
+            "pids.max",
+        )?)
+    }
+
+    fn get_cgroup_v1_created_at(&self) -> io::Result<SystemTime> {
+        // every controller has its own directory for the cgroup, the cpu one is as good as any
+        fs::metadata(get_path_or_croak(&self.mount_points.cpu, "cpu cgroup")?)?.modified()
+    }
 }
@@ -1,4 +1,5 @@
 use crate::stats::cgroup_v1::CgroupV1Provider;
+use crate::stats::unlimited;
 use std::io;
 use tracing::debug;
 
@@ -20,7 +21,7 @@ pub fn get_num_cpus<P: CgroupV1Provider>(provider: &P) -> io::Result<f64> {
         )
     })?;
     if quota <= 0 {
-        return Err(io::Error::new(
+        return Err(unlimited(
             io::ErrorKind::InvalidData,
             "cpu.cfs_quota_us is zero or less (unlimited), cannot determine the actual CPU count",
         ));
@@ -48,6 +49,7 @@ pub fn get_num_cpus<P: CgroupV1Provider>(provider: &P) -> io::Result<f64> {
 mod tests {
     use super::*;
     use crate::stats::cgroup_v1::MockCgroupV1Provider;
+    use crate::stats::is_unlimited;
 
     #[test]
     fn test_normal() -> io::Result<()> {
@@ -89,8 +91,8 @@ mod tests {
             .expect_get_cgroup_v1_cpu_cfs_period()
             .returning(|| Ok("100000\n".to_string()));
 
-        let result = get_num_cpus(&mock_provider);
-        assert!(result.is_err());
+        let error = get_num_cpus(&mock_provider).unwrap_err();
+        assert!(is_unlimited(&error));
     }
 
     #[test]
@@ -0,0 +1,55 @@
+use crate::stats::cgroup_v1::CgroupV1Provider;
+use crate::stats::unsupported;
+use crate::utils::find_stat_value;
+use std::io;
+use tracing::debug;
+
+/// Get the cumulative number of processes the OOM killer has killed in the cgroup from the cgroup v1
+/// `memory.oom_control`, which only has the count on kernels from 4.13 on
+pub fn get_oom_kill_count<P: CgroupV1Provider>(provider: &P) -> io::Result<u64> {
+    let lines = provider.get_cgroup_v1_memory_oom_control()?;
+
+    let oom_kills = find_stat_value(&lines, "oom_kill")
+        .ok_or_else(|| unsupported("OOM kill count on this kernel"))?;
+    debug!("Using cgroup v1 for OOM kills");
+    Ok(oom_kills)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v1::MockCgroupV1Provider;
+
+    #[test]
+    fn test_get_oom_kill_count() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_memory_oom_control()
+            .returning(|| {
+                Ok(vec![
+                    "oom_kill_disable 0".to_string(),
+                    "under_oom 0".to_string(),
+                    "oom_kill 1".to_string(),
+                ])
+            });
+
+        assert_eq!(get_oom_kill_count(&mock_provider)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_oom_kill_count_on_older_kernels() {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_memory_oom_control()
+            .returning(|| {
+                Ok(vec![
+                    "oom_kill_disable 0".to_string(),
+                    "under_oom 0".to_string(),
+                ])
+            });
+
+        let error = get_oom_kill_count(&mock_provider).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,44 @@
+use crate::stats::cgroup_v1::CgroupV1Provider;
+use crate::stats::{PidsStats, parse_pids_max};
+use std::io;
+use tracing::debug;
+
+/// Get the number of processes and the process limit from the cgroup v1 `pids` controller
+pub fn get_pids_stats<P: CgroupV1Provider>(provider: &P) -> io::Result<PidsStats> {
+    let current_text = provider.get_cgroup_v1_pids_current()?;
+    let current = current_text.trim().parse::<u64>().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid pids.current format: {e}"),
+        )
+    })?;
+    let max = provider
+        .get_cgroup_v1_pids_max()
+        .ok()
+        .and_then(|max_text| parse_pids_max(&max_text));
+
+    debug!("Using cgroup v1 for pids");
+    Ok(PidsStats { current, max })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v1::MockCgroupV1Provider;
+
+    #[test]
+    fn test_get_pids_stats() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV1Provider::new();
+        mock_provider
+            .expect_get_cgroup_v1_pids_current()
+            .returning(|| Ok("10\n".to_string()));
+        mock_provider
+            .expect_get_cgroup_v1_pids_max()
+            .returning(|| Ok("max\n".to_string()));
+
+        let pids_stats = get_pids_stats(&mock_provider)?;
+        assert_eq!(pids_stats.current, 10);
+        assert_eq!(pids_stats.max, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use std::io;
+
+/// Get the controllers enabled for the cgroup from `cgroup.controllers`
+pub fn get_available_controllers<P: CgroupV2Provider>(provider: &P) -> io::Result<Vec<String>> {
+    // space separated, e.g. `cpuset cpu io memory hugetlb pids rdma misc`; empty if none are enabled
+    let controllers = provider.get_cgroup_v2_controllers()?;
+    Ok(controllers
+        .split_whitespace()
+        .map(|controller| controller.to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::{CgroupV2FilesystemReader, MockCgroupV2Provider};
+    use std::fs;
+
+    #[test]
+    fn test_get_available_controllers_from_file() -> io::Result<()> {
+        let cgroup = tempfile::tempdir()?;
+        fs::write(
+            cgroup.path().join("cgroup.controllers"),
+            "cpuset cpu io memory hugetlb pids rdma misc\n",
+        )?;
+        let reader = CgroupV2FilesystemReader::new(cgroup.path().to_path_buf());
+
+        let controllers = get_available_controllers(&reader)?;
+        assert_eq!(
+            controllers,
+            vec![
+                "cpuset", "cpu", "io", "memory", "hugetlb", "pids", "rdma", "misc"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_available_controllers_when_none_enabled() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_controllers()
+            .returning(|| Ok("".to_string()));
+
+        assert!(get_available_controllers(&mock_provider)?.is_empty());
+        Ok(())
+    }
+}
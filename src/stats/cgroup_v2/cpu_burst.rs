@@ -0,0 +1,70 @@
+use crate::stats::CpuBurstStats;
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::utils::find_stat_value;
+use std::io;
+use tracing::debug;
+
+/// Get CPU burst counters from the cgroup v2 `cpu.stat`
+pub fn get_cpu_burst_stats<P: CgroupV2Provider>(provider: &P) -> io::Result<CpuBurstStats> {
+    let lines = provider.get_cgroup_v2_cpu_stat()?;
+
+    // `nr_bursts` and `burst_usec` only show up on kernels with CFS burst support (5.14+),
+    // older kernels simply don't have the lines so we report them as not found
+    let Some(nr_bursts) = find_stat_value(&lines, "nr_bursts") else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Could not find nr_bursts in v2 cgroup/cpu.stat",
+        ));
+    };
+    let burst_usec = find_stat_value(&lines, "burst_usec");
+
+    debug!("Using cgroup v2 for CPU burst stats");
+    Ok(CpuBurstStats {
+        nr_bursts,
+        burst_usec,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::MockCgroupV2Provider;
+
+    #[test]
+    fn test_get_cpu_burst_stats() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider.expect_get_cgroup_v2_cpu_stat().returning(|| {
+            Ok(vec![
+                "usage_usec 1000000".to_string(),
+                "user_usec 800000".to_string(),
+                "system_usec 200000".to_string(),
+                "nr_periods 100".to_string(),
+                "nr_throttled 5".to_string(),
+                "throttled_usec 25000".to_string(),
+                "nr_bursts 12".to_string(),
+                "burst_usec 34000".to_string(),
+            ])
+        });
+
+        let burst_stats = get_cpu_burst_stats(&mock_provider)?;
+        assert_eq!(burst_stats.nr_bursts, 12);
+        assert_eq!(burst_stats.burst_usec, Some(34000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_cpu_burst_stats_without_burst_support() {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider.expect_get_cgroup_v2_cpu_stat().returning(|| {
+            Ok(vec![
+                "usage_usec 1000000".to_string(),
+                "user_usec 800000".to_string(),
+                "system_usec 200000".to_string(),
+            ])
+        });
+
+        let result = get_cpu_burst_stats(&mock_provider);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::stats::CpuThrottlingStats;
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::utils::find_stat_value;
+use std::io;
+use tracing::debug;
+
+/// Get CPU throttling counters from the cgroup v2 `cpu.stat`
+pub fn get_cpu_throttling_stats<P: CgroupV2Provider>(
+    provider: &P,
+) -> io::Result<CpuThrottlingStats> {
+    let lines = provider.get_cgroup_v2_cpu_stat()?;
+
+    // the throttling lines are only there when the `cpu` controller is enabled for the cgroup,
+    // `cpu.stat` itself always exists with just the usage lines
+    let (Some(nr_periods), Some(nr_throttled)) = (
+        find_stat_value(&lines, "nr_periods"),
+        find_stat_value(&lines, "nr_throttled"),
+    ) else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Could not find throttling stats in v2 cgroup/cpu.stat",
+        ));
+    };
+
+    debug!("Using cgroup v2 for CPU throttling stats");
+    Ok(CpuThrottlingStats {
+        nr_periods,
+        nr_throttled,
+        throttled_usec: find_stat_value(&lines, "throttled_usec"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::MockCgroupV2Provider;
+
+    #[test]
+    fn test_get_cpu_throttling_stats() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider.expect_get_cgroup_v2_cpu_stat().returning(|| {
+            Ok(vec![
+                "usage_usec 1000000".to_string(),
+                "user_usec 800000".to_string(),
+                "system_usec 200000".to_string(),
+                "nr_periods 100".to_string(),
+                "nr_throttled 5".to_string(),
+                "throttled_usec 25000".to_string(),
+            ])
+        });
+
+        let throttling_stats = get_cpu_throttling_stats(&mock_provider)?;
+        assert_eq!(throttling_stats.nr_periods, 100);
+        assert_eq!(throttling_stats.nr_throttled, 5);
+        assert_eq!(throttling_stats.throttled_usec, Some(25000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_cpu_throttling_stats_without_cpu_controller() {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_cpu_stat()
+            .returning(|| Ok(vec!["usage_usec 1000000".to_string()]));
+
+        let result = get_cpu_throttling_stats(&mock_provider);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
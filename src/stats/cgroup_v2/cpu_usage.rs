@@ -1,56 +1,46 @@
-use crate::stats::CpuUsageValue;
 use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::stats::{CpuTimeReading, CpuUsageValue};
+use crate::utils::find_stat_value;
 use std::io;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tracing::{debug, warn};
 
-/// Get normalized CPU usage from cgroup v2
+/// Get normalized CPU usage from cgroup v2, measured over `sample_interval` from now
 pub fn get_cpu_usage<P: CgroupV2Provider>(
     provider: &P,
     sample_interval: Duration,
 ) -> io::Result<CpuUsageValue> {
-    let start_time = Instant::now();
-
-    let initial = get_cpu_usage_usec(provider)?;
+    let initial = get_cpu_time(provider)?;
     std::thread::sleep(sample_interval);
-    let current = get_cpu_usage_usec(provider)?;
-
-    // wall-clock time between the two readings
-    let elapsed_usec = start_time.elapsed().as_micros() as f64;
-    if elapsed_usec <= 0.0 {
-        warn!("Elapsed time is zero or negative");
-        return Ok(CpuUsageValue::FromCgroupV2(0.0));
-    }
-
-    // CPU time consumed between the two readings
-    let delta_usage_usec = current.saturating_sub(initial) as f64;
+    let current = get_cpu_time(provider)?;
 
     // Values from cgroup v2 are combined usage _time_ across all CPUs without idle times available,
     // so it's already the "normalized usage" we are familiar with:
     // - If a process used 100ms of CPU time in 100ms of real time, that is 1.0.
     // - If a process used 75ms of 2 CPUs in 100ms of real time, that is 1.5, but note that it's cumulative so cgroup reports 150ms
-    let normalized_cpu_usage = delta_usage_usec / elapsed_usec;
+    let normalized_cpu_usage = current.usage_since(&initial).unwrap_or_else(|| {
+        warn!("Elapsed time is zero or negative");
+        0.0
+    });
     debug!("Using cgroup v2 for CPU usage");
     Ok(CpuUsageValue::FromCgroupV2(normalized_cpu_usage))
 }
 
+/// Read the cumulative CPU time from cgroup v2 to compute usage against a later reading
+pub fn get_cpu_time<P: CgroupV2Provider>(provider: &P) -> io::Result<CpuTimeReading> {
+    let usage_usec = get_cpu_usage_usec(provider)?;
+    Ok(CpuTimeReading::now(Duration::from_micros(usage_usec)))
+}
+
 fn get_cpu_usage_usec<P: CgroupV2Provider>(provider: &P) -> io::Result<u64> {
     let lines = provider.get_cgroup_v2_cpu_stat()?;
 
-    for line in lines {
-        if line.starts_with("usage_usec") {
-            if let Some(value_str) = line.split_whitespace().nth(1) {
-                if let Ok(value) = value_str.parse::<u64>() {
-                    return Ok(value);
-                }
-            }
-        }
-    }
-
-    Err(io::Error::new(
-        io::ErrorKind::InvalidData,
-        "Could not find usage_usec in v2 cgroup/cpu.stat",
-    ))
+    find_stat_value(&lines, "usage_usec").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Could not find usage_usec in v2 cgroup/cpu.stat",
+        )
+    })
 }
 
 #[cfg(test)]
@@ -73,4 +63,23 @@ mod tests {
         assert!(microseconds.is_ok());
         assert_eq!(microseconds.unwrap(), 1000000);
     }
+
+    #[test]
+    fn test_cpu_usage_since_prior_reading() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_cpu_stat()
+            .returning(|| Ok(vec!["usage_usec 8000000".to_string()]));
+
+        let current = get_cpu_time(&mock_provider)?;
+        assert_eq!(current.cpu_time, Duration::from_secs(8));
+
+        // 3 seconds of CPU time over a 2 second interval
+        let previous = CpuTimeReading {
+            time: current.time - Duration::from_secs(2),
+            cpu_time: Duration::from_secs(5),
+        };
+        assert_eq!(current.usage_since(&previous), Some(1.5));
+        Ok(())
+    }
 }
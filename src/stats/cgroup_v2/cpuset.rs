@@ -0,0 +1,53 @@
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::stats::{parse_cpu_list_count, unsupported};
+use std::io;
+use tracing::debug;
+
+/// Get the number of CPUs the cgroup may run on from the cgroup v2 `cpuset.cpus.effective`, e.g. `0-3,8`.
+///
+/// It's not there when the `cpuset` controller isn't enabled for the cgroup.
+pub fn get_cpuset_cpu_count<P: CgroupV2Provider>(provider: &P) -> io::Result<f64> {
+    let cpus_text = provider
+        .get_cgroup_v2_cpuset_cpus_effective()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => unsupported("Cpuset without the cpuset controller"),
+            _ => e,
+        })?;
+    let count = parse_cpu_list_count(&cpus_text).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid cpuset.cpus.effective format: {cpus_text:?}"),
+        )
+    })?;
+
+    debug!("Using cgroup v2 for cpuset");
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::MockCgroupV2Provider;
+
+    #[test]
+    fn test_get_cpuset_cpu_count() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_cpuset_cpus_effective()
+            .returning(|| Ok("0-3,8\n".to_string()));
+
+        assert_eq!(get_cpuset_cpu_count(&mock_provider)?, 5.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_cpuset_cpu_count_without_cpuset_controller() {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_cpuset_cpus_effective()
+            .returning(|| Err(io::Error::from(io::ErrorKind::NotFound)));
+
+        let error = get_cpuset_cpu_count(&mock_provider).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+}
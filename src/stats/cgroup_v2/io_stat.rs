@@ -0,0 +1,116 @@
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use std::io;
+use tracing::debug;
+
+/// A single device line from the cgroup v2 `io.stat`
+///
+/// e.g. `259:0 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0`
+#[derive(Debug, PartialEq)]
+pub struct IoDeviceStat {
+    pub device: String,
+    fields: Vec<(String, String)>,
+}
+
+impl IoDeviceStat {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Parse the device lines of `io.stat`, keeping every `key=value` pair present.
+///
+/// The set of keys depends on the kernel version and which io controllers (`io.latency`, `io.cost`)
+/// are enabled, so nothing here assumes a particular key is there or where it is on the line.
+pub fn parse_io_stat(lines: &[String]) -> Vec<IoDeviceStat> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let device = parts.next()?.to_string();
+            let fields = parts
+                .filter_map(|part| part.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            Some(IoDeviceStat { device, fields })
+        })
+        .collect()
+}
+
+/// Get the average IO latency across devices from the cgroup v2 `io.stat`
+pub fn get_disk_io_latency_ms<P: CgroupV2Provider>(provider: &P) -> io::Result<f64> {
+    let lines = provider.get_cgroup_v2_io_stat()?;
+    let devices = parse_io_stat(&lines);
+
+    // `avg_lat` (in microseconds) is only reported when the `io.latency` controller is
+    // configured for the device, so it's perfectly normal for none of the devices to have it
+    let latencies_usec: Vec<f64> = devices
+        .iter()
+        .filter_map(|device| device.get("avg_lat"))
+        .filter_map(|value| value.parse::<f64>().ok())
+        .collect();
+
+    if latencies_usec.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No latency fields found in v2 cgroup/io.stat",
+        ));
+    }
+
+    let avg_latency_usec = latencies_usec.iter().sum::<f64>() / latencies_usec.len() as f64;
+    debug!("Using cgroup v2 for disk IO latency");
+    Ok(avg_latency_usec / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::MockCgroupV2Provider;
+
+    #[test]
+    fn test_parse_io_stat() {
+        let lines = vec![
+            "259:0 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0".to_string(),
+            "".to_string(),
+        ];
+
+        let devices = parse_io_stat(&lines);
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].device, "259:0");
+        assert_eq!(devices[0].get("rbytes"), Some("1459200"));
+        assert_eq!(devices[0].get("wios"), Some("353"));
+        assert_eq!(devices[0].get("avg_lat"), None);
+    }
+
+    #[test]
+    fn test_get_disk_io_latency_ms_with_extended_fields() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider.expect_get_cgroup_v2_io_stat().returning(|| {
+            Ok(vec![
+                "8:16 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0 depth=1 avg_lat=4000 win=100".to_string(),
+                "8:0 rbytes=90430464 wbytes=299008000 rios=8950 wios=1252 dbytes=0 dios=0 depth=8 avg_lat=2000 win=100".to_string(),
+                "253:0 rbytes=0 wbytes=0 rios=0 wios=0 dbytes=0 dios=0".to_string(),
+            ])
+        });
+
+        let latency_ms = get_disk_io_latency_ms(&mock_provider)?;
+        assert_eq!(latency_ms, 3.0); // (4000 + 2000) / 2 usec
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_disk_io_latency_ms_without_latency_fields() {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider.expect_get_cgroup_v2_io_stat().returning(|| {
+            Ok(vec![
+                "259:0 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0"
+                    .to_string(),
+            ])
+        });
+
+        let result = get_disk_io_latency_ms(&mock_provider);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use crate::stats::DiskBytes;
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::stats::cgroup_v2::io_stat::parse_io_stat;
+use std::io;
+use tracing::debug;
+
+/// Get the bytes read and written so far by device from the cgroup v2 `io.stat`; the throughput is the rate
+/// between consecutive readings
+pub fn get_disk_bytes<P: CgroupV2Provider>(provider: &P) -> io::Result<DiskBytes> {
+    let lines = provider.get_cgroup_v2_io_stat()?;
+    let disk_bytes = parse_io_stat(&lines)
+        .into_iter()
+        .map(|device| {
+            let bytes = |key| device.get(key).and_then(|value| value.parse::<u64>().ok());
+            let read_write = (bytes("rbytes").unwrap_or(0), bytes("wbytes").unwrap_or(0));
+            (device.device, read_write)
+        })
+        .collect();
+
+    debug!("Using cgroup v2 for disk throughput");
+    Ok(disk_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::SummedCounterRates;
+    use crate::stats::cgroup_v2::MockCgroupV2Provider;
+    use mockall::Sequence;
+    use std::time::{Duration, Instant};
+
+    fn io_stat_provider(readings: Vec<Vec<&'static str>>) -> MockCgroupV2Provider {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        let mut sequence = Sequence::new();
+        for lines in readings {
+            mock_provider
+                .expect_get_cgroup_v2_io_stat()
+                .times(1)
+                .in_sequence(&mut sequence)
+                .returning(move || Ok(lines.iter().map(|line| line.to_string()).collect()));
+        }
+        mock_provider
+    }
+
+    #[test]
+    fn test_get_disk_bytes() -> io::Result<()> {
+        let mock_provider = io_stat_provider(vec![vec![
+            "8:0 rbytes=1024 wbytes=2048 rios=1 wios=1 dbytes=0 dios=0",
+            "8:16 rbytes=4096 wbytes=0 rios=1 wios=0 dbytes=0 dios=0",
+            // no byte counters to go by, counted as nothing
+            "253:0 rios=1 wios=1",
+        ]]);
+
+        let disk_bytes = get_disk_bytes(&mock_provider)?;
+        assert_eq!(disk_bytes.len(), 3);
+        assert_eq!(disk_bytes["8:0"], (1024, 2048));
+        assert_eq!(disk_bytes["8:16"], (4096, 0));
+        assert_eq!(disk_bytes["253:0"], (0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_throughput_between() -> io::Result<()> {
+        let mock_provider = io_stat_provider(vec![
+            vec![
+                "8:0 rbytes=1024 wbytes=0",
+                "8:16 rbytes=0 wbytes=4096",
+                "253:0 rbytes=10240 wbytes=0",
+            ],
+            // 253:0 went away and 259:0 showed up; 8:16's counters went backwards
+            vec![
+                "8:0 rbytes=3072 wbytes=2048",
+                "8:16 rbytes=0 wbytes=0",
+                "259:0 rbytes=5120 wbytes=0",
+            ],
+        ]);
+        let start = Instant::now();
+        let mut rates = SummedCounterRates::new();
+
+        assert_eq!(rates.update(start, get_disk_bytes(&mock_provider)?), None);
+        let rates = rates.update(
+            start + Duration::from_secs(2),
+            get_disk_bytes(&mock_provider)?,
+        );
+        assert_eq!(rates, Some((1024.0, 1024.0)));
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::stats::unsupported;
+use crate::utils::find_stat_value;
+use std::io;
+use tracing::debug;
+
+/// Get the cumulative number of processes the OOM killer has killed in the cgroup from the cgroup v2 `memory.events`
+pub fn get_oom_kill_count<P: CgroupV2Provider>(provider: &P) -> io::Result<u64> {
+    let lines = provider.get_cgroup_v2_memory_events()?;
+
+    // `oom_kill` only came with Linux 4.13
+    let oom_kills = find_stat_value(&lines, "oom_kill")
+        .ok_or_else(|| unsupported("OOM kills without oom_kill in memory.events"))?;
+    debug!("Using cgroup v2 for OOM kills");
+    Ok(oom_kills)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::MockCgroupV2Provider;
+
+    #[test]
+    fn test_get_oom_kill_count() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_events()
+            .returning(|| {
+                Ok(vec![
+                    "low 0".to_string(),
+                    "high 0".to_string(),
+                    "max 12".to_string(),
+                    "oom 3".to_string(),
+                    "oom_kill 2".to_string(),
+                    "oom_group_kill 0".to_string(),
+                ])
+            });
+
+        assert_eq!(get_oom_kill_count(&mock_provider)?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_oom_kill_count_without_oom_kill() {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_events()
+            .returning(|| Ok(vec!["oom 3".to_string()]));
+
+        let e = get_oom_kill_count(&mock_provider).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::stats::unlimited;
 use std::io;
 use tracing::debug;
 
@@ -7,7 +8,7 @@ pub fn get_memory_max_kb<P: CgroupV2Provider>(provider: &P) -> io::Result<u64> {
     let memory_max_text = provider.get_cgroup_v2_memory_max()?;
 
     if memory_max_text.trim() == "max" {
-        return Err(io::Error::new(
+        return Err(unlimited(
             io::ErrorKind::InvalidData,
             "`memory.max` contains 'max' (unlimited), cannot determine the actual memory limit",
         ));
@@ -29,6 +30,7 @@ pub fn get_memory_max_kb<P: CgroupV2Provider>(provider: &P) -> io::Result<u64> {
 mod tests {
     use super::*;
     use crate::stats::cgroup_v2::MockCgroupV2Provider;
+    use crate::stats::is_unlimited;
 
     #[test]
     fn test_get_memory_max_kb_normal() -> io::Result<()> {
@@ -49,9 +51,9 @@ mod tests {
             .expect_get_cgroup_v2_memory_max()
             .returning(|| Ok("max".to_string()));
 
-        let result = get_memory_max_kb(&mock_provider);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("unlimited"));
+        let error = get_memory_max_kb(&mock_provider).unwrap_err();
+        assert!(error.to_string().contains("unlimited"));
+        assert!(is_unlimited(&error));
     }
 
     #[test]
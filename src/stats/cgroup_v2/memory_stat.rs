@@ -0,0 +1,96 @@
+use crate::stats::MemoryBreakdown;
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::utils::find_stat_value;
+use std::io;
+use tracing::debug;
+
+/// Get the cumulative number of major page faults from the cgroup v2 `memory.stat`
+pub fn get_major_faults<P: CgroupV2Provider>(provider: &P) -> io::Result<u64> {
+    let lines = provider.get_cgroup_v2_memory_stat()?;
+
+    let major_faults = find_stat_value(&lines, "pgmajfault").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Could not find pgmajfault in v2 cgroup/memory.stat",
+        )
+    })?;
+    debug!("Using cgroup v2 for major page faults");
+    Ok(major_faults)
+}
+
+/// Get the anonymous memory (heap, stacks etc.) and the page cache making up `memory.current`
+/// from the cgroup v2 `memory.stat`, which has them in bytes
+pub fn get_memory_breakdown<P: CgroupV2Provider>(provider: &P) -> io::Result<MemoryBreakdown> {
+    let lines = provider.get_cgroup_v2_memory_stat()?;
+
+    let find = |key| {
+        find_stat_value(&lines, key).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Could not find {key} in v2 cgroup/memory.stat"),
+            )
+        })
+    };
+    let breakdown = MemoryBreakdown {
+        anon_kb: find("anon")? / 1024,
+        file_kb: find("file")? / 1024,
+    };
+    debug!("Using cgroup v2 for the memory breakdown");
+    Ok(breakdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::MockCgroupV2Provider;
+
+    #[test]
+    fn test_get_major_faults() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_stat()
+            .returning(|| {
+                Ok(vec![
+                    "anon 1048576".to_string(),
+                    "file 2097152".to_string(),
+                    "pgfault 123456".to_string(),
+                    "pgmajfault 789".to_string(),
+                    "pgscan 0".to_string(),
+                    "pgsteal 0".to_string(),
+                ])
+            });
+
+        assert_eq!(get_major_faults(&mock_provider)?, 789);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_memory_breakdown() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_stat()
+            .returning(|| {
+                Ok(vec![
+                    "anon 1048576".to_string(),
+                    "file 2097152".to_string(),
+                    "kernel 65536".to_string(),
+                    "file_mapped 4096".to_string(),
+                ])
+            });
+
+        let breakdown = get_memory_breakdown(&mock_provider)?;
+        assert_eq!(breakdown.anon_kb, 1024);
+        assert_eq!(breakdown.file_kb, 2048);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_major_faults_missing() {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_stat()
+            .returning(|| Ok(vec!["anon 1048576".to_string()]));
+
+        assert!(get_major_faults(&mock_provider).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::stats::{SwapUsage, unsupported};
+use std::io;
+use tracing::debug;
+
+/// Get the swap used by the cgroup and its swap limit from the cgroup v2 `memory.swap.current` and `memory.swap.max`.
+///
+/// Unlike cgroup v1's `memory.memsw.*`, these are swap alone; they're not there on kernels without swap accounting.
+pub fn get_swap_usage<P: CgroupV2Provider>(provider: &P) -> io::Result<SwapUsage> {
+    let current_text =
+        provider
+            .get_cgroup_v2_memory_swap_current()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => unsupported("Swap without swap accounting"),
+                _ => e,
+            })?;
+    let current = current_text.trim().parse::<u64>().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid memory.swap.current format: {e}"),
+        )
+    })?;
+    let max_text = provider.get_cgroup_v2_memory_swap_max()?;
+    // `max` for no limit
+    let max = match max_text.trim() {
+        "max" => None,
+        max => Some(max.parse::<u64>().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid memory.swap.max format: {e}"),
+            )
+        })?),
+    };
+
+    debug!("Using cgroup v2 for swap");
+    Ok(SwapUsage {
+        usage_kb: current / 1024,
+        limit_kb: max.map(|max| max / 1024),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::MockCgroupV2Provider;
+
+    #[test]
+    fn test_get_swap_usage() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_swap_current()
+            .returning(|| Ok("1048576\n".to_string()));
+        mock_provider
+            .expect_get_cgroup_v2_memory_swap_max()
+            .returning(|| Ok("4194304\n".to_string()));
+
+        let swap = get_swap_usage(&mock_provider)?;
+        assert_eq!(swap.usage_kb, 1024);
+        assert_eq!(swap.limit_kb, Some(4096));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_swap_usage_unlimited() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_swap_current()
+            .returning(|| Ok("0\n".to_string()));
+        mock_provider
+            .expect_get_cgroup_v2_memory_swap_max()
+            .returning(|| Ok("max\n".to_string()));
+
+        assert_eq!(get_swap_usage(&mock_provider)?.limit_kb, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_swap_usage_without_swap_accounting() {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_swap_current()
+            .returning(|| Err(io::Error::from(io::ErrorKind::NotFound)));
+
+        let error = get_swap_usage(&mock_provider).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_get_swap_usage_with_unreadable_limit() {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_swap_current()
+            .returning(|| Ok("0\n".to_string()));
+        mock_provider
+            .expect_get_cgroup_v2_memory_swap_max()
+            .returning(|| Err(io::Error::from(io::ErrorKind::PermissionDenied)));
+
+        // not to be mistaken for `max`
+        let error = get_swap_usage(&mock_provider).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
@@ -1,33 +1,89 @@
-use crate::stats::{CpuUsageValue, SystemStatsSource};
+use crate::stats::{
+    CpuBurstStats, CpuThrottlingStats, CpuTimeReading, CpuUsageValue, DiskBytes, MemoryBreakdown,
+    MemoryPressure, Metric, PidsStats, SourceKind, SwapUsage, SystemStatsSource, unlimited,
+};
+mod controllers;
+mod cpu_burst;
+mod cpu_throttling;
 mod cpu_usage;
+mod cpuset;
+mod io_stat;
+mod io_usage;
 mod memory_current;
+mod memory_events;
 mod memory_max;
+mod memory_stat;
+mod memory_swap;
 mod num_cpus;
+mod pids;
+mod pressure;
 use crate::utils::{read_all_lines, read_first_line};
 #[cfg(test)]
 use mockall::automock;
+use std::fs;
 use std::io::{self};
-use std::path::PathBuf;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::info;
 
 pub struct CgroupV2Source<P: CgroupV2Provider> {
     provider: P,
+    // the root cgroup has no `cpu.max` or `memory.max` as it's always unlimited
+    is_root: bool,
 }
 
 impl<P: CgroupV2Provider> CgroupV2Source<P> {
-    fn new(provider: P) -> Self {
-        Self { provider }
+    pub(crate) fn new(provider: P, is_root: bool) -> Self {
+        Self { provider, is_root }
     }
 }
 
 impl CgroupV2Source<CgroupV2FilesystemReader> {
     pub fn with_filesystem_reader_at(path: PathBuf) -> Self {
-        Self::new(CgroupV2FilesystemReader::new(path))
+        let is_root = is_root_cgroup(&path);
+        if is_root {
+            info!(
+                "Running in the root cgroup (effectively unlimited), using host CPU and memory limits"
+            );
+        }
+        Self::new(CgroupV2FilesystemReader::new(path), is_root)
     }
 }
 
+/// Whether the cgroup directory is the root of the hierarchy.
+///
+/// Every cgroup except the root has a `cgroup.type`, including the one a cgroup namespace presents
+/// as `/`, so this tells a namespaced container apart from really being in the root.
+fn is_root_cgroup(path: &Path) -> bool {
+    path.is_dir() && !path.join("cgroup.type").exists()
+}
+
 impl<P: CgroupV2Provider> SystemStatsSource for CgroupV2Source<P> {
+    fn kind(&self) -> SourceKind {
+        SourceKind::CgroupV2
+    }
+
+    fn supports(&self, metric: Metric) -> bool {
+        match metric {
+            Metric::NumCpus | Metric::MemoryTotal => !self.is_root,
+            Metric::SchedulerCounters
+            | Metric::SwapActivity
+            | Metric::MemorySwap
+            | Metric::LoadAverage
+            | Metric::Uptime
+            | Metric::PerCoreCpuUsage
+            | Metric::NetworkThroughput => false,
+            _ => true,
+        }
+    }
+
     fn get_num_cpus(&self) -> io::Result<f64> {
+        if self.is_root {
+            return Err(unlimited(
+                io::ErrorKind::Unsupported,
+                "CPU limit in the root cgroup not supported (unlimited)",
+            ));
+        }
         num_cpus::get_num_cpus(&self.provider)
     }
 
@@ -35,34 +91,134 @@ impl<P: CgroupV2Provider> SystemStatsSource for CgroupV2Source<P> {
         cpu_usage::get_cpu_usage(&self.provider, sample_interval)
     }
 
+    fn get_cpu_time(&self) -> io::Result<CpuTimeReading> {
+        cpu_usage::get_cpu_time(&self.provider)
+    }
+
+    fn get_cgroup_cpuset_cpu_count(&self) -> io::Result<f64> {
+        cpuset::get_cpuset_cpu_count(&self.provider)
+    }
+
     fn get_memory_usage_kb(&self) -> io::Result<u64> {
         memory_current::get_memory_current_kb(&self.provider)
     }
 
     fn get_memory_total_kb(&self) -> io::Result<u64> {
+        if self.is_root {
+            return Err(unlimited(
+                io::ErrorKind::Unsupported,
+                "Memory limit in the root cgroup not supported (unlimited)",
+            ));
+        }
         memory_max::get_memory_max_kb(&self.provider)
     }
+
+    fn get_cpu_burst_stats(&self) -> io::Result<CpuBurstStats> {
+        cpu_burst::get_cpu_burst_stats(&self.provider)
+    }
+
+    fn get_cpu_throttling_stats(&self) -> io::Result<CpuThrottlingStats> {
+        cpu_throttling::get_cpu_throttling_stats(&self.provider)
+    }
+
+    fn get_cpu_pressure_some_avg10(&self) -> io::Result<f64> {
+        pressure::get_cpu_pressure_some_avg10(&self.provider)
+    }
+
+    fn get_oom_kill_count(&self) -> io::Result<u64> {
+        memory_events::get_oom_kill_count(&self.provider)
+    }
+
+    fn get_memory_breakdown(&self) -> io::Result<MemoryBreakdown> {
+        memory_stat::get_memory_breakdown(&self.provider)
+    }
+
+    fn get_swap_usage(&self) -> io::Result<SwapUsage> {
+        memory_swap::get_swap_usage(&self.provider)
+    }
+
+    fn get_memory_pressure(&self) -> io::Result<MemoryPressure> {
+        pressure::get_memory_pressure(&self.provider)
+    }
+
+    fn get_io_pressure_full_avg10(&self) -> io::Result<f64> {
+        pressure::get_io_pressure_full_avg10(&self.provider)
+    }
+
+    fn get_disk_bytes(&self) -> io::Result<DiskBytes> {
+        io_usage::get_disk_bytes(&self.provider)
+    }
+
+    fn get_disk_io_latency_ms(&self) -> io::Result<f64> {
+        io_stat::get_disk_io_latency_ms(&self.provider)
+    }
+
+    fn get_major_faults(&self) -> io::Result<u64> {
+        memory_stat::get_major_faults(&self.provider)
+    }
+
+    fn get_pids_stats(&self) -> io::Result<PidsStats> {
+        pids::get_pids_stats(&self.provider)
+    }
+
+    fn get_available_controllers(&self) -> io::Result<Vec<String>> {
+        controllers::get_available_controllers(&self.provider)
+    }
+
+    fn get_cgroup_created_at(&self) -> io::Result<SystemTime> {
+        self.provider.get_cgroup_v2_created_at()
+    }
 }
 
 pub struct CgroupV2FilesystemReader {
+    cgroup_path: PathBuf,
+    controllers_path: PathBuf,
     cpu_max_path: PathBuf,
+    cpu_pressure_path: PathBuf,
     cpu_stat_path: PathBuf,
+    cpuset_cpus_effective_path: PathBuf,
+    io_pressure_path: PathBuf,
+    io_stat_path: PathBuf,
     mem_current_path: PathBuf,
+    mem_events_path: PathBuf,
     mem_max_path: PathBuf,
+    mem_pressure_path: PathBuf,
+    mem_stat_path: PathBuf,
+    mem_swap_current_path: PathBuf,
+    mem_swap_max_path: PathBuf,
+    pids_current_path: PathBuf,
+    pids_max_path: PathBuf,
 }
 
 impl CgroupV2FilesystemReader {
     fn new(cgroup_v2_path: PathBuf) -> Self {
         Self {
+            controllers_path: cgroup_v2_path.join("cgroup.controllers"),
             cpu_max_path: cgroup_v2_path.join("cpu.max"),
+            cpu_pressure_path: cgroup_v2_path.join("cpu.pressure"),
             cpu_stat_path: cgroup_v2_path.join("cpu.stat"),
+            cpuset_cpus_effective_path: cgroup_v2_path.join("cpuset.cpus.effective"),
+            io_pressure_path: cgroup_v2_path.join("io.pressure"),
+            io_stat_path: cgroup_v2_path.join("io.stat"),
             mem_current_path: cgroup_v2_path.join("memory.current"),
+            mem_events_path: cgroup_v2_path.join("memory.events"),
             mem_max_path: cgroup_v2_path.join("memory.max"),
+            mem_pressure_path: cgroup_v2_path.join("memory.pressure"),
+            mem_stat_path: cgroup_v2_path.join("memory.stat"),
+            mem_swap_current_path: cgroup_v2_path.join("memory.swap.current"),
+            mem_swap_max_path: cgroup_v2_path.join("memory.swap.max"),
+            pids_current_path: cgroup_v2_path.join("pids.current"),
+            pids_max_path: cgroup_v2_path.join("pids.max"),
+            cgroup_path: cgroup_v2_path,
         }
     }
 }
 
 impl CgroupV2Provider for CgroupV2FilesystemReader {
+    fn get_cgroup_v2_controllers(&self) -> io::Result<String> {
+        read_first_line(&self.controllers_path)
+    }
+
     fn get_cgroup_v2_cpu_stat(&self) -> io::Result<Vec<String>> {
         read_all_lines(&self.cpu_stat_path)
     }
@@ -71,19 +227,171 @@ impl CgroupV2Provider for CgroupV2FilesystemReader {
         read_first_line(&self.cpu_max_path)
     }
 
+    fn get_cgroup_v2_cpu_pressure(&self) -> io::Result<Vec<String>> {
+        read_all_lines(&self.cpu_pressure_path)
+    }
+
+    fn get_cgroup_v2_cpuset_cpus_effective(&self) -> io::Result<String> {
+        read_first_line(&self.cpuset_cpus_effective_path)
+    }
+
     fn get_cgroup_v2_memory_current(&self) -> io::Result<String> {
         read_first_line(&self.mem_current_path)
     }
 
+    fn get_cgroup_v2_memory_events(&self) -> io::Result<Vec<String>> {
+        read_all_lines(&self.mem_events_path)
+    }
+
     fn get_cgroup_v2_memory_max(&self) -> io::Result<String> {
         read_first_line(&self.mem_max_path)
     }
+
+    fn get_cgroup_v2_memory_pressure(&self) -> io::Result<Vec<String>> {
+        read_all_lines(&self.mem_pressure_path)
+    }
+
+    fn get_cgroup_v2_io_pressure(&self) -> io::Result<Vec<String>> {
+        read_all_lines(&self.io_pressure_path)
+    }
+
+    fn get_cgroup_v2_io_stat(&self) -> io::Result<Vec<String>> {
+        read_all_lines(&self.io_stat_path)
+    }
+
+    fn get_cgroup_v2_memory_stat(&self) -> io::Result<Vec<String>> {
+        read_all_lines(&self.mem_stat_path)
+    }
+
+    fn get_cgroup_v2_memory_swap_current(&self) -> io::Result<String> {
+        read_first_line(&self.mem_swap_current_path)
+    }
+
+    fn get_cgroup_v2_memory_swap_max(&self) -> io::Result<String> {
+        read_first_line(&self.mem_swap_max_path)
+    }
+
+    fn get_cgroup_v2_pids_current(&self) -> io::Result<String> {
+        read_first_line(&self.pids_current_path)
+    }
+
+    fn get_cgroup_v2_pids_max(&self) -> io::Result<String> {
+        read_first_line(&self.pids_max_path)
+    }
+
+    fn get_cgroup_v2_created_at(&self) -> io::Result<SystemTime> {
+        // the kernel doesn't record a creation time, but the directory's mtime is set when it's made
+        fs::metadata(&self.cgroup_path)?.modified()
+    }
 }
 
 #[cfg_attr(test, automock)]
 pub trait CgroupV2Provider {
+    fn get_cgroup_v2_controllers(&self) -> io::Result<String>;
     fn get_cgroup_v2_cpu_stat(&self) -> io::Result<Vec<String>>;
     fn get_cgroup_v2_cpu_max(&self) -> io::Result<String>;
+    fn get_cgroup_v2_cpu_pressure(&self) -> io::Result<Vec<String>>;
+    fn get_cgroup_v2_cpuset_cpus_effective(&self) -> io::Result<String>;
     fn get_cgroup_v2_memory_current(&self) -> io::Result<String>;
+    fn get_cgroup_v2_memory_events(&self) -> io::Result<Vec<String>>;
     fn get_cgroup_v2_memory_max(&self) -> io::Result<String>;
+    fn get_cgroup_v2_memory_pressure(&self) -> io::Result<Vec<String>>;
+    fn get_cgroup_v2_io_pressure(&self) -> io::Result<Vec<String>>;
+    fn get_cgroup_v2_io_stat(&self) -> io::Result<Vec<String>>;
+    fn get_cgroup_v2_memory_stat(&self) -> io::Result<Vec<String>>;
+    fn get_cgroup_v2_memory_swap_current(&self) -> io::Result<String>;
+    fn get_cgroup_v2_memory_swap_max(&self) -> io::Result<String>;
+    fn get_cgroup_v2_pids_current(&self) -> io::Result<String>;
+    fn get_cgroup_v2_pids_max(&self) -> io::Result<String>;
+    fn get_cgroup_v2_created_at(&self) -> io::Result<SystemTime>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::is_unlimited;
+    use std::fs::File;
+
+    #[test]
+    fn test_is_root_cgroup() -> io::Result<()> {
+        let root = tempfile::tempdir()?;
+        fs::write(root.path().join("cgroup.controllers"), "cpu memory\n")?;
+        let child = root.path().join("kubepods.slice");
+        fs::create_dir(&child)?;
+        fs::write(child.join("cgroup.type"), "domain\n")?;
+
+        assert!(is_root_cgroup(root.path()));
+        assert!(!is_root_cgroup(&child));
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_cgroup_skips_limits() {
+        // no expectations set, so any read of the limit files would panic
+        let source = CgroupV2Source::new(MockCgroupV2Provider::new(), true);
+
+        let num_cpus = source.get_num_cpus().unwrap_err();
+        assert_eq!(num_cpus.kind(), io::ErrorKind::Unsupported);
+        assert!(is_unlimited(&num_cpus));
+        let memory_total = source.get_memory_total_kb().unwrap_err();
+        assert_eq!(memory_total.kind(), io::ErrorKind::Unsupported);
+        assert!(is_unlimited(&memory_total));
+    }
+
+    #[test]
+    fn test_cgroup_created_at_from_directory_mtime() -> io::Result<()> {
+        let cgroup = tempfile::tempdir()?;
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        File::open(cgroup.path())?.set_modified(an_hour_ago)?;
+        let source = CgroupV2Source::with_filesystem_reader_at(cgroup.path().to_path_buf());
+
+        let created_at = source.get_cgroup_created_at()?;
+        // filesystems may store the time at a coarser precision than it was set at
+        let difference = created_at
+            .duration_since(an_hour_ago)
+            .unwrap_or_else(|e| e.duration());
+        assert!(difference < Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stat_fields_in_any_order() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        // the fields have come and gone across kernel versions, so no parser may rely on their order
+        mock_provider.expect_get_cgroup_v2_cpu_stat().returning(|| {
+            Ok(vec![
+                "burst_usec 34000".to_string(),
+                "nr_throttled 5".to_string(),
+                "core_sched.force_idle_usec 0".to_string(),
+                "system_usec 200000".to_string(),
+                "nr_bursts 12".to_string(),
+                "throttled_usec 25000".to_string(),
+                "usage_usec 1000000".to_string(),
+                "nr_periods 100".to_string(),
+                "user_usec 800000".to_string(),
+            ])
+        });
+        mock_provider
+            .expect_get_cgroup_v2_memory_stat()
+            .returning(|| {
+                Ok(vec![
+                    "pgmajfault 789".to_string(),
+                    "pgscan 0".to_string(),
+                    "anon 1048576".to_string(),
+                    "pgfault 123456".to_string(),
+                ])
+            });
+        let source = CgroupV2Source::new(mock_provider, false);
+
+        assert_eq!(
+            source.get_cpu_time()?.cpu_time,
+            Duration::from_micros(1000000)
+        );
+        let throttling = source.get_cpu_throttling_stats()?;
+        assert_eq!((throttling.nr_periods, throttling.nr_throttled), (100, 5));
+        let burst = source.get_cpu_burst_stats()?;
+        assert_eq!((burst.nr_bursts, burst.burst_usec), (12, Some(34000)));
+        assert_eq!(source.get_major_faults()?, 789);
+        Ok(())
+    }
 }
@@ -1,4 +1,5 @@
 use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::stats::unlimited;
 use std::io;
 use tracing::debug;
 
@@ -25,7 +26,7 @@ pub fn get_num_cpus<P: CgroupV2Provider>(provider: &P) -> io::Result<f64> {
     let period_str = parts[1];
 
     if quota_str == "max" {
-        return Err(io::Error::new(
+        return Err(unlimited(
             io::ErrorKind::InvalidData,
             "`cpu.max` contains 'max' quota (unlimited), cannot determine the actual CPU count",
         ));
@@ -60,6 +61,7 @@ pub fn get_num_cpus<P: CgroupV2Provider>(provider: &P) -> io::Result<f64> {
 mod tests {
     use super::*;
     use crate::stats::cgroup_v2::MockCgroupV2Provider;
+    use crate::stats::is_unlimited;
 
     #[test]
     fn test_get_num_cpus_with_quota() {
@@ -91,9 +93,9 @@ mod tests {
             .expect_get_cgroup_v2_cpu_max()
             .returning(|| Ok("max 100000".to_string()));
 
-        let result = get_num_cpus(&mock_provider);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("unlimited"));
+        let error = get_num_cpus(&mock_provider).unwrap_err();
+        assert!(error.to_string().contains("unlimited"));
+        assert!(is_unlimited(&error));
     }
 
     #[test]
@@ -0,0 +1,96 @@
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::stats::{PidsStats, parse_pids_max, unsupported};
+use std::io;
+use tracing::debug;
+
+/// Get the number of processes and the process limit from the cgroup v2 `pids.current` and `pids.max`.
+///
+/// Neither is there when the `pids` controller isn't enabled for the cgroup.
+pub fn get_pids_stats<P: CgroupV2Provider>(provider: &P) -> io::Result<PidsStats> {
+    let current_text = provider
+        .get_cgroup_v2_pids_current()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => unsupported("Pids without the pids controller"),
+            _ => e,
+        })?;
+    let current = current_text.trim().parse::<u64>().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid pids.current format: {e}"),
+        )
+    })?;
+    // the limit is an extra; without it we still know the process count
+    let max = provider
+        .get_cgroup_v2_pids_max()
+        .ok()
+        .and_then(|max_text| parse_pids_max(&max_text));
+
+    debug!("Using cgroup v2 for pids");
+    Ok(PidsStats { current, max })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::MockCgroupV2Provider;
+
+    #[test]
+    fn test_get_pids_stats() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_pids_current()
+            .returning(|| Ok("42\n".to_string()));
+        mock_provider
+            .expect_get_cgroup_v2_pids_max()
+            .returning(|| Ok("1024\n".to_string()));
+
+        let pids_stats = get_pids_stats(&mock_provider)?;
+        assert_eq!(pids_stats.current, 42);
+        assert_eq!(pids_stats.max, Some(1024));
+        assert_eq!(pids_stats.ratio(), Some(42.0 / 1024.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_pids_stats_unlimited() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_pids_current()
+            .returning(|| Ok("42\n".to_string()));
+        mock_provider
+            .expect_get_cgroup_v2_pids_max()
+            .returning(|| Ok("max\n".to_string()));
+
+        let pids_stats = get_pids_stats(&mock_provider)?;
+        assert_eq!(pids_stats.max, None);
+        assert_eq!(pids_stats.ratio(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_pids_stats_without_pids_max() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_pids_current()
+            .returning(|| Ok("7\n".to_string()));
+        mock_provider
+            .expect_get_cgroup_v2_pids_max()
+            .returning(|| Err(io::Error::new(io::ErrorKind::NotFound, "No such file")));
+
+        let pids_stats = get_pids_stats(&mock_provider)?;
+        assert_eq!(pids_stats.current, 7);
+        assert_eq!(pids_stats.max, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_pids_stats_without_pids_controller() {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_pids_current()
+            .returning(|| Err(io::Error::from(io::ErrorKind::NotFound)));
+
+        let error = get_pids_stats(&mock_provider).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+}
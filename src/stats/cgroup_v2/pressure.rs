@@ -0,0 +1,116 @@
+use crate::stats::cgroup_v2::CgroupV2Provider;
+use crate::stats::{MemoryPressure, parse_pressure_avg10, unsupported};
+use std::io;
+use tracing::debug;
+
+/// Get the share of the last 10 seconds that some of the cgroup's tasks were waiting for CPU, in percent,
+/// from the `some` line of the cgroup v2 `cpu.pressure`.
+pub fn get_cpu_pressure_some_avg10<P: CgroupV2Provider>(provider: &P) -> io::Result<f64> {
+    let lines = provider
+        .get_cgroup_v2_cpu_pressure()
+        .map_err(|e| without_psi_unsupported(e, "CPU pressure"))?;
+    let some_avg10 = parse_pressure_avg10(&lines, "some", "cpu.pressure")?;
+
+    debug!("Using cgroup v2 for CPU pressure");
+    Ok(some_avg10)
+}
+
+/// Get the share of the last 10 seconds that some, and all, of the cgroup's tasks were stalled on memory,
+/// in percent, from the cgroup v2 `memory.pressure`.
+pub fn get_memory_pressure<P: CgroupV2Provider>(provider: &P) -> io::Result<MemoryPressure> {
+    let lines = provider
+        .get_cgroup_v2_memory_pressure()
+        .map_err(|e| without_psi_unsupported(e, "Memory pressure"))?;
+    let some_avg10 = parse_pressure_avg10(&lines, "some", "memory.pressure")?;
+    let full_avg10 = parse_pressure_avg10(&lines, "full", "memory.pressure")?;
+
+    debug!("Using cgroup v2 for memory pressure");
+    Ok(MemoryPressure {
+        some_avg10,
+        full_avg10,
+    })
+}
+
+/// Get the share of the last 10 seconds that all of the cgroup's tasks were waiting on IO, in percent,
+/// from the `full` line of the cgroup v2 `io.pressure`.
+pub fn get_io_pressure_full_avg10<P: CgroupV2Provider>(provider: &P) -> io::Result<f64> {
+    let lines = provider
+        .get_cgroup_v2_io_pressure()
+        .map_err(|e| without_psi_unsupported(e, "IO pressure"))?;
+    let full_avg10 = parse_pressure_avg10(&lines, "full", "io.pressure")?;
+
+    debug!("Using cgroup v2 for IO pressure");
+    Ok(full_avg10)
+}
+
+/// Kernels without PSI (or booted with `psi=0`) don't have the pressure files
+fn without_psi_unsupported(e: io::Error, what: &str) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::NotFound => unsupported(&format!("{what} without PSI")),
+        _ => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::MockCgroupV2Provider;
+
+    fn pressure_lines() -> Vec<String> {
+        vec![
+            "some avg10=1.50 avg60=0.80 avg300=0.20 total=12345".to_string(),
+            "full avg10=0.75 avg60=0.40 avg300=0.10 total=6789".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_get_cpu_pressure_some_avg10() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_cpu_pressure()
+            .returning(|| Ok(pressure_lines()));
+
+        assert_eq!(get_cpu_pressure_some_avg10(&mock_provider)?, 1.5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_memory_pressure() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_pressure()
+            .returning(|| Ok(pressure_lines()));
+
+        let pressure = get_memory_pressure(&mock_provider)?;
+        assert_eq!(pressure.some_avg10, 1.5);
+        assert_eq!(pressure.full_avg10, 0.75);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_io_pressure_full_avg10() -> io::Result<()> {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_io_pressure()
+            .returning(|| Ok(pressure_lines()));
+
+        assert_eq!(get_io_pressure_full_avg10(&mock_provider)?, 0.75);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pressure_without_psi() {
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_cpu_pressure()
+            .returning(|| Err(io::Error::from(io::ErrorKind::NotFound)));
+        mock_provider
+            .expect_get_cgroup_v2_memory_pressure()
+            .returning(|| Err(io::Error::from(io::ErrorKind::NotFound)));
+
+        let error = get_cpu_pressure_some_avg10(&mock_provider).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        let error = get_memory_pressure(&mock_provider).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::stats::{
+    CpuBurstStats, CpuInfo, CpuThrottlingStats, CpuTimeReading, CpuUsageValue, DiskBytes,
+    LoadAverage, MemoryBreakdown, MemoryPressure, MemorySwapStats, Metric, NetworkBytes, OomScore,
+    PidsStats, SchedulerCounters, SourceKind, SwapCounters, SwapUsage, SystemStatsSource,
+    unsupported,
+};
+use std::io;
+use std::time::{Duration, SystemTime};
+
+/// The cgroup controllers whose reads can be disabled
+pub const CONTROLLERS: [&str; 5] = ["cpu", "cpuset", "memory", "io", "pids"];
+
+/// Wraps a cgroup source to skip reading the disabled controllers' files altogether, e.g. ones that are slow or
+/// permission-gated on a node; their metrics come out as unsupported, so they're left to the next source.
+pub struct ControllerFilter {
+    inner: Box<dyn SystemStatsSource>,
+    disabled: Vec<String>,
+}
+
+impl ControllerFilter {
+    pub fn new(inner: Box<dyn SystemStatsSource>, disabled: Vec<String>) -> Self {
+        Self { inner, disabled }
+    }
+
+    fn is_enabled(&self, metric: Metric) -> bool {
+        metric
+            .controller()
+            .is_none_or(|controller| !self.disabled.iter().any(|disabled| disabled == controller))
+    }
+
+    fn read<T>(&self, metric: Metric, read: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+        if !self.is_enabled(metric) {
+            return Err(unsupported(&format!(
+                "Reading {} disabled by configuration",
+                metric.name()
+            )));
+        }
+        read()
+    }
+}
+
+impl SystemStatsSource for ControllerFilter {
+    fn kind(&self) -> SourceKind {
+        self.inner.kind()
+    }
+
+    fn supports(&self, metric: Metric) -> bool {
+        self.is_enabled(metric) && self.inner.supports(metric)
+    }
+
+    fn get_num_cpus(&self) -> io::Result<f64> {
+        self.read(Metric::NumCpus, || self.inner.get_num_cpus())
+    }
+
+    fn get_cpu_usage(&self, sample_interval: Duration) -> io::Result<CpuUsageValue> {
+        self.read(Metric::CpuUsage, || {
+            self.inner.get_cpu_usage(sample_interval)
+        })
+    }
+
+    fn get_memory_usage_kb(&self) -> io::Result<u64> {
+        self.read(Metric::MemoryUsage, || self.inner.get_memory_usage_kb())
+    }
+
+    fn get_memory_total_kb(&self) -> io::Result<u64> {
+        self.read(Metric::MemoryTotal, || self.inner.get_memory_total_kb())
+    }
+
+    fn get_per_core_cpu_usage(&self, sample_interval: Duration) -> io::Result<Vec<f64>> {
+        self.read(Metric::PerCoreCpuUsage, || {
+            self.inner.get_per_core_cpu_usage(sample_interval)
+        })
+    }
+
+    fn get_cpu_time(&self) -> io::Result<CpuTimeReading> {
+        self.read(Metric::CpuUsage, || self.inner.get_cpu_time())
+    }
+
+    fn get_cpu_burst_stats(&self) -> io::Result<CpuBurstStats> {
+        self.read(Metric::CpuBurst, || self.inner.get_cpu_burst_stats())
+    }
+
+    fn get_cpu_throttling_stats(&self) -> io::Result<CpuThrottlingStats> {
+        self.read(Metric::CpuThrottling, || {
+            self.inner.get_cpu_throttling_stats()
+        })
+    }
+
+    fn get_network_bytes(&self) -> io::Result<NetworkBytes> {
+        self.read(Metric::NetworkThroughput, || self.inner.get_network_bytes())
+    }
+
+    fn get_disk_io_latency_ms(&self) -> io::Result<f64> {
+        self.read(Metric::DiskIoLatency, || {
+            self.inner.get_disk_io_latency_ms()
+        })
+    }
+
+    fn get_cpu_pressure_some_avg10(&self) -> io::Result<f64> {
+        self.read(Metric::CpuPressure, || {
+            self.inner.get_cpu_pressure_some_avg10()
+        })
+    }
+
+    fn get_cgroup_cpuset_cpu_count(&self) -> io::Result<f64> {
+        self.read(Metric::Cpuset, || self.inner.get_cgroup_cpuset_cpu_count())
+    }
+
+    fn get_oom_kill_count(&self) -> io::Result<u64> {
+        self.read(Metric::OomKills, || self.inner.get_oom_kill_count())
+    }
+
+    fn get_memory_breakdown(&self) -> io::Result<MemoryBreakdown> {
+        self.read(Metric::MemoryBreakdown, || {
+            self.inner.get_memory_breakdown()
+        })
+    }
+
+    fn get_swap_usage(&self) -> io::Result<SwapUsage> {
+        self.read(Metric::SwapUsage, || self.inner.get_swap_usage())
+    }
+
+    fn get_memory_pressure(&self) -> io::Result<MemoryPressure> {
+        self.read(Metric::MemoryPressure, || self.inner.get_memory_pressure())
+    }
+
+    fn get_io_pressure_full_avg10(&self) -> io::Result<f64> {
+        self.read(Metric::IoPressure, || {
+            self.inner.get_io_pressure_full_avg10()
+        })
+    }
+
+    fn get_disk_bytes(&self) -> io::Result<DiskBytes> {
+        self.read(Metric::DiskThroughput, || self.inner.get_disk_bytes())
+    }
+
+    fn get_memory_swap_stats(&self) -> io::Result<MemorySwapStats> {
+        self.read(Metric::MemorySwap, || self.inner.get_memory_swap_stats())
+    }
+
+    fn get_major_faults(&self) -> io::Result<u64> {
+        self.read(Metric::MajorFaults, || self.inner.get_major_faults())
+    }
+
+    fn get_pids_stats(&self) -> io::Result<PidsStats> {
+        self.read(Metric::Pids, || self.inner.get_pids_stats())
+    }
+
+    fn get_load_average(&self) -> io::Result<LoadAverage> {
+        self.read(Metric::LoadAverage, || self.inner.get_load_average())
+    }
+
+    fn get_uptime_secs(&self) -> io::Result<f64> {
+        self.read(Metric::Uptime, || self.inner.get_uptime_secs())
+    }
+
+    fn get_scheduler_counters(&self) -> io::Result<SchedulerCounters> {
+        self.read(Metric::SchedulerCounters, || {
+            self.inner.get_scheduler_counters()
+        })
+    }
+
+    fn get_swap_counters(&self) -> io::Result<SwapCounters> {
+        self.read(Metric::SwapActivity, || self.inner.get_swap_counters())
+    }
+
+    fn get_available_controllers(&self) -> io::Result<Vec<String>> {
+        self.inner.get_available_controllers()
+    }
+
+    fn get_cpu_info(&self) -> io::Result<CpuInfo> {
+        self.inner.get_cpu_info()
+    }
+
+    fn get_oom_score(&self, pid: u32) -> io::Result<OomScore> {
+        self.inner.get_oom_score(pid)
+    }
+
+    fn get_cgroup_created_at(&self) -> io::Result<SystemTime> {
+        self.inner.get_cgroup_created_at()
+    }
+
+    fn get_thread_count(&self, pid: u32) -> io::Result<u64> {
+        self.inner.get_thread_count(pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::cgroup_v2::{CgroupV2Source, MockCgroupV2Provider};
+
+    #[test]
+    fn test_disabled_controllers_are_not_read() -> io::Result<()> {
+        // no expectations for io.stat or pids.*, so reading them fails the test
+        let mut mock_provider = MockCgroupV2Provider::new();
+        mock_provider
+            .expect_get_cgroup_v2_memory_current()
+            .times(1)
+            .returning(|| Ok("1048576".to_string()));
+        let source = ControllerFilter::new(
+            Box::new(CgroupV2Source::new(mock_provider, false)),
+            vec!["io".to_string(), "pids".to_string()],
+        );
+
+        assert_eq!(source.get_memory_usage_kb()?, 1024);
+        let e = source.get_disk_io_latency_ms().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+        let e = source.get_pids_stats().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+
+        assert!(source.supports(Metric::MemoryUsage));
+        assert!(!source.supports(Metric::DiskIoLatency));
+        assert!(!source.supports(Metric::Pids));
+        Ok(())
+    }
+}
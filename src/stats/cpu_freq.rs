@@ -0,0 +1,91 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use tracing::debug;
+
+const CPU_SYSFS_DIR: &str = "/sys/devices/system/cpu";
+
+/// Get the average current clock speed across all CPUs in MHz
+pub fn get_cpu_freq_mhz() -> io::Result<f64> {
+    get_avg_cpu_freq_mhz(CPU_SYSFS_DIR)
+}
+
+/// Average the `cpu*/cpufreq/scaling_cur_freq` values (in kHz) under the given sysfs directory
+fn get_avg_cpu_freq_mhz<P: AsRef<Path>>(cpu_sysfs_dir: P) -> io::Result<f64> {
+    let mut freqs_khz = vec![];
+    for entry in fs::read_dir(cpu_sysfs_dir)?.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        // skip `cpufreq`, `cpuidle` etc. that sit next to the numbered CPU directories
+        let is_cpu_dir = name
+            .strip_prefix("cpu")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+        if !is_cpu_dir {
+            continue;
+        }
+        // offline CPUs and VMs without frequency scaling don't have the `cpufreq` directory at all
+        let freq_path = entry.path().join("cpufreq/scaling_cur_freq");
+        if let Ok(content) = fs::read_to_string(freq_path)
+            && let Some(freq_khz) = parse_freq_khz(&content)
+        {
+            freqs_khz.push(freq_khz);
+        }
+    }
+
+    if freqs_khz.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No cpufreq scaling_cur_freq found",
+        ));
+    }
+
+    debug!("Using sysfs cpufreq for CPU frequency");
+    let avg_freq_khz = freqs_khz.iter().sum::<f64>() / freqs_khz.len() as f64;
+    Ok(avg_freq_khz / 1000.0)
+}
+
+fn parse_freq_khz(content: &str) -> Option<f64> {
+    content.trim().parse::<u64>().ok().map(|khz| khz as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_cpu_dir(cpu_sysfs_dir: &Path, name: &str, freq_khz: Option<&str>) {
+        let cpu_dir = cpu_sysfs_dir.join(name);
+        fs::create_dir(&cpu_dir).unwrap();
+        if let Some(freq_khz) = freq_khz {
+            fs::create_dir(cpu_dir.join("cpufreq")).unwrap();
+            fs::write(cpu_dir.join("cpufreq/scaling_cur_freq"), freq_khz).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_freq_khz() {
+        assert_eq!(parse_freq_khz("2400000\n"), Some(2400000.0));
+        assert_eq!(parse_freq_khz("<unknown>\n"), None);
+    }
+
+    #[test]
+    fn test_get_avg_cpu_freq_mhz() -> io::Result<()> {
+        let cpu_sysfs_dir = tempfile::tempdir()?;
+        create_cpu_dir(cpu_sysfs_dir.path(), "cpu0", Some("3600000\n"));
+        create_cpu_dir(cpu_sysfs_dir.path(), "cpu1", Some("1200000\n"));
+        create_cpu_dir(cpu_sysfs_dir.path(), "cpu2", None); // offline
+        create_cpu_dir(cpu_sysfs_dir.path(), "cpuidle", Some("1\n"));
+
+        assert_eq!(get_avg_cpu_freq_mhz(cpu_sysfs_dir.path())?, 2400.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_avg_cpu_freq_mhz_without_cpufreq() -> io::Result<()> {
+        let cpu_sysfs_dir = tempfile::tempdir()?;
+        create_cpu_dir(cpu_sysfs_dir.path(), "cpu0", None);
+
+        let result = get_avg_cpu_freq_mhz(cpu_sysfs_dir.path());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use crate::shutdown;
+use crate::stats::{CpuUsageValue, SystemStatsSource};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Samples CPU usage over and over on a thread of its own, so the latest usage can be had without waiting
+/// for a sample window to pass.
+pub struct CpuSampler {
+    latest: Arc<Mutex<Option<(Instant, CpuUsageValue)>>>,
+    sample_interval: Duration,
+    started: Instant,
+}
+
+impl CpuSampler {
+    /// Start sampling every `sample_interval` from the first of the sources to provide CPU usage.
+    ///
+    /// The sources are made on the sampling thread, as they can't be shared with the main loop's.
+    pub fn spawn<F>(sample_interval: Duration, make_sources: F) -> io::Result<Self>
+    where
+        F: FnOnce() -> Vec<Box<dyn SystemStatsSource>> + Send + 'static,
+    {
+        let latest = Arc::new(Mutex::new(None));
+        let thread_latest = latest.clone();
+        thread::Builder::new()
+            .name("cpu-sampler".to_string())
+            .spawn(move || {
+                let sources = make_sources();
+                while !shutdown::is_shutdown_requested() {
+                    match sample(&sources, sample_interval) {
+                        Some(usage) => {
+                            *thread_latest.lock().unwrap() = Some((Instant::now(), usage));
+                        }
+                        None => {
+                            // nothing slept inside the sources, so don't spin
+                            shutdown::sleep(sample_interval);
+                        }
+                    }
+                }
+            })?;
+        Ok(Self {
+            latest,
+            sample_interval,
+            started: Instant::now(),
+        })
+    }
+
+    /// The latest CPU usage sampled, unless the sampler has stalled and it's out of date
+    pub fn latest(&self) -> Option<CpuUsageValue> {
+        let (sampled_at, usage) = (*self.latest.lock().unwrap())?;
+        if sampled_at.elapsed() > self.max_sample_age() {
+            debug!("Latest CPU usage sample is stale, ignoring it");
+            return None;
+        }
+        Some(usage)
+    }
+
+    /// Whether the sampler has yet to have had the time for its first sample, as opposed to failing to take any
+    pub fn is_starting(&self) -> bool {
+        self.started.elapsed() <= self.max_sample_age()
+    }
+
+    fn max_sample_age(&self) -> Duration {
+        // a sample is taken at least every other interval when things are working
+        self.sample_interval * 3
+    }
+}
+
+/// CPU usage over `sample_interval` from the first source to provide it
+fn sample(
+    sources: &[Box<dyn SystemStatsSource>],
+    sample_interval: Duration,
+) -> Option<CpuUsageValue> {
+    sources
+        .iter()
+        .find_map(|source| source.get_cpu_usage(sample_interval).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::{SourceKind, unsupported};
+
+    /// A source whose CPU usage climbs by one CPU with each sample
+    struct ClimbingSource {
+        samples: Mutex<f64>,
+    }
+
+    impl SystemStatsSource for ClimbingSource {
+        fn kind(&self) -> SourceKind {
+            SourceKind::CgroupV2
+        }
+        fn get_num_cpus(&self) -> io::Result<f64> {
+            Err(unsupported("Fake"))
+        }
+        fn get_cpu_usage(&self, sample_interval: Duration) -> io::Result<CpuUsageValue> {
+            thread::sleep(sample_interval);
+            let mut samples = self.samples.lock().unwrap();
+            *samples += 1.0;
+            Ok(CpuUsageValue::FromCgroupV2(*samples))
+        }
+        fn get_memory_usage_kb(&self) -> io::Result<u64> {
+            Err(unsupported("Fake"))
+        }
+        fn get_memory_total_kb(&self) -> io::Result<u64> {
+            Err(unsupported("Fake"))
+        }
+    }
+
+    fn latest_usage(sampler: &CpuSampler) -> Option<f64> {
+        sampler.latest().and_then(|usage| usage.normalize(None))
+    }
+
+    /// Wait for the sampler to have a usage above `above`, as the thread takes its time
+    fn wait_for_usage_above(sampler: &CpuSampler, above: f64) -> f64 {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            if let Some(usage) = latest_usage(sampler).filter(|&usage| usage > above) {
+                return usage;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("No CPU usage above {above} sampled in time");
+    }
+
+    #[test]
+    fn test_sampler_keeps_latest_usage() {
+        let sampler = CpuSampler::spawn(Duration::from_millis(10), || {
+            let source: Box<dyn SystemStatsSource> = Box::new(ClimbingSource {
+                samples: Mutex::new(0.0),
+            });
+            vec![source]
+        })
+        .unwrap();
+
+        // without waiting on the caller's side for a sample window
+        let first = wait_for_usage_above(&sampler, 0.0);
+        wait_for_usage_above(&sampler, first);
+    }
+
+    #[test]
+    fn test_stale_sample_is_ignored() {
+        let sampler = CpuSampler {
+            latest: Arc::new(Mutex::new(Some((
+                Instant::now() - Duration::from_secs(1),
+                CpuUsageValue::FromCgroupV2(1.5),
+            )))),
+            sample_interval: Duration::from_millis(100),
+            started: Instant::now() - Duration::from_secs(1),
+        };
+        assert!(sampler.latest().is_none());
+
+        *sampler.latest.lock().unwrap() = Some((Instant::now(), CpuUsageValue::FromCgroupV2(1.5)));
+        assert_eq!(latest_usage(&sampler), Some(1.5));
+    }
+
+    #[test]
+    fn test_is_starting() {
+        let mut sampler = CpuSampler {
+            latest: Arc::new(Mutex::new(None)),
+            sample_interval: Duration::from_millis(100),
+            started: Instant::now(),
+        };
+        assert!(sampler.is_starting());
+
+        // long enough for a sample, which it doesn't have
+        sampler.started -= Duration::from_secs(1);
+        assert!(!sampler.is_starting());
+    }
+}
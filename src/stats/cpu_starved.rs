@@ -0,0 +1,94 @@
+use crate::stats::CpuThrottlingStats;
+
+/// Flags a container as CPU-starved when it's both using (nearly) all of its CPU limit and
+/// getting throttled in a notable share of the CFS periods since the previous check.
+///
+/// High usage alone is fine (the limit is simply well sized) and occasional throttling alone is
+/// normal for bursty workloads; it's the combination that means the work is waiting on CPU.
+pub struct CpuStarvationDetector {
+    usage_threshold: f64,
+    throttled_threshold: f64,
+    previous: Option<CpuThrottlingStats>,
+}
+
+impl CpuStarvationDetector {
+    /// `usage_threshold` is the fraction of the CPU limit in use (e.g. 0.9 for 90%) and
+    /// `throttled_threshold` the fraction of elapsed CFS periods that were throttled
+    pub fn new(usage_threshold: f64, throttled_threshold: f64) -> Self {
+        Self {
+            usage_threshold,
+            throttled_threshold,
+            previous: None,
+        }
+    }
+
+    /// Record the latest throttling counters, returning whether the container looks CPU-starved.
+    ///
+    /// `cpu_usage` and `cpu_limit` are in cores. Returns `None` until there are two readings to
+    /// compare, or if the counters went backwards in between.
+    pub fn update(
+        &mut self,
+        cpu_usage: f64,
+        cpu_limit: f64,
+        throttling: CpuThrottlingStats,
+    ) -> Option<bool> {
+        let previous = self.previous.replace(throttling)?;
+        let periods = throttling.nr_periods.checked_sub(previous.nr_periods)?;
+        let throttled = throttling.nr_throttled.checked_sub(previous.nr_throttled)?;
+
+        if periods == 0 || cpu_limit <= 0.0 {
+            // no CFS periods elapsed means the container was idle (or has no quota)
+            return Some(false);
+        }
+        let usage_ratio = cpu_usage / cpu_limit;
+        let throttled_ratio = throttled as f64 / periods as f64;
+        Some(usage_ratio >= self.usage_threshold && throttled_ratio >= self.throttled_threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttling(nr_periods: u64, nr_throttled: u64) -> CpuThrottlingStats {
+        CpuThrottlingStats {
+            nr_periods,
+            nr_throttled,
+            throttled_usec: None,
+        }
+    }
+
+    #[test]
+    fn test_starved_when_busy_and_throttled() {
+        let mut detector = CpuStarvationDetector::new(0.9, 0.1);
+
+        assert_eq!(detector.update(1.9, 2.0, throttling(100, 0)), None);
+        assert_eq!(detector.update(1.9, 2.0, throttling(200, 50)), Some(true));
+    }
+
+    #[test]
+    fn test_not_starved_when_throttled_but_not_busy() {
+        let mut detector = CpuStarvationDetector::new(0.9, 0.1);
+
+        detector.update(0.5, 2.0, throttling(100, 0));
+        assert_eq!(detector.update(0.5, 2.0, throttling(200, 50)), Some(false));
+    }
+
+    #[test]
+    fn test_not_starved_when_busy_but_not_throttled_lately() {
+        let mut detector = CpuStarvationDetector::new(0.9, 0.1);
+
+        // plenty of throttling in the past, but none since the previous reading
+        detector.update(1.9, 2.0, throttling(100, 80));
+        assert_eq!(detector.update(1.9, 2.0, throttling(200, 82)), Some(false));
+    }
+
+    #[test]
+    fn test_counter_reset() {
+        let mut detector = CpuStarvationDetector::new(0.9, 0.1);
+
+        detector.update(1.9, 2.0, throttling(1000, 500));
+        assert_eq!(detector.update(1.9, 2.0, throttling(100, 50)), None);
+        assert_eq!(detector.update(1.9, 2.0, throttling(200, 100)), Some(true));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::stats::{CpuUsageValue, Metric, SourceKind, SystemStatsSource, unsupported};
+use std::env;
+use std::io;
+use std::time::Duration;
+use tracing::debug;
+
+const CPU_LIMIT_ENV_VAR: &str = "ACOLYTE_CPU_LIMIT";
+const MEMORY_LIMIT_ENV_VAR: &str = "ACOLYTE_MEM_LIMIT";
+
+/// A source that only knows the CPU and memory limits the orchestrator passed in as environment
+/// variables, e.g. through the Kubernetes downward API (`resourceFieldRef`).
+///
+/// This is an escape hatch for sandboxes where no cgroup files are readable, so it's consulted
+/// after the cgroup sources but before `/proc`, which would report the whole host's resources.
+pub struct EnvLimitsSource {
+    cpu_limit: Option<String>,
+    memory_limit: Option<String>,
+}
+
+impl EnvLimitsSource {
+    pub(crate) fn from_values(cpu_limit: Option<String>, memory_limit: Option<String>) -> Self {
+        Self {
+            cpu_limit,
+            memory_limit,
+        }
+    }
+
+    /// Create the source if any of the limit variables are set
+    pub fn from_env() -> Option<Self> {
+        let cpu_limit = env::var(CPU_LIMIT_ENV_VAR).ok();
+        let memory_limit = env::var(MEMORY_LIMIT_ENV_VAR).ok();
+        if cpu_limit.is_none() && memory_limit.is_none() {
+            return None;
+        }
+        Some(Self::from_values(cpu_limit, memory_limit))
+    }
+}
+
+impl SystemStatsSource for EnvLimitsSource {
+    fn kind(&self) -> SourceKind {
+        SourceKind::EnvLimits
+    }
+
+    fn supports(&self, metric: Metric) -> bool {
+        matches!(metric, Metric::NumCpus | Metric::MemoryTotal)
+    }
+
+    fn get_num_cpus(&self) -> io::Result<f64> {
+        let cpu_limit = self
+            .cpu_limit
+            .as_deref()
+            .ok_or_else(|| unsupported("CPU limit without ACOLYTE_CPU_LIMIT"))?;
+        let num_cpus = parse_cpu_quantity(cpu_limit)?;
+        debug!("Using {CPU_LIMIT_ENV_VAR} for number of CPUs");
+        Ok(num_cpus)
+    }
+
+    fn get_cpu_usage(&self, _sample_interval: Duration) -> io::Result<CpuUsageValue> {
+        Err(unsupported("CPU usage"))
+    }
+
+    fn get_memory_usage_kb(&self) -> io::Result<u64> {
+        Err(unsupported("Memory usage"))
+    }
+
+    fn get_memory_total_kb(&self) -> io::Result<u64> {
+        let memory_limit = self
+            .memory_limit
+            .as_deref()
+            .ok_or_else(|| unsupported("Memory limit without ACOLYTE_MEM_LIMIT"))?;
+        let memory_bytes = parse_memory_quantity(memory_limit)?;
+        debug!("Using {MEMORY_LIMIT_ENV_VAR} for memory total");
+        Ok(memory_bytes / 1024)
+    }
+}
+
+/// Parse a Kubernetes CPU quantity like `2`, `1.5` or `500m` into cores
+fn parse_cpu_quantity(quantity: &str) -> io::Result<f64> {
+    let quantity = quantity.trim();
+    let cores = match quantity.strip_suffix('m') {
+        Some(millicores) => millicores.parse::<f64>().map(|m| m / 1000.0),
+        None => quantity.parse::<f64>(),
+    }
+    .map_err(|e| invalid_quantity(quantity, e))?;
+
+    if !cores.is_finite() || cores <= 0.0 {
+        return Err(invalid_quantity(quantity, "not a positive number"));
+    }
+    Ok(cores)
+}
+
+/// Parse a Kubernetes memory quantity like `512Mi`, `1G` or `134217728` into bytes
+fn parse_memory_quantity(quantity: &str) -> io::Result<u64> {
+    const SUFFIXES: [(&str, f64); 12] = [
+        // the binary ones first so that e.g. `Mi` isn't mistaken for `M`
+        ("Ki", 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Gi", 1024.0 * 1024.0 * 1024.0),
+        ("Ti", 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Pi", 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Ei", 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+        ("P", 1e15),
+        ("E", 1e18),
+    ];
+
+    let quantity = quantity.trim();
+    let (number, multiplier) = SUFFIXES
+        .iter()
+        .find_map(|(suffix, multiplier)| {
+            quantity
+                .strip_suffix(suffix)
+                .map(|number| (number, *multiplier))
+        })
+        .unwrap_or((quantity, 1.0));
+
+    // plain numbers may also use an exponent, e.g. `129e6`
+    let bytes = number
+        .parse::<f64>()
+        .map_err(|e| invalid_quantity(quantity, e))?
+        * multiplier;
+    if !bytes.is_finite() || bytes <= 0.0 {
+        return Err(invalid_quantity(quantity, "not a positive number"));
+    }
+    Ok(bytes as u64)
+}
+
+fn invalid_quantity(quantity: &str, reason: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid quantity {quantity:?}: {reason}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_quantity() -> io::Result<()> {
+        assert_eq!(parse_cpu_quantity("2")?, 2.0);
+        assert_eq!(parse_cpu_quantity("1.5")?, 1.5);
+        assert_eq!(parse_cpu_quantity("500m")?, 0.5);
+        assert_eq!(parse_cpu_quantity("2500m")?, 2.5);
+        assert!(parse_cpu_quantity("0").is_err());
+        assert!(parse_cpu_quantity("lots").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_memory_quantity() -> io::Result<()> {
+        assert_eq!(parse_memory_quantity("134217728")?, 134217728);
+        assert_eq!(parse_memory_quantity("512Mi")?, 512 * 1024 * 1024);
+        assert_eq!(parse_memory_quantity("2Gi")?, 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_memory_quantity("1G")?, 1_000_000_000);
+        assert_eq!(parse_memory_quantity("500M")?, 500_000_000);
+        assert_eq!(parse_memory_quantity("129e6")?, 129_000_000);
+        assert!(parse_memory_quantity("Mi").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_limits_source() -> io::Result<()> {
+        let source =
+            EnvLimitsSource::from_values(Some("250m".to_string()), Some("1Gi".to_string()));
+
+        assert_eq!(source.get_num_cpus()?, 0.25);
+        assert_eq!(source.get_memory_total_kb()?, 1024 * 1024);
+        assert!(source.get_memory_usage_kb().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_limits_source_with_only_memory() {
+        let source = EnvLimitsSource::from_values(None, Some("1Gi".to_string()));
+
+        let result = source.get_num_cpus();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+}
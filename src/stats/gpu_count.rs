@@ -0,0 +1,103 @@
+use tracing::{info, warn};
+
+/// What to do when the number of GPUs changes while running, e.g. when the device plugin reassigns them
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GpuCountChange {
+    /// Report the new count, treating it as the one to expect from then on
+    Adapt,
+    /// Like `Adapt`, but warn about the change
+    Warn,
+    /// Report no GPU stats until the count is back to the first one seen
+    Error,
+}
+
+/// Keeps track of the number of GPUs to notice when it changes.
+pub struct GpuCountWatch {
+    on_change: GpuCountChange,
+    expected: Option<u32>,
+}
+
+impl GpuCountWatch {
+    pub fn new(on_change: GpuCountChange) -> Self {
+        Self {
+            on_change,
+            expected: None,
+        }
+    }
+
+    /// Check the number of GPUs just seen against the expected one, failing if a change is an error.
+    pub fn check(&mut self, num_gpus: u32) -> Result<(), String> {
+        let Some(expected) = self.expected.filter(|&expected| expected != num_gpus) else {
+            self.expected = Some(num_gpus);
+            return Ok(());
+        };
+        match self.on_change {
+            GpuCountChange::Adapt => {
+                info!("Number of GPUs changed from {expected} to {num_gpus}");
+            }
+            GpuCountChange::Warn => {
+                warn!("Number of GPUs changed from {expected} to {num_gpus}");
+            }
+            GpuCountChange::Error => {
+                return Err(format!(
+                    "Number of GPUs changed from {expected} to {num_gpus}"
+                ));
+            }
+        }
+        // nvidia-smi is asked afresh every time, but NVML holds on to what it found when it was
+        // loaded; the next sample loads it anew to see the GPUs as they are now
+        crate::stats::reset_gpu_sources();
+        self.expected = Some(num_gpus);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::nvidia_smi::{self, MockNvidiaSmiProvider};
+    use mockall::Sequence;
+
+    /// Number of GPUs nvidia-smi lists on each call: two, then one taken away, then two again
+    fn gpu_counts(on_change: GpuCountChange) -> Vec<Result<u32, String>> {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        let mut sequence = Sequence::new();
+        for output in [
+            "0, 50, 1000, 16000, GPU-a, 00000000:3B:00.0\n1, 50, 1000, 16000, GPU-b, 00000000:AF:00.0",
+            "0, 50, 1000, 16000, GPU-a, 00000000:3B:00.0",
+            "0, 50, 1000, 16000, GPU-a, 00000000:3B:00.0\n1, 50, 1000, 16000, GPU-b, 00000000:AF:00.0",
+        ] {
+            mock_provider
+                .expect_get_nvidia_gpu_stats()
+                .times(1)
+                .in_sequence(&mut sequence)
+                .returning(move || Ok(output.to_string()));
+        }
+
+        let mut watch = GpuCountWatch::new(on_change);
+        (0..3)
+            .map(|_| {
+                let gpu_stats = nvidia_smi::get_gpu_stats(&mock_provider).unwrap();
+                watch.check(gpu_stats.num_gpus).map(|_| gpu_stats.num_gpus)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_adapt_to_count_change() {
+        assert_eq!(gpu_counts(GpuCountChange::Adapt), vec![Ok(2), Ok(1), Ok(2)]);
+        assert_eq!(gpu_counts(GpuCountChange::Warn), vec![Ok(2), Ok(1), Ok(2)]);
+    }
+
+    #[test]
+    fn test_count_change_as_error() {
+        assert_eq!(
+            gpu_counts(GpuCountChange::Error),
+            vec![
+                Ok(2),
+                Err("Number of GPUs changed from 2 to 1".to_string()),
+                Ok(2) // back to what was expected
+            ]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use std::str::FromStr;
+use tracing::debug;
+
+// the GPU tools report memory in MiB or bytes; like the rest of our `*_kb` fields, we report KiB
+// (1024 bytes), and MiB alongside
+pub const BYTES_PER_KIB: u64 = 1024;
+pub const KIB_PER_MIB: u64 = 1024;
+
+// what the tools give instead of a value the GPU or driver can't provide; nvidia-smi brackets it
+pub const UNAVAILABLE_VALUES: [&str; 3] = ["[N/A]", "[Not Supported]", "N/A"];
+
+/// Parse a required field, treating an empty or unavailable one as a failure rather than a zero,
+/// so it's left out of the totals instead of dragging them down
+pub fn parse_field<T: FromStr>(value: &str, name: &str) -> Option<T> {
+    if value.is_empty() {
+        debug!("Missing GPU {}", name);
+        return None;
+    }
+    if UNAVAILABLE_VALUES.contains(&value) {
+        debug!("GPU {} not available ({}), leaving it out", name, value);
+        return None;
+    }
+    let parsed = value.parse::<T>().ok();
+    if parsed.is_none() {
+        debug!("Failed to parse GPU {}: {}", name, value);
+    }
+    parsed
+}
+
+/// The PCI bus ID in one format whatever tool reported it, e.g. nvidia-smi's `00000000:3B:00.0`
+/// and sysfs' `0000:3b:00.0` both become `0000:3b:00.0`
+pub fn normalize_pci_bus_id(bus_id: &str) -> String {
+    let bus_id = bus_id.to_lowercase();
+    match bus_id
+        .split_once(':')
+        .and_then(|(domain, rest)| Some((u32::from_str_radix(domain, 16).ok()?, rest)))
+    {
+        Some((domain, rest)) => format!("{domain:04x}:{rest}"),
+        None => bus_id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_field() {
+        assert_eq!(parse_field::<u64>("8000", "memory used"), Some(8000));
+        assert_eq!(parse_field::<f64>("75.5", "utilization"), Some(75.5));
+        for value in ["", "[N/A]", "[Not Supported]", "N/A", "lots"] {
+            assert_eq!(parse_field::<u64>(value, "memory used"), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn test_normalize_pci_bus_id() {
+        assert_eq!(normalize_pci_bus_id("00000000:3B:00.0"), "0000:3b:00.0");
+        assert_eq!(normalize_pci_bus_id("0000:3b:00.0"), "0000:3b:00.0");
+        assert_eq!(normalize_pci_bus_id("00000001:AF:00.0"), "0001:af:00.0");
+        assert_eq!(normalize_pci_bus_id("garbage"), "garbage");
+    }
+}
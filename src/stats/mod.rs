@@ -1,18 +1,48 @@
+mod affinity;
+mod buddyinfo;
 pub(crate) mod cgroup_v1;
 pub(crate) mod cgroup_v2;
+mod controller_filter;
+mod cpu_freq;
+mod cpu_sampler;
+mod cpu_starved;
+pub(crate) mod env_limits;
+mod gpu_count;
+mod gpu_fields;
 mod nvidia_smi;
+#[cfg(feature = "nvml")]
+mod nvml;
 mod paths;
 pub(crate) mod proc;
+mod rate;
+mod rocm_smi;
 
+pub use crate::stats::affinity::{get_cpuset_cpu_count, reconcile_num_cpus};
+pub use crate::stats::buddyinfo::get_memory_max_free_order;
+pub use crate::stats::controller_filter::{CONTROLLERS, ControllerFilter};
+pub use crate::stats::cpu_freq::get_cpu_freq_mhz;
+pub use crate::stats::cpu_sampler::CpuSampler;
+pub use crate::stats::cpu_starved::CpuStarvationDetector;
+pub use crate::stats::gpu_count::{GpuCountChange, GpuCountWatch};
 pub use crate::stats::paths::{
     detect_cgroup_version, get_cgroup_v1_mount_points, get_cgroup_v2_mount_point,
+    get_cgroup_v2_self_path, is_rootfs_readonly,
 };
+pub use crate::stats::rate::{CounterRate, SummedCounterRates};
+#[cfg(test)]
+use mockall::automock;
 use nvidia_smi::NvidiaSmiExecutor;
+use rocm_smi::RocmSmiExecutor;
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
 use std::io;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use tracing::debug;
 
 // TODO: see if we could make this a bit simpler or give these a better name
+#[derive(Debug, Clone, Copy)]
 pub enum CpuUsageValue {
     FromCgroupV2(f64), // normalized CPU usage i.e., 1.5 for one and a half CPUs busy
     FromCgroupV1(f64), // normalized CPU usage, like the V2 above
@@ -40,11 +70,222 @@ impl CpuUsageValue {
     }
 }
 
+/// A reading of cumulative CPU time (a cgroup's, or the host's via proc); the usage between two readings can be worked out
+/// without sleeping in between, so it can cover the whole stat interval
+#[derive(Debug, Clone, Copy)]
+pub struct CpuTimeReading {
+    pub time: Instant,      // when the counter was read
+    pub cpu_time: Duration, // CPU time used across all CPUs so far
+}
+
+impl CpuTimeReading {
+    pub fn now(cpu_time: Duration) -> Self {
+        Self {
+            time: Instant::now(),
+            cpu_time,
+        }
+    }
+
+    /// Normalized CPU usage since an earlier reading, i.e. 1.5 for one and a half CPUs busy
+    pub fn usage_since(&self, previous: &CpuTimeReading) -> Option<f64> {
+        let elapsed_secs = self
+            .time
+            .checked_duration_since(previous.time)?
+            .as_secs_f64();
+        if elapsed_secs <= 0.0 {
+            return None;
+        }
+        // the counter only goes backwards if the cgroup was recreated, which is as good as idle
+        let used_secs = self
+            .cpu_time
+            .saturating_sub(previous.cpu_time)
+            .as_secs_f64();
+        Some(used_secs / elapsed_secs)
+    }
+
+    /// CPU time used since an earlier reading, in CPU-seconds
+    pub fn cpu_seconds_since(&self, previous: &CpuTimeReading) -> f64 {
+        self.cpu_time
+            .saturating_sub(previous.cpu_time)
+            .as_secs_f64()
+    }
+}
+
+#[derive(Debug)]
+pub struct CpuBurstStats {
+    pub nr_bursts: u64, // number of periods where the cgroup used burst credit
+    pub burst_usec: Option<u64>, // cumulative CPU time spent bursting over the quota
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CpuThrottlingStats {
+    pub nr_periods: u64,   // number of CFS enforcement periods that have elapsed
+    pub nr_throttled: u64, // number of those periods where the cgroup hit its quota
+    pub throttled_usec: Option<u64>, // total time spent throttled, if the kernel reports it
+}
+
+/// The host's CPUs, for inventory rather than every entry
+#[derive(Debug, Serialize)]
+pub struct CpuInfo {
+    pub model_name: Option<String>, // e.g. "Intel(R) Xeon(R) Gold 6154 CPU @ 3.00GHz"
+    pub logical_cores: usize,       // i.e. hardware threads
+    pub physical_cores: Option<usize>, // across all sockets, when the topology is known
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SchedulerCounters {
+    pub context_switches: u64, // context switches across all CPUs since boot
+    pub interrupts: u64,       // interrupts serviced since boot
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SwapCounters {
+    pub pages_in: u64,  // pages swapped in since boot
+    pub pages_out: u64, // pages swapped out since boot
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OomScore {
+    pub score: i32, // the kernel's badness score, the highest is killed first when out of memory
+    pub adj: i32,   // the adjustment to it, -1000 to 1000, e.g. set by Kubernetes per QoS class
+}
+
+#[derive(Debug)]
+pub struct PidsStats {
+    pub current: u64,     // number of processes (well, tasks) in the cgroup
+    pub max: Option<u64>, // the process limit, `None` when unlimited
+}
+
+impl PidsStats {
+    /// How close the cgroup is to its process limit, e.g. 0.5 when half of the PIDs are in use
+    pub fn ratio(&self) -> Option<f64> {
+        self.max
+            .filter(|max| *max > 0)
+            .map(|max| self.current as f64 / max as f64)
+    }
+}
+
+#[derive(Debug)]
+pub struct MemorySwapStats {
+    pub usage_kb: u64,         // memory and swap used combined
+    pub limit_kb: Option<u64>, // the combined limit, `None` when unlimited
+}
+
+impl MemorySwapStats {
+    /// How close the combined usage is to the combined limit, e.g. 0.5 when half of it is in use
+    pub fn ratio(&self) -> Option<f64> {
+        self.limit_kb
+            .filter(|limit_kb| *limit_kb > 0)
+            .map(|limit_kb| self.usage_kb as f64 / limit_kb as f64)
+    }
+}
+
+#[derive(Debug)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+#[derive(Debug)]
+pub struct MemoryBreakdown {
+    pub anon_kb: u64, // what the processes allocated, e.g. heap and stacks
+    pub file_kb: u64, // page cache, which the kernel can mostly reclaim under pressure
+}
+
+#[derive(Debug)]
+pub struct SwapUsage {
+    pub usage_kb: u64,
+    pub limit_kb: Option<u64>, // `None` when unlimited
+}
+
+/// Bytes read and written so far by block device, keyed by its `major:minor`
+pub type DiskBytes = HashMap<String, (u64, u64)>;
+
+/// Bytes received and transmitted so far by network interface, keyed by its name
+pub type NetworkBytes = HashMap<String, (u64, u64)>;
+
+/// Memory stalls over the last 10 seconds, in percent of the time
+#[derive(Debug)]
+pub struct MemoryPressure {
+    pub some_avg10: f64, // some of the tasks were waiting on memory, e.g. reclaim or swap-in
+    pub full_avg10: f64, // all of them were, so no work got done at all
+}
+
+/// Count the CPUs in a cpuset list like `0-3,8`; `None` if it's malformed or empty
+pub(crate) fn parse_cpu_list_count(text: &str) -> Option<f64> {
+    let mut count = 0;
+    for cpus in text.trim().split(',').filter(|cpus| !cpus.is_empty()) {
+        let (first, last) = match cpus.split_once('-') {
+            Some((first, last)) => (first.parse::<u32>().ok()?, last.parse::<u32>().ok()?),
+            None => {
+                let cpu = cpus.parse::<u32>().ok()?;
+                (cpu, cpu)
+            }
+        };
+        count += last.checked_sub(first)? + 1;
+    }
+    (count > 0).then_some(count as f64)
+}
+
+/// Parse `avg10` from the line of the given kind in a PSI pressure file,
+/// e.g. `some avg10=1.50 avg60=0.80 avg300=0.20 total=12345`
+pub(crate) fn parse_pressure_avg10(
+    lines: &[String],
+    kind: &str,
+    file_name: &str,
+) -> io::Result<f64> {
+    let line = lines
+        .iter()
+        .find_map(|line| line.strip_prefix(kind)?.strip_prefix(' '))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("No `{kind}` line in {file_name}"),
+            )
+        })?;
+    let avg10 = line
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("No avg10 in {file_name}"),
+            )
+        })?;
+    avg10.parse::<f64>().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid {file_name} format: {e}"),
+        )
+    })
+}
+
+/// Parse `pids.max`, which is either a number or `max` for no limit
+pub(crate) fn parse_pids_max(text: &str) -> Option<u64> {
+    text.trim().parse::<u64>().ok()
+}
+
 pub struct GpuStats {
-    pub num_gpus: u32,        // N = number of GPUs
-    pub gpu_usage: f64,       // normalized usage across all GPUs (0.0 - N.0)
-    pub memory_usage_kb: u64, // sum of memory usage across all GPUs
-    pub memory_total_kb: u64, // sum of total memory across all GPUs
+    pub num_gpus: u32,         // N = number of GPUs
+    pub gpu_usage: f64,        // normalized usage across all GPUs (0.0 - N.0)
+    pub memory_usage_kb: u64,  // sum of memory usage across all GPUs, in KiB
+    pub memory_total_kb: u64,  // sum of total memory across all GPUs, in KiB
+    pub gpus: Vec<GpuUsage>,   // the same per GPU
+    pub memory_usage_mib: u64, // sums of memory usage and total in MiB, as nvidia-smi reports them
+    pub memory_total_mib: u64,
+    pub temperature_c: Option<f64>, // the hottest GPU's temperature, where reported
+    pub power_watts: Option<f64>,   // sum of power draw across the GPUs that report it
+    pub unparsed_fields: u32,       // number of empty or malformed fields left out of the above
+}
+
+pub struct GpuUsage {
+    pub index: u32,                   // as the vendor's tool numbers the GPU
+    pub mig_index: Option<u32>,       // the instance on the card, for a MIG instance
+    pub uuid: Option<String>,         // to tell which GPU per-process stats are about
+    pub usage: Option<f64>,           // 0.0 - 1.0, if known
+    pub memory_usage_kb: Option<u64>, // if known
+    pub memory_total_kb: Option<u64>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -55,6 +296,14 @@ pub enum CgroupVersion {
 }
 
 impl CgroupVersion {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+            Self::V1AndV2 => "hybrid",
+        }
+    }
+
     pub fn has_v1(&self) -> bool {
         match self {
             Self::V1 | Self::V1AndV2 => true,
@@ -69,15 +318,654 @@ impl CgroupVersion {
     }
 }
 
+/// A GPU vendor's tool to get GPU stats from
+#[cfg_attr(test, automock)]
+pub trait GpuStatsSource {
+    fn get_gpu_stats(&self) -> io::Result<GpuStats>;
+}
+
+/// The GPU stats sources in the order they're tried in: NVIDIA first, through NVML if it's built in and
+/// can be loaded, then AMD
+fn get_gpu_sources() -> Vec<Box<dyn GpuStatsSource>> {
+    let smi_sources: [Box<dyn GpuStatsSource>; 2] = [
+        Box::new(NvidiaSmiExecutor::new()),
+        Box::new(RocmSmiExecutor::new()),
+    ];
+    get_nvml_source().into_iter().chain(smi_sources).collect()
+}
+
+#[cfg(feature = "nvml")]
+fn get_nvml_source() -> Option<Box<dyn GpuStatsSource>> {
+    nvml::NvmlGpuSource::get().map(|source| -> Box<dyn GpuStatsSource> { Box::new(source) })
+}
+
+#[cfg(not(feature = "nvml"))]
+fn get_nvml_source() -> Option<Box<dyn GpuStatsSource>> {
+    None
+}
+
+/// Drop what the GPU stats sources hold on to about the GPUs, so the next sample starts afresh
+pub fn reset_gpu_sources() {
+    #[cfg(feature = "nvml")]
+    nvml::reset();
+}
+
 pub fn get_gpu_stats() -> Option<GpuStats> {
-    // we only support NVIDIA GPUs for now so no need to check for other sources
+    first_gpu_stats(&get_gpu_sources())
+}
+
+/// GPU stats from the first source to provide them
+fn first_gpu_stats(sources: &[Box<dyn GpuStatsSource>]) -> Option<GpuStats> {
+    // none of the tools being installed is the usual case on nodes without GPUs, so failures aren't worth reporting
+    sources
+        .iter()
+        .find_map(|source| match source.get_gpu_stats() {
+            Ok(gpu_stats) => Some(gpu_stats),
+            Err(e) => {
+                debug!("No GPU stats: {e}");
+                None
+            }
+        })
+}
+
+/// GPU usage scaled down by the share of each GPU's memory that our processes hold.
+///
+/// On GPUs shared through MPS or time-slicing, the utilization is for everything running on the GPU,
+/// so this is a rough guess at our part of it; the processes have to be visible to nvidia-smi.
+pub fn get_gpu_usage_weighted(gpu_stats: &GpuStats) -> Option<f64> {
     let executor = NvidiaSmiExecutor::new();
-    nvidia_smi::get_gpu_stats(&executor).ok()
+    let own_cgroup = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    nvidia_smi::get_gpu_usage_weighted(&executor, &gpu_stats.gpus, |pid| {
+        // the GPU's processes are ours if they're in the same cgroup, i.e. container, as we are
+        std::fs::read_to_string(format!("/proc/{pid}/cgroup"))
+            .is_ok_and(|cgroup| cgroup == own_cgroup)
+    })
+    .ok()
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum SourceKind {
+    CgroupV2,
+    CgroupV1,
+    EnvLimits,
+    Proc,
+}
+
+impl SourceKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CgroupV2 => "cgroup_v2",
+            Self::CgroupV1 => "cgroup_v1",
+            Self::EnvLimits => "env_limits",
+            Self::Proc => "proc",
+        }
+    }
+
+    /// Whether the values come from the container's cgroup rather than the whole host
+    pub fn is_cgroup(&self) -> bool {
+        match self {
+            Self::CgroupV2 | Self::CgroupV1 => true,
+            Self::EnvLimits | Self::Proc => false,
+        }
+    }
+}
+
+/// The metrics sources can provide, used to describe which sources will be tried for what
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Metric {
+    NumCpus,
+    CpuUsage,
+    MemoryUsage,
+    MemoryTotal,
+    CpuBurst,
+    CpuThrottling,
+    DiskIoLatency,
+    MajorFaults,
+    Pids,
+    SchedulerCounters,
+    SwapActivity,
+    MemorySwap,
+    CpuPressure,
+    MemoryPressure,
+    IoPressure,
+    DiskThroughput,
+    Cpuset,
+    SwapUsage,
+    MemoryBreakdown,
+    OomKills,
+    LoadAverage,
+    Uptime,
+    PerCoreCpuUsage,
+    NetworkThroughput,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 24] = [
+        Self::NumCpus,
+        Self::CpuUsage,
+        Self::MemoryUsage,
+        Self::MemoryTotal,
+        Self::CpuBurst,
+        Self::CpuThrottling,
+        Self::DiskIoLatency,
+        Self::MajorFaults,
+        Self::Pids,
+        Self::SchedulerCounters,
+        Self::SwapActivity,
+        Self::MemorySwap,
+        Self::CpuPressure,
+        Self::MemoryPressure,
+        Self::IoPressure,
+        Self::DiskThroughput,
+        Self::Cpuset,
+        Self::SwapUsage,
+        Self::MemoryBreakdown,
+        Self::OomKills,
+        Self::LoadAverage,
+        Self::Uptime,
+        Self::PerCoreCpuUsage,
+        Self::NetworkThroughput,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::NumCpus => "num_cpus",
+            Self::CpuUsage => "cpu_usage",
+            Self::MemoryUsage => "memory_usage",
+            Self::MemoryTotal => "memory_total",
+            Self::CpuBurst => "cpu_burst",
+            Self::CpuThrottling => "cpu_throttling",
+            Self::DiskIoLatency => "disk_io_latency",
+            Self::MajorFaults => "major_faults",
+            Self::Pids => "pids",
+            Self::SchedulerCounters => "scheduler_counters",
+            Self::SwapActivity => "swap_activity",
+            Self::MemorySwap => "memory_swap",
+            Self::CpuPressure => "cpu_pressure",
+            Self::MemoryPressure => "memory_pressure",
+            Self::IoPressure => "io_pressure",
+            Self::DiskThroughput => "disk_throughput",
+            Self::Cpuset => "cpuset",
+            Self::SwapUsage => "swap_usage",
+            Self::MemoryBreakdown => "memory_breakdown",
+            Self::OomKills => "oom_kills",
+            Self::LoadAverage => "load_average",
+            Self::Uptime => "uptime",
+            Self::PerCoreCpuUsage => "per_core_cpu_usage",
+            Self::NetworkThroughput => "network_throughput",
+        }
+    }
+
+    /// The cgroup controller whose files the metric is read from, if it comes from a cgroup at all
+    pub fn controller(&self) -> Option<&'static str> {
+        match self {
+            Self::NumCpus
+            | Self::CpuUsage
+            | Self::CpuBurst
+            | Self::CpuThrottling
+            | Self::CpuPressure => Some("cpu"),
+            Self::MemoryUsage
+            | Self::MemoryTotal
+            | Self::MajorFaults
+            | Self::MemorySwap
+            | Self::MemoryPressure
+            | Self::SwapUsage
+            | Self::MemoryBreakdown
+            | Self::OomKills => Some("memory"),
+            Self::DiskIoLatency | Self::IoPressure | Self::DiskThroughput => Some("io"),
+            Self::Cpuset => Some("cpuset"),
+            Self::Pids => Some("pids"),
+            Self::SchedulerCounters
+            | Self::SwapActivity
+            | Self::LoadAverage
+            | Self::Uptime
+            | Self::PerCoreCpuUsage
+            | Self::NetworkThroughput => None,
+        }
+    }
+}
+
+/// Describe the order sources will be tried in for a metric, e.g. `cgroup_v2 → cgroup_v1 → proc`.
+///
+/// Sources are tried in the order they're listed in, the first one to succeed wins.
+pub fn describe_fallback_chain(sources: &[Box<dyn SystemStatsSource>], metric: Metric) -> String {
+    let names: Vec<&str> = sources
+        .iter()
+        .filter(|source| source.supports(metric))
+        .map(|source| source.kind().name())
+        .collect();
+    if names.is_empty() {
+        "(none)".to_string()
+    } else {
+        names.join(" → ")
+    }
+}
+
+/// Get a value from the first source to provide it, noting why any sources before it failed,
+/// e.g. `cgroup_v2 cpu_usage: ...`; sources that don't support the value at all aren't failures.
+pub fn find_first<T>(
+    sources: &[Box<dyn SystemStatsSource>],
+    what: &str,
+    errors: &mut Vec<String>,
+    get: impl Fn(&dyn SystemStatsSource) -> io::Result<T>,
+) -> Option<(SourceKind, T)> {
+    for source in sources {
+        match get(source.as_ref()) {
+            Ok(value) => return Some((source.kind(), value)),
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {}
+            Err(e) => errors.push(format!("{} {what}: {e}", source.kind().name())),
+        }
+    }
+    None
 }
 
 pub trait SystemStatsSource {
+    fn kind(&self) -> SourceKind;
+
+    /// Whether the source can provide the metric at all; it may still fail to read it at runtime
+    fn supports(&self, metric: Metric) -> bool {
+        matches!(
+            metric,
+            Metric::NumCpus | Metric::CpuUsage | Metric::MemoryUsage | Metric::MemoryTotal
+        )
+    }
+
     fn get_num_cpus(&self) -> io::Result<f64>;
     fn get_cpu_usage(&self, sample_interval: Duration) -> io::Result<CpuUsageValue>;
     fn get_memory_usage_kb(&self) -> io::Result<u64>;
     fn get_memory_total_kb(&self) -> io::Result<u64>;
+
+    /// Number of CPUs the cgroup may run on by its `cpuset`, whatever its CPU quota
+    fn get_cgroup_cpuset_cpu_count(&self) -> io::Result<f64> {
+        Err(unsupported("Cpuset"))
+    }
+
+    // the rest are optional extras that only some sources can provide
+
+    /// Usage of each CPU core (1.0 being one core fully busy), measured over `sample_interval` from now
+    fn get_per_core_cpu_usage(&self, _sample_interval: Duration) -> io::Result<Vec<f64>> {
+        Err(unsupported("Per-core CPU usage"))
+    }
+
+    /// Cumulative CPU time, for measuring CPU usage across the stat interval instead of sleeping
+    fn get_cpu_time(&self) -> io::Result<CpuTimeReading> {
+        Err(unsupported("Cumulative CPU time"))
+    }
+
+    fn get_cpu_burst_stats(&self) -> io::Result<CpuBurstStats> {
+        Err(unsupported("CPU burst stats"))
+    }
+
+    fn get_cpu_throttling_stats(&self) -> io::Result<CpuThrottlingStats> {
+        Err(unsupported("CPU throttling stats"))
+    }
+
+    /// Share of the last 10 seconds some tasks were waiting for CPU, in percent, from PSI
+    fn get_cpu_pressure_some_avg10(&self) -> io::Result<f64> {
+        Err(unsupported("CPU pressure"))
+    }
+
+    /// Cumulative number of processes killed by the OOM killer in the cgroup
+    fn get_oom_kill_count(&self) -> io::Result<u64> {
+        Err(unsupported("OOM kill count"))
+    }
+
+    /// How much of the memory usage is anonymous memory and how much page cache
+    fn get_memory_breakdown(&self) -> io::Result<MemoryBreakdown> {
+        Err(unsupported("Memory breakdown"))
+    }
+
+    /// Swap used and the swap limit (or the host's swap) on their own
+    fn get_swap_usage(&self) -> io::Result<SwapUsage> {
+        Err(unsupported("Swap usage"))
+    }
+
+    /// Share of the last 10 seconds tasks were stalled on memory, from cgroup v2 PSI
+    fn get_memory_pressure(&self) -> io::Result<MemoryPressure> {
+        Err(unsupported("Memory pressure"))
+    }
+
+    /// Share of the last 10 seconds all tasks were waiting on IO, in percent, from cgroup v2 PSI
+    fn get_io_pressure_full_avg10(&self) -> io::Result<f64> {
+        Err(unsupported("IO pressure"))
+    }
+
+    /// Bytes read and written by block device so far, for the throughput between samples
+    fn get_disk_bytes(&self) -> io::Result<DiskBytes> {
+        Err(unsupported("Disk throughput"))
+    }
+
+    /// Bytes received and transmitted by network interface so far, for the throughput between samples
+    fn get_network_bytes(&self) -> io::Result<NetworkBytes> {
+        Err(unsupported("Network throughput"))
+    }
+
+    fn get_disk_io_latency_ms(&self) -> io::Result<f64> {
+        Err(unsupported("Disk IO latency"))
+    }
+
+    /// Combined memory and swap usage and limit, for cgroup v1 with swap accounting on
+    fn get_memory_swap_stats(&self) -> io::Result<MemorySwapStats> {
+        Err(unsupported("Memory+swap"))
+    }
+
+    /// Cumulative number of major page faults
+    fn get_major_faults(&self) -> io::Result<u64> {
+        Err(unsupported("Major page faults"))
+    }
+
+    fn get_pids_stats(&self) -> io::Result<PidsStats> {
+        Err(unsupported("Pids"))
+    }
+
+    /// The host's load averages, as cgroups have nothing like them
+    fn get_load_average(&self) -> io::Result<LoadAverage> {
+        Err(unsupported("Load average"))
+    }
+
+    /// Seconds since the host booted
+    fn get_uptime_secs(&self) -> io::Result<f64> {
+        Err(unsupported("Uptime"))
+    }
+
+    /// Cumulative numbers of context switches and interrupts
+    fn get_scheduler_counters(&self) -> io::Result<SchedulerCounters> {
+        Err(unsupported("Scheduler counters"))
+    }
+
+    /// Cumulative numbers of pages swapped in and out
+    fn get_swap_counters(&self) -> io::Result<SwapCounters> {
+        Err(unsupported("Swap counters"))
+    }
+
+    /// Names of the cgroup controllers available to us, e.g. `["cpu", "memory"]`
+    fn get_available_controllers(&self) -> io::Result<Vec<String>> {
+        Err(unsupported("Controller list"))
+    }
+
+    fn get_cpu_info(&self) -> io::Result<CpuInfo> {
+        Err(unsupported("CPU info"))
+    }
+
+    /// How likely a process is to be killed when out of memory
+    fn get_oom_score(&self, _pid: u32) -> io::Result<OomScore> {
+        Err(unsupported("OOM score"))
+    }
+
+    /// When the cgroup was created, going by the modification time of its directory
+    fn get_cgroup_created_at(&self) -> io::Result<SystemTime> {
+        Err(unsupported("Cgroup creation time"))
+    }
+
+    /// Number of threads in a process
+    fn get_thread_count(&self, _pid: u32) -> io::Result<u64> {
+        Err(unsupported("Thread count"))
+    }
+}
+
+pub(crate) fn unsupported(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{what} not supported by this source"),
+    )
+}
+
+/// The error a source gives when a limit is explicitly unlimited rather than unreadable
+#[derive(Debug)]
+struct Unlimited(String);
+
+impl fmt::Display for Unlimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Unlimited {}
+
+pub(crate) fn unlimited(kind: io::ErrorKind, message: &str) -> io::Error {
+    io::Error::new(kind, Unlimited(message.to_string()))
+}
+
+/// Whether the error says the limit is unlimited, e.g. `cpu.max` is `max`
+pub fn is_unlimited(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<Unlimited>())
+}
+
+/// Get a limit as `find_first` does, along with whether the cgroup sets none: `Some(true)` if a cgroup source says
+/// it's unlimited, `Some(false)` if one has a limit, and `None` if no cgroup source could tell either way.
+///
+/// It's the one pass over the sources, as the cgroup ones come before the rest.
+pub fn find_first_limit<T>(
+    sources: &[Box<dyn SystemStatsSource>],
+    what: &str,
+    errors: &mut Vec<String>,
+    get: impl Fn(&dyn SystemStatsSource) -> io::Result<T>,
+) -> (Option<(SourceKind, T)>, Option<bool>) {
+    let unlimited = Cell::new(None);
+    let found = find_first(sources, what, errors, |source| {
+        let result = get(source);
+        if source.kind().is_cgroup() && unlimited.get().is_none() {
+            unlimited.set(match &result {
+                Ok(_) => Some(false),
+                Err(e) if is_unlimited(e) => Some(true),
+                Err(_) => None,
+            });
+        }
+        result
+    });
+    (found, unlimited.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::env_limits::EnvLimitsSource;
+
+    struct FakeSource(SourceKind);
+
+    impl SystemStatsSource for FakeSource {
+        fn kind(&self) -> SourceKind {
+            self.0
+        }
+        fn get_num_cpus(&self) -> io::Result<f64> {
+            Err(unsupported("Fake"))
+        }
+        fn get_cpu_usage(&self, _sample_interval: Duration) -> io::Result<CpuUsageValue> {
+            Err(unsupported("Fake"))
+        }
+        fn get_memory_usage_kb(&self) -> io::Result<u64> {
+            Err(unsupported("Fake"))
+        }
+        fn get_memory_total_kb(&self) -> io::Result<u64> {
+            Err(unsupported("Fake"))
+        }
+    }
+
+    #[test]
+    fn test_describe_fallback_chain() {
+        let sources: Vec<Box<dyn SystemStatsSource>> = vec![
+            Box::new(FakeSource(SourceKind::CgroupV1)),
+            Box::new(EnvLimitsSource::from_values(None, Some("1Gi".to_string()))),
+            Box::new(FakeSource(SourceKind::Proc)),
+        ];
+
+        assert_eq!(
+            describe_fallback_chain(&sources, Metric::MemoryTotal),
+            "cgroup_v1 → env_limits → proc"
+        );
+        assert_eq!(
+            describe_fallback_chain(&sources, Metric::MemoryUsage),
+            "cgroup_v1 → proc"
+        );
+        assert_eq!(
+            describe_fallback_chain(&sources, Metric::MajorFaults),
+            "(none)"
+        );
+    }
+
+    struct FailingSource(SourceKind);
+
+    impl SystemStatsSource for FailingSource {
+        fn kind(&self) -> SourceKind {
+            self.0
+        }
+        fn get_num_cpus(&self) -> io::Result<f64> {
+            Err(io::Error::new(io::ErrorKind::NotFound, "cpu.max not found"))
+        }
+        fn get_cpu_usage(&self, _sample_interval: Duration) -> io::Result<CpuUsageValue> {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "cpu.stat not found",
+            ))
+        }
+        fn get_memory_usage_kb(&self) -> io::Result<u64> {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "memory.current not found",
+            ))
+        }
+        fn get_memory_total_kb(&self) -> io::Result<u64> {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "memory.max not found",
+            ))
+        }
+    }
+
+    #[test]
+    fn test_find_first_records_errors() {
+        let sources: Vec<Box<dyn SystemStatsSource>> = vec![
+            Box::new(FailingSource(SourceKind::CgroupV2)),
+            Box::new(FakeSource(SourceKind::CgroupV1)),
+            Box::new(EnvLimitsSource::from_values(None, Some("1Gi".to_string()))),
+        ];
+        let mut errors = vec![];
+
+        let memory_total = find_first(&sources, "memory_total", &mut errors, |source| {
+            source.get_memory_total_kb()
+        });
+        assert!(matches!(
+            memory_total,
+            Some((SourceKind::EnvLimits, 1_048_576))
+        ));
+        // the unsupported fake isn't a failure
+        assert_eq!(errors, vec!["cgroup_v2 memory_total: memory.max not found"]);
+
+        let memory_usage = find_first(&sources, "memory_usage", &mut errors, |source| {
+            source.get_memory_usage_kb()
+        });
+        assert!(memory_usage.is_none());
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[1],
+            "cgroup_v2 memory_usage: memory.current not found"
+        );
+    }
+
+    struct UnlimitedCpuSource;
+
+    impl SystemStatsSource for UnlimitedCpuSource {
+        fn kind(&self) -> SourceKind {
+            SourceKind::CgroupV1
+        }
+        fn get_num_cpus(&self) -> io::Result<f64> {
+            Err(unlimited(io::ErrorKind::InvalidData, "no CPU quota"))
+        }
+        fn get_cpu_usage(&self, _sample_interval: Duration) -> io::Result<CpuUsageValue> {
+            Err(unsupported("Fake"))
+        }
+        fn get_memory_usage_kb(&self) -> io::Result<u64> {
+            Err(unsupported("Fake"))
+        }
+        fn get_memory_total_kb(&self) -> io::Result<u64> {
+            Ok(1024)
+        }
+    }
+
+    #[test]
+    fn test_parse_cpu_list_count() {
+        assert_eq!(parse_cpu_list_count("0-3,8\n"), Some(5.0));
+        assert_eq!(parse_cpu_list_count("7"), Some(1.0));
+        assert_eq!(parse_cpu_list_count("0-1,4-5,16-31"), Some(20.0));
+        assert_eq!(parse_cpu_list_count(""), None);
+        assert_eq!(parse_cpu_list_count("3-1"), None);
+        assert_eq!(parse_cpu_list_count("0-3,x"), None);
+    }
+
+    #[test]
+    fn test_find_first_limit() {
+        let sources: Vec<Box<dyn SystemStatsSource>> = vec![
+            Box::new(FailingSource(SourceKind::CgroupV2)),
+            Box::new(UnlimitedCpuSource),
+            Box::new(EnvLimitsSource::from_values(Some("2".to_string()), None)),
+        ];
+        let (num_cpus, unlimited) = find_first_limit(&sources, "num_cpus", &mut vec![], |source| {
+            source.get_num_cpus()
+        });
+        assert_eq!(num_cpus, Some((SourceKind::EnvLimits, 2.0)));
+        assert_eq!(unlimited, Some(true));
+        let (_, unlimited) = find_first_limit(&sources, "memory_total", &mut vec![], |source| {
+            source.get_memory_total_kb()
+        });
+        assert_eq!(unlimited, Some(false));
+
+        // only cgroups can tell, the env limits answering doesn't count
+        let sources: Vec<Box<dyn SystemStatsSource>> = vec![
+            Box::new(FailingSource(SourceKind::CgroupV2)),
+            Box::new(EnvLimitsSource::from_values(Some("2".to_string()), None)),
+        ];
+        let (num_cpus, unlimited) = find_first_limit(&sources, "num_cpus", &mut vec![], |source| {
+            source.get_num_cpus()
+        });
+        assert_eq!(num_cpus, Some((SourceKind::EnvLimits, 2.0)));
+        assert_eq!(unlimited, None);
+    }
+
+    fn gpu_stats(num_gpus: u32) -> GpuStats {
+        GpuStats {
+            num_gpus,
+            gpu_usage: 0.5 * num_gpus as f64,
+            memory_usage_kb: 0,
+            memory_total_kb: 0,
+            gpus: vec![],
+            memory_usage_mib: 0,
+            memory_total_mib: 0,
+            temperature_c: None,
+            power_watts: None,
+            unparsed_fields: 0,
+        }
+    }
+
+    #[test]
+    fn test_gpu_stats_from_first_source_to_provide_them() {
+        let mut failing = MockGpuStatsSource::new();
+        failing
+            .expect_get_gpu_stats()
+            .times(1)
+            .returning(|| Err(io::Error::new(io::ErrorKind::NotFound, "No such tool")));
+        let mut working = MockGpuStatsSource::new();
+        working
+            .expect_get_gpu_stats()
+            .times(1)
+            .returning(|| Ok(gpu_stats(2)));
+        let mut never_asked = MockGpuStatsSource::new();
+        never_asked.expect_get_gpu_stats().times(0);
+        let sources: Vec<Box<dyn GpuStatsSource>> =
+            vec![Box::new(failing), Box::new(working), Box::new(never_asked)];
+
+        let gpu_stats = first_gpu_stats(&sources).unwrap();
+        assert_eq!(gpu_stats.num_gpus, 2);
+    }
+
+    #[test]
+    fn test_no_gpu_stats_without_sources_to_provide_them() {
+        let mut failing = MockGpuStatsSource::new();
+        failing
+            .expect_get_gpu_stats()
+            .returning(|| Err(io::Error::new(io::ErrorKind::NotFound, "No such tool")));
+        let sources: Vec<Box<dyn GpuStatsSource>> = vec![Box::new(failing)];
+
+        assert!(first_gpu_stats(&sources).is_none());
+        assert!(first_gpu_stats(&[]).is_none());
+    }
 }
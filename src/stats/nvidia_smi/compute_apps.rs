@@ -0,0 +1,96 @@
+use super::NvidiaSmiProvider;
+use crate::stats::GpuUsage;
+use crate::stats::gpu_fields::KIB_PER_MIB;
+use std::collections::HashMap;
+use std::io;
+use tracing::debug;
+
+/// Weight each GPU's utilization by the fraction of its used memory held by processes `is_own_pid` accepts
+pub fn get_gpu_usage_weighted<P: NvidiaSmiProvider>(
+    provider: &P,
+    gpus: &[GpuUsage],
+    is_own_pid: impl Fn(u32) -> bool,
+) -> io::Result<f64> {
+    // Format: gpu_uuid, pid, used_memory [MiB]
+    // e.g. "GPU-5a9f6c7e-..., 4242, 2000"
+    let output = provider.get_nvidia_compute_apps()?;
+
+    let mut own_memory_kb: HashMap<&str, u64> = HashMap::new();
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
+        if parts.len() < 3 {
+            debug!("Skipping malformed line: {}", line);
+            continue;
+        }
+        let (Ok(pid), Ok(used_memory)) = (parts[1].parse::<u32>(), parts[2].parse::<u64>()) else {
+            debug!("Failed to parse GPU process: {}", line);
+            continue;
+        };
+        if is_own_pid(pid) {
+            *own_memory_kb.entry(parts[0]).or_default() += used_memory * KIB_PER_MIB;
+        }
+    }
+
+    let weighted_usage = gpus
+        .iter()
+        .filter_map(|gpu| {
+            let memory_usage_kb = gpu.memory_usage_kb.filter(|kb| *kb > 0)?;
+            let own_kb = *own_memory_kb.get(gpu.uuid.as_deref()?)?;
+            let share = (own_kb as f64 / memory_usage_kb as f64).min(1.0);
+            Some(gpu.usage? * share)
+        })
+        .sum();
+    Ok(weighted_usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::nvidia_smi::MockNvidiaSmiProvider;
+
+    fn gpu(uuid: &str, usage: f64, memory_usage_mib: u64) -> GpuUsage {
+        GpuUsage {
+            index: 0,
+            mig_index: None,
+            uuid: Some(uuid.to_string()),
+            usage: Some(usage),
+            memory_usage_kb: Some(memory_usage_mib * KIB_PER_MIB),
+            memory_total_kb: Some(16384 * KIB_PER_MIB),
+        }
+    }
+
+    #[test]
+    fn test_get_gpu_usage_weighted() -> io::Result<()> {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider
+            .expect_get_nvidia_compute_apps()
+            .returning(|| {
+                Ok("\
+GPU-aaaa, 100, 2000
+GPU-aaaa, 101, 1000
+GPU-aaaa, 200, 5000
+GPU-bbbb, 200, 4000
+"
+                .to_string())
+            });
+        let gpus = [gpu("GPU-aaaa", 0.5, 8000), gpu("GPU-bbbb", 0.5, 4000)];
+
+        // we hold 3000 of the 8000 MiB in use on the first GPU and nothing on the second
+        let weighted = get_gpu_usage_weighted(&mock_provider, &gpus, |pid| pid < 200)?;
+        assert_eq!(weighted, 0.5 * 0.375);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_gpu_usage_weighted_without_processes() -> io::Result<()> {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider
+            .expect_get_nvidia_compute_apps()
+            .returning(|| Ok("".to_string()));
+        let gpus = [gpu("GPU-aaaa", 0.8, 8000)];
+
+        let weighted = get_gpu_usage_weighted(&mock_provider, &gpus, |_| true)?;
+        assert_eq!(weighted, 0.0);
+        Ok(())
+    }
+}
@@ -1,17 +1,32 @@
 use super::NvidiaSmiProvider;
-use crate::stats::GpuStats;
+use super::mig::{MigDevice, get_mig_devices};
+use crate::stats::gpu_fields::{
+    KIB_PER_MIB, UNAVAILABLE_VALUES, normalize_pci_bus_id, parse_field,
+};
+use crate::stats::{GpuStats, GpuUsage};
+use std::collections::{HashMap, HashSet};
 use std::io;
 use tracing::debug;
 
 pub fn get_gpu_stats<P: NvidiaSmiProvider>(provider: &P) -> io::Result<GpuStats> {
-    // Format: index, utilization.gpu [%], memory.used [MiB], memory.total [MiB]
-    // e.g. "0, 75, 8000, 16000"
+    // Format: index, utilization.gpu [%], memory.used [MiB], memory.total [MiB], uuid, pci.bus_id,
+    // temperature.gpu [C], power.draw [W], mig.mode.current
+    // e.g. "0, 75, 8000, 16000, GPU-5a9f6c7e-..., 00000000:3B:00.0, 64, 215.32, Disabled"; the uuid is only
+    // needed to match up per-process stats, and the bus ID to not count a device listed twice; the columns
+    // after the first four are optional
     let output = provider.get_nvidia_gpu_stats()?;
 
     let mut num_gpus = 0;
     let mut total_gpu_usage = 0.0;
-    let mut total_memory_usage_kb = 0;
-    let mut total_memory_kb = 0;
+    let mut total_memory_usage_mib = 0;
+    let mut total_memory_mib = 0;
+    let mut gpus = vec![];
+    let mut max_temperature_c: Option<f64> = None;
+    let mut total_power_watts: Option<f64> = None;
+    let mut unparsed_fields = 0;
+    let mut seen_bus_ids = HashSet::new();
+    // only asked for once a card turns out to have MIG enabled
+    let mut mig_devices: Option<HashMap<String, Vec<MigDevice>>> = None;
 
     for line in output.lines() {
         let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
@@ -19,36 +34,110 @@ pub fn get_gpu_stats<P: NvidiaSmiProvider>(provider: &P) -> io::Result<GpuStats>
             debug!("Skipping malformed line: {}", line);
             continue;
         }
+        if let Some(bus_id) = parts.get(5).filter(|bus_id| !bus_id.is_empty())
+            && !seen_bus_ids.insert(normalize_pci_bus_id(bus_id))
+        {
+            debug!("Skipping GPU at an already counted PCI bus ID: {}", line);
+            continue;
+        }
+
+        // not every GPU has the sensors, in which case they're `[N/A]` or `[Not Supported]`
+        if let Some(temperature_c) = parse_optional_field(parts.get(6).copied()) {
+            max_temperature_c =
+                Some(max_temperature_c.map_or(temperature_c, |max| max.max(temperature_c)));
+        }
+        if let Some(power_watts) = parse_optional_field(parts.get(7).copied()) {
+            total_power_watts = Some(total_power_watts.unwrap_or(0.0) + power_watts);
+        }
+
+        // a card split into MIG instances counts as the instances we can see, each with its slice of the
+        // memory, rather than as the whole card; nvidia-smi doesn't give utilization per instance, so theirs
+        // is unknown rather than 0
+        if parts.get(8) == Some(&"Enabled")
+            && let Some(bus_id) = parts.get(5)
+            && let Some(card_mig_devices) = mig_devices
+                .get_or_insert_with(|| get_mig_devices(provider))
+                .get(*bus_id)
+        {
+            for (position, mig_device) in card_mig_devices.iter().enumerate() {
+                num_gpus += 1;
+                total_memory_usage_mib += mig_device.memory_usage_mib.unwrap_or(0);
+                total_memory_mib += mig_device.memory_total_mib.unwrap_or(0);
+                unparsed_fields += [mig_device.memory_usage_mib, mig_device.memory_total_mib]
+                    .iter()
+                    .filter(|mib| mib.is_none())
+                    .count() as u32;
+                gpus.push(GpuUsage {
+                    index: parts[0].parse().unwrap_or(gpus.len() as u32), // the card's
+                    mig_index: Some(mig_device.index.unwrap_or(position as u32)),
+                    uuid: None,
+                    usage: None,
+                    memory_usage_kb: mig_device.memory_usage_mib.map(|mib| mib * KIB_PER_MIB),
+                    memory_total_kb: mig_device.memory_total_mib.map(|mib| mib * KIB_PER_MIB),
+                });
+            }
+            continue;
+        }
 
         num_gpus += 1;
 
-        if let Ok(usage) = parts[1].parse::<f64>() {
-            total_gpu_usage += usage / 100.0;
+        let usage = parse_field::<f64>(parts[1], "utilization").map(|usage| usage / 100.0);
+        if let Some(usage) = usage {
+            total_gpu_usage += usage;
         } else {
-            debug!("Failed to parse GPU utilization: {}", parts[1]);
+            unparsed_fields += 1;
         }
 
-        if let Ok(mem_used) = parts[2].parse::<u64>() {
-            total_memory_usage_kb += mem_used * 1024; // MiB to KB
+        let memory_usage_mib = parse_field::<u64>(parts[2], "memory used");
+        if let Some(memory_usage_mib) = memory_usage_mib {
+            total_memory_usage_mib += memory_usage_mib;
         } else {
-            debug!("Failed to parse GPU memory used: {}", parts[2]);
+            unparsed_fields += 1;
         }
 
-        if let Ok(mem_total) = parts[3].parse::<u64>() {
-            total_memory_kb += mem_total * 1024; // MiB to KB
+        let memory_total_mib = parse_field::<u64>(parts[3], "total memory");
+        if let Some(memory_total_mib) = memory_total_mib {
+            total_memory_mib += memory_total_mib;
         } else {
-            debug!("Failed to parse GPU total memory: {}", parts[3]);
+            unparsed_fields += 1;
         }
+
+        gpus.push(GpuUsage {
+            // going by the position for a malformed index keeps the GPUs apart all the same
+            index: parts[0].parse().unwrap_or(gpus.len() as u32),
+            mig_index: None,
+            // a trailing comma leaves an empty uuid, which is as good as none
+            uuid: parts
+                .get(4)
+                .filter(|uuid| !uuid.is_empty())
+                .map(|uuid| uuid.to_string()),
+            usage,
+            memory_usage_kb: memory_usage_mib.map(|mib| mib * KIB_PER_MIB),
+            memory_total_kb: memory_total_mib.map(|mib| mib * KIB_PER_MIB),
+        });
     }
 
     Ok(GpuStats {
         num_gpus,
         gpu_usage: total_gpu_usage,
-        memory_usage_kb: total_memory_usage_kb,
-        memory_total_kb: total_memory_kb,
+        memory_usage_kb: total_memory_usage_mib * KIB_PER_MIB,
+        memory_total_kb: total_memory_mib * KIB_PER_MIB,
+        memory_usage_mib: total_memory_usage_mib,
+        memory_total_mib: total_memory_mib,
+        temperature_c: max_temperature_c,
+        power_watts: total_power_watts,
+        gpus,
+        unparsed_fields,
     })
 }
 
+/// Parse a field that only some GPUs report, quietly leaving it out when it's missing, unavailable or not a number
+fn parse_optional_field(value: Option<&str>) -> Option<f64> {
+    value
+        .filter(|value| !UNAVAILABLE_VALUES.contains(value))
+        .and_then(|value| value.parse::<f64>().ok())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -68,6 +157,37 @@ mod tests {
         assert_eq!(stats.memory_total_kb, 32_768_000); // (16000+16000)*1024
     }
 
+    #[test]
+    fn test_get_gpu_stats_per_gpu() {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider.expect_get_nvidia_gpu_stats().returning(|| {
+            Ok("0, 75, 8000, 16000, GPU-aaaa\n1, 50, 4000, 16000, GPU-bbbb".to_string())
+        });
+
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.gpus.len(), 2);
+        assert_eq!(stats.gpus[1].uuid.as_deref(), Some("GPU-bbbb"));
+        assert_eq!(stats.gpus[1].usage, Some(0.5));
+        assert_eq!(stats.gpus[1].memory_usage_kb, Some(4_096_000));
+        assert_eq!(stats.gpus[1].index, 1);
+        assert_eq!(stats.gpus[1].memory_total_kb, Some(16_384_000));
+    }
+
+    #[test]
+    fn test_get_gpu_stats_memory_is_in_kib() {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider
+            .expect_get_nvidia_gpu_stats()
+            .returning(|| Ok("0, 0, 1, 16384".to_string()));
+
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.memory_usage_kb, 1024); // 1 MiB = 1048576 B = 1024 KiB
+        assert_eq!(stats.memory_total_kb, 16_777_216); // 16 GiB = 16384 MiB = 16777216 KiB
+        // and as nvidia-smi gave them
+        assert_eq!(stats.memory_usage_mib, 1);
+        assert_eq!(stats.memory_total_mib, 16384);
+    }
+
     #[test]
     fn test_get_gpu_stats_when_not_available() {
         let mut mock_provider = MockNvidiaSmiProvider::new();
@@ -124,4 +244,181 @@ mod tests {
         assert_eq!(stats.memory_usage_kb, 8_192_000);
         assert_eq!(stats.memory_total_kb, 16_384_000);
     }
+
+    #[test]
+    fn test_get_gpu_stats_with_trailing_comma() {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider
+            .expect_get_nvidia_gpu_stats()
+            .returning(|| Ok("0, 75, 8000, 16000,\n1, 50, 4000, 16000, ".to_string()));
+
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.num_gpus, 2);
+        assert_eq!(stats.gpu_usage, 1.25);
+        assert_eq!(stats.memory_usage_kb, 12_288_000);
+        assert_eq!(stats.memory_total_kb, 32_768_000);
+        assert_eq!(stats.unparsed_fields, 0);
+        assert!(stats.gpus.iter().all(|gpu| gpu.uuid.is_none()));
+    }
+
+    #[test]
+    fn test_get_gpu_stats_with_empty_field() {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider
+            .expect_get_nvidia_gpu_stats()
+            .returning(|| Ok("0, , 8000, 16000\n1, 50, , 16000".to_string()));
+
+        // both GPUs are counted, but the empty fields don't contribute anything
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.num_gpus, 2);
+        assert_eq!(stats.gpu_usage, 0.5);
+        assert_eq!(stats.memory_usage_kb, 8_192_000);
+        assert_eq!(stats.memory_total_kb, 32_768_000);
+        assert_eq!(stats.unparsed_fields, 2);
+    }
+
+    #[test]
+    fn test_get_gpu_stats_with_unavailable_values() {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider.expect_get_nvidia_gpu_stats().returning(|| {
+            Ok(
+                "0, [N/A], 8000, 16000\n1, 50, [Not Supported], 16000\n2, 25, 2000, [N/A]"
+                    .to_string(),
+            )
+        });
+
+        // each unavailable value is left out of its total, the rest of the GPU's values still count
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.num_gpus, 3);
+        assert_eq!(stats.gpu_usage, 0.75);
+        assert_eq!(stats.memory_usage_kb, 10_240_000); // (8000+2000)*1024
+        assert_eq!(stats.memory_total_kb, 32_768_000); // (16000+16000)*1024
+        assert_eq!(stats.unparsed_fields, 3);
+        assert_eq!(stats.gpus[0].usage, None);
+        assert_eq!(stats.gpus[0].memory_total_kb, Some(16_384_000));
+        // and left out of the GPU's own values rather than reported as 0
+        assert_eq!(stats.gpus[1].memory_usage_kb, None);
+        assert_eq!(stats.gpus[2].memory_total_kb, None);
+    }
+
+    #[test]
+    fn test_get_gpu_stats_with_duplicate_bus_ids() {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider.expect_get_nvidia_gpu_stats().returning(|| {
+            Ok([
+                "0, 75, 8000, 16000, GPU-aaaa, 00000000:3B:00.0",
+                "1, 50, 4000, 16000, GPU-bbbb, 00000000:5E:00.0",
+                "2, 75, 8000, 16000, GPU-aaaa, 0000:3b:00.0", // the first one again
+            ]
+            .join("\n"))
+        });
+
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.num_gpus, 2);
+        assert_eq!(stats.gpu_usage, 1.25);
+        assert_eq!(stats.memory_usage_kb, 12_288_000);
+        assert_eq!(stats.memory_total_kb, 32_768_000);
+        assert_eq!(stats.gpus.len(), 2);
+    }
+
+    #[test]
+    fn test_get_gpu_stats_temperature_and_power() {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider.expect_get_nvidia_gpu_stats().returning(|| {
+            Ok([
+                "0, 75, 8000, 16000, GPU-aaaa, 00000000:3B:00.0, 64, 215.5",
+                "1, 50, 4000, 16000, GPU-bbbb, 00000000:5E:00.0, 81, [N/A]",
+                "2, 25, 2000, 16000, GPU-cccc, 00000000:AF:00.0, [N/A], 100.25",
+            ]
+            .join("\n"))
+        });
+
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.num_gpus, 3);
+        assert_eq!(stats.temperature_c, Some(81.0));
+        assert_eq!(stats.power_watts, Some(315.75));
+        assert_eq!(stats.unparsed_fields, 0);
+    }
+
+    #[test]
+    fn test_get_gpu_stats_without_temperature_and_power() {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider
+            .expect_get_nvidia_gpu_stats()
+            .returning(|| Ok("0, 75, 8000, 16000, GPU-aaaa, 00000000:3B:00.0".to_string()));
+
+        // short lines are as good as before
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.num_gpus, 1);
+        assert_eq!(stats.gpu_usage, 0.75);
+        assert_eq!(stats.temperature_c, None);
+        assert_eq!(stats.power_watts, None);
+    }
+
+    #[test]
+    fn test_get_gpu_stats_with_mig_instances() {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider.expect_get_nvidia_gpu_stats().returning(|| {
+            Ok([
+                "0, [N/A], 20480, 81920, GPU-aaaa, 00000000:3B:00.0, 40, 90.5, Enabled",
+                "1, 50, 4000, 81920, GPU-bbbb, 00000000:5E:00.0, 50, 200.0, Disabled",
+            ]
+            .join("\n"))
+        });
+        mock_provider
+            .expect_get_nvidia_mig_devices()
+            .times(1)
+            .returning(|| {
+                Ok([
+                    "GPU 00000000:3B:00.0",
+                    "    MIG Devices",
+                    "        MIG Device",
+                    "            Index                         : 0",
+                    "            FB Memory Usage",
+                    "                Total                     : 4864 MiB",
+                    "                Used                      : 1013 MiB",
+                    "        MIG Device",
+                    "            Index                         : 1",
+                    "            FB Memory Usage",
+                    "                Total                     : 9728 MiB",
+                    "                Used                      : 13 MiB",
+                ]
+                .join("\n"))
+            });
+
+        // the pod sees 1g.5gb and 2g.10gb slices of the first card rather than all of its 80 GB
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.num_gpus, 3);
+        assert_eq!(stats.gpu_usage, 0.5);
+        assert_eq!(stats.memory_total_mib, 4864 + 9728 + 81920);
+        assert_eq!(stats.memory_usage_mib, 1013 + 13 + 4000);
+        assert_eq!(stats.gpus[0].memory_total_kb, Some(4864 * 1024));
+        // the instances share the card's index, but not the MIG one, and their usage is unknown
+        assert_eq!(
+            stats
+                .gpus
+                .iter()
+                .map(|gpu| (gpu.index, gpu.mig_index, gpu.usage))
+                .collect::<Vec<_>>(),
+            vec![(0, Some(0), None), (0, Some(1), None), (1, None, Some(0.5))]
+        );
+        assert_eq!(stats.power_watts, Some(290.5));
+        assert_eq!(stats.unparsed_fields, 0);
+    }
+
+    #[test]
+    fn test_get_gpu_stats_with_mig_but_no_instances_found() {
+        let mut mock_provider = MockNvidiaSmiProvider::new();
+        mock_provider.expect_get_nvidia_gpu_stats().returning(|| {
+            Ok("0, 10, 20480, 81920, GPU-aaaa, 00000000:3B:00.0, 40, 90.5, Enabled".to_string())
+        });
+        mock_provider
+            .expect_get_nvidia_mig_devices()
+            .returning(|| Err(io::Error::other("nvidia-smi exited with non-zero status")));
+
+        // counted as the whole card, as before
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.num_gpus, 1);
+        assert_eq!(stats.memory_total_mib, 81920);
+    }
 }
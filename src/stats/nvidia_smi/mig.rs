@@ -0,0 +1,177 @@
+use super::NvidiaSmiProvider;
+use std::collections::HashMap;
+use tracing::debug;
+
+/// A MIG instance's memory, all of the card's that a pod pinned to the instance gets
+#[derive(Debug, PartialEq)]
+pub struct MigDevice {
+    pub index: Option<u32>, // the instance's on its card
+    pub memory_usage_mib: Option<u64>,
+    pub memory_total_mib: Option<u64>,
+}
+
+/// The MIG instances visible to us on each card with MIG enabled, by the card's PCI bus ID as given;
+/// empty if nvidia-smi fails, so the cards are counted whole instead
+pub fn get_mig_devices<P: NvidiaSmiProvider>(provider: &P) -> HashMap<String, Vec<MigDevice>> {
+    match provider.get_nvidia_mig_devices() {
+        Ok(output) => parse_mig_devices(&output),
+        Err(e) => {
+            debug!("Failed to get MIG devices: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Parse the MIG devices out of `nvidia-smi -q -d MEMORY`, which lists them under each card, e.g.
+///
+/// ```text
+/// GPU 00000000:3B:00.0
+///     FB Memory Usage
+///         Total                             : 81920 MiB
+///         ...
+///     MIG Devices
+///         MIG Device
+///             Index                         : 0
+///             FB Memory Usage
+///                 Total                     : 4864 MiB
+///                 Used                      : 13 MiB
+/// ```
+fn parse_mig_devices(output: &str) -> HashMap<String, Vec<MigDevice>> {
+    let mut devices: HashMap<String, Vec<MigDevice>> = HashMap::new();
+    let mut bus_id: Option<&str> = None;
+    let mut in_mig_device = false;
+    let mut in_mig_memory = false;
+    for line in output.lines() {
+        // a card's section starts unindented
+        if let Some(card_bus_id) = line.strip_prefix("GPU ") {
+            bus_id = Some(card_bus_id.trim());
+            in_mig_device = false;
+            in_mig_memory = false;
+            continue;
+        }
+        let Some(bus_id) = bus_id else {
+            continue;
+        };
+        let line = line.trim();
+        match line
+            .split_once(':')
+            .map(|(key, value)| (key.trim(), value.trim()))
+        {
+            None if line == "MIG Device" => {
+                devices
+                    .entry(bus_id.to_string())
+                    .or_default()
+                    .push(MigDevice {
+                        index: None,
+                        memory_usage_mib: None,
+                        memory_total_mib: None,
+                    });
+                in_mig_device = true;
+                in_mig_memory = false;
+            }
+            None => {
+                // the card's own memory comes before its MIG devices, so only the latter are picked up
+                in_mig_memory = line == "FB Memory Usage"
+                    && devices
+                        .get(bus_id)
+                        .is_some_and(|devices| !devices.is_empty());
+            }
+            Some((key, value)) if in_mig_memory => {
+                let Some(device) = devices
+                    .get_mut(bus_id)
+                    .and_then(|devices| devices.last_mut())
+                else {
+                    continue;
+                };
+                let mib = value
+                    .strip_suffix("MiB")
+                    .and_then(|mib| mib.trim().parse::<u64>().ok());
+                match key {
+                    "Total" => device.memory_total_mib = mib,
+                    "Used" => device.memory_usage_mib = mib,
+                    _ => {}
+                }
+            }
+            Some(("Index", value)) if in_mig_device => {
+                if let Some(device) = devices
+                    .get_mut(bus_id)
+                    .and_then(|devices| devices.last_mut())
+                {
+                    device.index = value.parse().ok();
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIG_OUTPUT: &str = "
+==============NVSMI LOG==============
+
+Timestamp                                 : Mon Feb 19 12:26:02 2024
+Driver Version                            : 535.104.12
+CUDA Version                              : 12.2
+
+Attached GPUs                             : 1
+GPU 00000000:3B:00.0
+    FB Memory Usage
+        Total                             : 81920 MiB
+        Reserved                          : 0 MiB
+        Used                              : 20480 MiB
+        Free                              : 61440 MiB
+    BAR1 Memory Usage
+        Total                             : 131072 MiB
+        Used                              : 1 MiB
+        Free                              : 131071 MiB
+    MIG Devices
+        MIG Device
+            Index                         : 0
+            FB Memory Usage
+                Total                     : 4864 MiB
+                Reserved                  : 0 MiB
+                Used                      : 1013 MiB
+                Free                      : 3851 MiB
+            BAR1 Memory Usage
+                Total                     : 8191 MiB
+                Used                      : 0 MiB
+                Free                      : 8191 MiB
+        MIG Device
+            Index                         : 1
+            FB Memory Usage
+                Total                     : 9728 MiB
+                Reserved                  : 0 MiB
+                Used                      : 13 MiB
+                Free                      : 9715 MiB
+";
+
+    #[test]
+    fn test_parse_mig_devices() {
+        let devices = parse_mig_devices(MIG_OUTPUT);
+        assert_eq!(
+            devices.get("00000000:3B:00.0"),
+            Some(&vec![
+                MigDevice {
+                    index: Some(0),
+                    memory_usage_mib: Some(1013),
+                    memory_total_mib: Some(4864),
+                },
+                MigDevice {
+                    index: Some(1),
+                    memory_usage_mib: Some(13),
+                    memory_total_mib: Some(9728),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_without_mig_devices() {
+        let output = MIG_OUTPUT.split("    MIG Devices").next().unwrap();
+        assert!(parse_mig_devices(output).is_empty());
+    }
+}
@@ -1,5 +1,9 @@
+mod compute_apps;
 mod gpu_stats;
+mod mig;
 
+use crate::stats::{GpuStats, GpuStatsSource};
+pub use compute_apps::get_gpu_usage_weighted;
 pub use gpu_stats::get_gpu_stats;
 use std::io;
 use std::process::Command;
@@ -11,6 +15,8 @@ use tracing::debug;
 #[cfg_attr(test, automock)]
 pub trait NvidiaSmiProvider {
     fn get_nvidia_gpu_stats(&self) -> io::Result<String>;
+    fn get_nvidia_compute_apps(&self) -> io::Result<String>;
+    fn get_nvidia_mig_devices(&self) -> io::Result<String>;
 }
 
 pub struct NvidiaSmiExecutor;
@@ -23,28 +29,50 @@ impl NvidiaSmiExecutor {
 
 impl NvidiaSmiProvider for NvidiaSmiExecutor {
     fn get_nvidia_gpu_stats(&self) -> io::Result<String> {
-        let output = Command::new("nvidia-smi")
-            .args([
-                "--query-gpu=index,utilization.gpu,memory.used,memory.total",
-                "--format=csv,noheader,nounits",
-            ])
-            .output()
-            .map_err(|e| {
-                io::Error::new(
-                    io::ErrorKind::NotFound,
-                    format!("Failed to run nvidia-smi: {e}"),
-                )
-            })?;
-
-        if !output.status.success() {
-            return Err(io::Error::other(format!(
-                "nvidia-smi exited with non-zero status: {}. stderr: {}",
-                output.status,
-                String::from_utf8_lossy(&output.stderr)
-            )));
-        }
-
+        let output = run_nvidia_smi(&[
+            "--query-gpu=index,utilization.gpu,memory.used,memory.total,uuid,pci.bus_id,temperature.gpu,power.draw,mig.mode.current",
+            "--format=csv,noheader,nounits",
+        ])?;
         debug!("Using nvidia-smi for GPU stats"); // report use here as we don't check for nvidia-smi availability
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        Ok(output)
+    }
+
+    fn get_nvidia_compute_apps(&self) -> io::Result<String> {
+        run_nvidia_smi(&[
+            "--query-compute-apps=gpu_uuid,pid,used_memory",
+            "--format=csv,noheader,nounits",
+        ])
     }
+
+    fn get_nvidia_mig_devices(&self) -> io::Result<String> {
+        run_nvidia_smi(&["-q", "-d", "MEMORY"])
+    }
+}
+
+impl GpuStatsSource for NvidiaSmiExecutor {
+    fn get_gpu_stats(&self) -> io::Result<GpuStats> {
+        get_gpu_stats(self)
+    }
+}
+
+fn run_nvidia_smi(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("nvidia-smi")
+        .args(args)
+        .output()
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Failed to run nvidia-smi: {e}"),
+            )
+        })?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "nvidia-smi exited with non-zero status: {}. stderr: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
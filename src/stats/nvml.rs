@@ -0,0 +1,248 @@
+use crate::stats::gpu_fields::{BYTES_PER_KIB, KIB_PER_MIB, normalize_pci_bus_id};
+use crate::stats::{GpuStats, GpuStatsSource, GpuUsage};
+use nvml_wrapper::Nvml;
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use std::collections::HashSet;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+const NVML_DEVICE_MIG_ENABLE: u32 = 1;
+
+/// How long to leave GPU stats to nvidia-smi after NVML failed to load before trying again, e.g. for
+/// a driver that's still being installed
+const INIT_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// NVML once it's loaded, or when loading it last failed; behind a mutex rather than in a `OnceLock`
+/// so that it can be dropped and loaded anew, see `reset`
+static NVML: Mutex<NvmlState> = Mutex::new(NvmlState::Unloaded);
+
+enum NvmlState {
+    Unloaded,
+    Loaded(Box<Nvml>),
+    Failed(Instant),
+}
+
+/// Drop the NVML handle, so that the next sample loads it anew, e.g. to pick up GPUs that have
+/// been added or taken away
+pub fn reset() {
+    *NVML.lock().unwrap() = NvmlState::Unloaded;
+}
+
+/// GPU stats queried from NVML directly, sparing the cost of running nvidia-smi for every sample.
+pub struct NvmlGpuSource;
+
+impl NvmlGpuSource {
+    /// The NVML source, unless `libnvidia-ml` can't be loaded, e.g. on a node without NVIDIA GPUs
+    /// or from a static binary, which can't load libraries at all
+    pub fn get() -> Option<Self> {
+        let mut nvml = NVML.lock().unwrap();
+        let load = match *nvml {
+            NvmlState::Unloaded => true,
+            NvmlState::Failed(failed_at) => failed_at.elapsed() >= INIT_RETRY_INTERVAL,
+            NvmlState::Loaded(_) => false,
+        };
+        if load {
+            *nvml = match Nvml::init() {
+                Ok(loaded) => {
+                    info!("Using NVML for GPU stats");
+                    NvmlState::Loaded(Box::new(loaded))
+                }
+                Err(e) => {
+                    debug!("NVML not available, leaving GPU stats to nvidia-smi: {e}");
+                    NvmlState::Failed(Instant::now())
+                }
+            };
+        }
+        matches!(*nvml, NvmlState::Loaded(_)).then_some(Self)
+    }
+}
+
+impl GpuStatsSource for NvmlGpuSource {
+    fn get_gpu_stats(&self) -> io::Result<GpuStats> {
+        let nvml = NVML.lock().unwrap();
+        let NvmlState::Loaded(nvml) = &*nvml else {
+            return Err(io::Error::other("NVML was reset"));
+        };
+        let num_gpus = nvml.device_count().map_err(io::Error::other)?;
+        let mut readings = vec![];
+        for index in 0..num_gpus {
+            let device = nvml.device_by_index(index).map_err(io::Error::other)?;
+            // the card's memory isn't what a pod pinned to one of its instances gets, nvidia-smi sorts that out
+            if device
+                .mig_mode()
+                .is_ok_and(|mode| mode.current == NVML_DEVICE_MIG_ENABLE)
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "MIG enabled, leaving GPU stats to nvidia-smi",
+                ));
+            }
+            let memory_info = device.memory_info().ok();
+            readings.push(DeviceReading {
+                index,
+                uuid: device.uuid().ok(),
+                bus_id: device.pci_info().ok().map(|pci_info| pci_info.bus_id),
+                usage_percent: device.utilization_rates().ok().map(|rates| rates.gpu),
+                memory_used_bytes: memory_info.as_ref().map(|info| info.used),
+                memory_total_bytes: memory_info.as_ref().map(|info| info.total),
+                temperature_c: device.temperature(TemperatureSensor::Gpu).ok(),
+                power_mw: device.power_usage().ok(),
+            });
+        }
+        Ok(combine_readings(&readings))
+    }
+}
+
+/// What NVML had to say about one GPU; a `None` is a value it couldn't get
+struct DeviceReading {
+    index: u32,
+    uuid: Option<String>,
+    bus_id: Option<String>,
+    usage_percent: Option<u32>,
+    memory_used_bytes: Option<u64>,
+    memory_total_bytes: Option<u64>,
+    temperature_c: Option<u32>,
+    power_mw: Option<u32>,
+}
+
+/// Sum up the GPUs the same way as from nvidia-smi's output, in the same units, counting a GPU
+/// listed more than once at the same PCI bus ID only once
+fn combine_readings(readings: &[DeviceReading]) -> GpuStats {
+    let mut gpu_stats = GpuStats {
+        num_gpus: 0,
+        gpu_usage: 0.0,
+        memory_usage_kb: 0,
+        memory_total_kb: 0,
+        gpus: vec![],
+        memory_usage_mib: 0,
+        memory_total_mib: 0,
+        temperature_c: None,
+        power_watts: None,
+        unparsed_fields: 0,
+    };
+    let mut seen_bus_ids = HashSet::new();
+    for reading in readings {
+        if let Some(bus_id) = &reading.bus_id
+            && !seen_bus_ids.insert(normalize_pci_bus_id(bus_id))
+        {
+            debug!(
+                "Skipping GPU {} at an already counted PCI bus ID {bus_id}",
+                reading.index
+            );
+            continue;
+        }
+        gpu_stats.num_gpus += 1;
+        let usage = reading.usage_percent.map(|usage| usage as f64 / 100.0);
+        let memory_usage_kb = reading.memory_used_bytes.map(|bytes| bytes / BYTES_PER_KIB);
+        let memory_total_kb = reading
+            .memory_total_bytes
+            .map(|bytes| bytes / BYTES_PER_KIB);
+        gpu_stats.unparsed_fields += [
+            usage.is_none(),
+            memory_usage_kb.is_none(),
+            memory_total_kb.is_none(),
+        ]
+        .into_iter()
+        .filter(|missing| *missing)
+        .count() as u32;
+
+        gpu_stats.gpu_usage += usage.unwrap_or(0.0);
+        gpu_stats.memory_usage_kb += memory_usage_kb.unwrap_or(0);
+        gpu_stats.memory_total_kb += memory_total_kb.unwrap_or(0);
+        if let Some(temperature_c) = reading.temperature_c {
+            let temperature_c = temperature_c as f64;
+            gpu_stats.temperature_c = Some(
+                gpu_stats
+                    .temperature_c
+                    .map_or(temperature_c, |max| max.max(temperature_c)),
+            );
+        }
+        if let Some(power_mw) = reading.power_mw {
+            gpu_stats.power_watts =
+                Some(gpu_stats.power_watts.unwrap_or(0.0) + power_mw as f64 / 1000.0);
+        }
+        gpu_stats.gpus.push(GpuUsage {
+            index: reading.index,
+            mig_index: None,
+            uuid: reading.uuid.clone(),
+            usage,
+            memory_usage_kb,
+            memory_total_kb,
+        });
+    }
+    gpu_stats.memory_usage_mib = gpu_stats.memory_usage_kb / KIB_PER_MIB;
+    gpu_stats.memory_total_mib = gpu_stats.memory_total_kb / KIB_PER_MIB;
+    gpu_stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine_readings() {
+        let readings = [
+            DeviceReading {
+                index: 0,
+                uuid: Some("GPU-aaaa".to_string()),
+                bus_id: Some("00000000:3B:00.0".to_string()),
+                usage_percent: Some(75),
+                memory_used_bytes: Some(8000 * 1024 * 1024),
+                memory_total_bytes: Some(16000 * 1024 * 1024),
+                temperature_c: Some(64),
+                power_mw: Some(215_500),
+            },
+            DeviceReading {
+                index: 1,
+                uuid: None,
+                bus_id: None,
+                usage_percent: Some(50),
+                memory_used_bytes: Some(4000 * 1024 * 1024),
+                memory_total_bytes: None,
+                temperature_c: Some(81),
+                power_mw: None,
+            },
+        ];
+
+        let gpu_stats = combine_readings(&readings);
+        assert_eq!(gpu_stats.num_gpus, 2);
+        assert_eq!(gpu_stats.gpu_usage, 1.25);
+        // the same KiB as nvidia-smi's MiB * 1024
+        assert_eq!(gpu_stats.memory_usage_kb, 12_288_000);
+        assert_eq!(gpu_stats.memory_total_kb, 16_384_000);
+        assert_eq!(gpu_stats.memory_usage_mib, 12_000);
+        assert_eq!(gpu_stats.memory_total_mib, 16_000);
+        assert_eq!(gpu_stats.temperature_c, Some(81.0));
+        assert_eq!(gpu_stats.power_watts, Some(215.5));
+        assert_eq!(gpu_stats.unparsed_fields, 1);
+        assert_eq!(gpu_stats.gpus[0].uuid.as_deref(), Some("GPU-aaaa"));
+        assert_eq!(gpu_stats.gpus[1].index, 1);
+    }
+
+    #[test]
+    fn test_combine_readings_counts_bus_id_once() {
+        let reading = |index, bus_id: &str| DeviceReading {
+            index,
+            uuid: None,
+            bus_id: Some(bus_id.to_string()),
+            usage_percent: Some(50),
+            memory_used_bytes: Some(1024 * 1024),
+            memory_total_bytes: Some(16 * 1024 * 1024),
+            temperature_c: None,
+            power_mw: None,
+        };
+        let readings = [
+            reading(0, "00000000:3B:00.0"),
+            reading(1, "0000:3b:00.0"), // the same card, as another tool would put it
+            reading(2, "00000000:AF:00.0"),
+        ];
+
+        let gpu_stats = combine_readings(&readings);
+        assert_eq!(gpu_stats.num_gpus, 2);
+        assert_eq!(gpu_stats.gpu_usage, 1.0);
+        assert_eq!(gpu_stats.memory_total_mib, 32);
+        assert_eq!(gpu_stats.gpus[1].index, 2);
+    }
+}
@@ -1,19 +1,33 @@
 use crate::stats::CgroupVersion;
 use crate::stats::cgroup_v1::CgroupV1MountPoints;
-use std::io;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
 use std::path::{Path, PathBuf};
+use tracing::debug;
 
 const MOUNT_POINT_INDEX: usize = 1; // ... in /proc/mounts
 const FILESYSTEM_TYPE_INDEX: usize = 2; // ... in /proc/mounts
 const FILESYSTEM_OPTIONS_INDEX: usize = 3; // ... in /proc/mounts
 
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const CGROUP_ROOT_PREFIX: &str = "/sys/fs/cgroup/";
+// filesystem types that can be found at the conventional cgroup mount points but aren't the hierarchy we want;
+// v1 setups mount a `tmpfs` at the root to hold the per-controller mounts, hybrid setups have v2 in a subdirectory
+const NON_V2_FILESYSTEM_TYPES: [&str; 2] = ["tmpfs", "cgroup"];
+const NON_V1_FILESYSTEM_TYPES: [&str; 2] = ["tmpfs", "cgroup2"];
+
 /// Return the single mount point for the cgroup v2 unified hierarchy.
 ///
 /// This will most frequently return `/sys/fs/cgroup`, but _can_ be different.
 pub fn get_cgroup_v2_mount_point<P: AsRef<Path>>(proc_mounts_path: P) -> io::Result<PathBuf> {
-    let content = std::fs::read_to_string(proc_mounts_path)?;
+    find_cgroup_v2_mount_point(open_mounts(proc_mounts_path)?)
+}
 
-    for line in content.lines() {
+fn find_cgroup_v2_mount_point<R: BufRead>(mounts: R) -> io::Result<PathBuf> {
+    let mut fallback_mount_point = None;
+
+    for line in mounts.lines() {
+        let line = line?;
         let parts: Vec<&str> = line.split_whitespace().collect();
         if parts.len() < FILESYSTEM_TYPE_INDEX + 1 {
             continue;
@@ -22,6 +36,17 @@ pub fn get_cgroup_v2_mount_point<P: AsRef<Path>>(proc_mounts_path: P) -> io::Res
             // with the v2 unified hierarchy, there is a single mount point for all controllers
             return Ok(PathBuf::from(parts[MOUNT_POINT_INDEX]));
         }
+        if parts[MOUNT_POINT_INDEX] == CGROUP_ROOT
+            && !NON_V2_FILESYSTEM_TYPES.contains(&parts[FILESYSTEM_TYPE_INDEX])
+        {
+            // some runtimes bind mount the hierarchy in, and then the type is whatever the source was
+            fallback_mount_point = Some(PathBuf::from(parts[MOUNT_POINT_INDEX]));
+        }
+    }
+
+    if let Some(mount_point) = fallback_mount_point {
+        debug!("No cgroup2 filesystem mounted, falling back to {mount_point:?}");
+        return Ok(mount_point);
     }
 
     Err(io::Error::new(
@@ -34,38 +59,103 @@ pub fn get_cgroup_v2_mount_point<P: AsRef<Path>>(proc_mounts_path: P) -> io::Res
 pub fn get_cgroup_v1_mount_points<P: AsRef<Path>>(
     proc_mounts_path: P,
 ) -> io::Result<CgroupV1MountPoints> {
-    let content = std::fs::read_to_string(proc_mounts_path)?;
+    find_cgroup_v1_mount_points(open_mounts(proc_mounts_path)?)
+}
 
+fn find_cgroup_v1_mount_points<R: BufRead>(mounts: R) -> io::Result<CgroupV1MountPoints> {
     let mut v1_points = CgroupV1MountPoints::default();
+    let mut fallback_points = CgroupV1MountPoints::default();
 
-    for line in content.lines() {
+    for line in mounts.lines() {
+        let line = line?;
         let parts: Vec<&str> = line.split_whitespace().collect();
         if parts.len() < FILESYSTEM_OPTIONS_INDEX + 1 {
             continue;
         }
 
+        let mount_point = parts[MOUNT_POINT_INDEX];
         if parts[FILESYSTEM_TYPE_INDEX] == "cgroup" {
             // the paths may differ, but the controller name(s) are in the options
             // and note that controllers can be co-mounted like `cpu,cpuacct` _usually_ are
-            let mount_point = parts[MOUNT_POINT_INDEX];
             let options_str = parts[FILESYSTEM_OPTIONS_INDEX];
             let options: Vec<&str> = options_str.split(',').collect();
-
-            if options.contains(&"cpu") {
-                v1_points.set_cpu(Some(PathBuf::from(mount_point)));
-            }
-            if options.contains(&"cpuacct") {
-                v1_points.set_cpuacct(Some(PathBuf::from(mount_point)));
-            }
-            if options.contains(&"memory") {
-                v1_points.set_memory(Some(PathBuf::from(mount_point)));
+            set_v1_controller_mount_points(&mut v1_points, &options, mount_point);
+            if has_all_v1_controllers(&v1_points) {
+                // no need to go through the rest of what can be thousands of mounts on a busy node
+                return Ok(v1_points);
             }
+        } else if let Some(dir_name) = mount_point.strip_prefix(CGROUP_ROOT_PREFIX)
+            && !NON_V1_FILESYSTEM_TYPES.contains(&parts[FILESYSTEM_TYPE_INDEX])
+        {
+            // bind mounted controllers don't have the controllers in the options,
+            // but by convention the directory is named after them e.g. `/sys/fs/cgroup/cpu,cpuacct`
+            let controllers: Vec<&str> = dir_name.split(',').collect();
+            set_v1_controller_mount_points(&mut fallback_points, &controllers, mount_point);
         }
     }
 
+    if v1_points.cpu().is_none() && v1_points.cpuacct().is_none() && v1_points.memory().is_none() {
+        debug!("No cgroup v1 filesystems mounted, falling back to mounts under {CGROUP_ROOT}");
+        return Ok(fallback_points);
+    }
+
     Ok(v1_points)
 }
 
+fn has_all_v1_controllers(v1_points: &CgroupV1MountPoints) -> bool {
+    v1_points.cpu().is_some()
+        && v1_points.cpuacct().is_some()
+        && v1_points.cpuset().is_some()
+        && v1_points.memory().is_some()
+        && v1_points.pids().is_some()
+}
+
+/// `/proc/mounts` can be big on nodes running lots of containers, so it's read line by line
+/// rather than all at once, and the readers stop as soon as they've found what they need
+fn open_mounts<P: AsRef<Path>>(proc_mounts_path: P) -> io::Result<BufReader<File>> {
+    Ok(BufReader::new(File::open(proc_mounts_path)?))
+}
+
+fn set_v1_controller_mount_points(
+    v1_points: &mut CgroupV1MountPoints,
+    controllers: &[&str],
+    mount_point: &str,
+) {
+    if controllers.contains(&"cpu") {
+        v1_points.set_cpu(Some(PathBuf::from(mount_point)));
+    }
+    if controllers.contains(&"cpuacct") {
+        v1_points.set_cpuacct(Some(PathBuf::from(mount_point)));
+    }
+    if controllers.contains(&"cpuset") {
+        v1_points.set_cpuset(Some(PathBuf::from(mount_point)));
+    }
+    if controllers.contains(&"memory") {
+        v1_points.set_memory(Some(PathBuf::from(mount_point)));
+    }
+    if controllers.contains(&"pids") {
+        v1_points.set_pids(Some(PathBuf::from(mount_point)));
+    }
+}
+
+/// Whether the root filesystem is mounted read-only, based on the `/` entry in `/proc/mounts`.
+pub fn is_rootfs_readonly<P: AsRef<Path>>(proc_mounts_path: P) -> io::Result<bool> {
+    // a later mount on the same path hides the earlier ones, so the last `/` is the one we see
+    // and the whole file has to be read
+    let mut root_options = None;
+    for line in open_mounts(proc_mounts_path)?.lines() {
+        let line = line?;
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() > FILESYSTEM_OPTIONS_INDEX && parts[MOUNT_POINT_INDEX] == "/" {
+            root_options = Some(parts[FILESYSTEM_OPTIONS_INDEX].to_string());
+        }
+    }
+    let root_options = root_options
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No root mount found"))?;
+
+    Ok(root_options.split(',').any(|option| option == "ro"))
+}
+
 /// Detect the cgroup version(s) of a process based on `/proc/[self|pid]/cgroup`.
 ///
 /// Mostly used with the `/proc/self/cgroup`, but support other processes with `/proc/[pid]/cgroup` as well.
@@ -116,12 +206,111 @@ pub fn detect_cgroup_version<P: AsRef<Path>>(self_cgroup_path: P) -> io::Result<
     }
 }
 
+/// Return the cgroup v2 path of a process from `/proc/[self|pid]/cgroup`, relative to the hierarchy root.
+///
+/// This is `/` when the process has its own cgroup namespace, as is usual in containers.
+pub fn get_cgroup_v2_self_path<P: AsRef<Path>>(self_cgroup_path: P) -> io::Result<PathBuf> {
+    let content = std::fs::read_to_string(self_cgroup_path)?;
+
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(PathBuf::from)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No cgroup v2 entry in /proc/[self|pid]/cgroup",
+            )
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Write;
+    use std::cell::Cell;
+    use std::io::{Cursor, Read, Write};
+    use std::rc::Rc;
     use tempfile::NamedTempFile;
 
+    /// A reader that keeps count of how many bytes have been read from it
+    struct CountingReader {
+        inner: Cursor<String>,
+        bytes_read: Rc<Cell<usize>>,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.bytes_read.set(self.bytes_read.get() + n);
+            Ok(n)
+        }
+    }
+
+    /// Mounts like a dense container host has, with the interesting lines in the middle
+    fn busy_node_mounts(interesting_lines: &str) -> String {
+        let irrelevant_mounts = |range: std::ops::Range<usize>| {
+            range
+                .map(|i| {
+                    let task_dir =
+                        format!("/run/containerd/io.containerd.runtime.v2.task/k8s.io/{i:064x}");
+                    format!("overlay {task_dir}/rootfs overlay rw,relatime 0 0\n")
+                })
+                .collect::<String>()
+        };
+        irrelevant_mounts(0..2000) + interesting_lines + &irrelevant_mounts(2000..4000)
+    }
+
+    fn read_with_counting<T>(
+        content: &str,
+        find: impl FnOnce(BufReader<CountingReader>) -> io::Result<T>,
+    ) -> io::Result<(T, usize)> {
+        let bytes_read = Rc::new(Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(content.to_string()),
+            bytes_read: bytes_read.clone(),
+        };
+        let found = find(BufReader::new(reader))?;
+        Ok((found, bytes_read.get()))
+    }
+
+    #[test]
+    fn test_v2_mount_point_stops_reading_once_found() -> io::Result<()> {
+        let content = busy_node_mounts(
+            "cgroup /sys/fs/cgroup cgroup2 ro,nosuid,nodev,noexec,relatime,nsdelegate 0 0\n",
+        );
+
+        let (mount_point, bytes_read) = read_with_counting(&content, find_cgroup_v2_mount_point)?;
+        assert_eq!(mount_point, PathBuf::from("/sys/fs/cgroup"));
+        assert!(
+            bytes_read < content.len() * 3 / 4,
+            "{bytes_read}/{}",
+            content.len()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_v1_mount_points_stop_reading_once_found() -> io::Result<()> {
+        let content = busy_node_mounts(
+            "\
+cgroup /sys/fs/cgroup/cpu,cpuacct cgroup ro,nosuid,nodev,noexec,relatime,cpu,cpuacct 0 0
+cgroup /sys/fs/cgroup/cpuset cgroup ro,nosuid,nodev,noexec,relatime,cpuset 0 0
+cgroup /sys/fs/cgroup/memory cgroup ro,nosuid,nodev,noexec,relatime,memory 0 0
+cgroup /sys/fs/cgroup/pids cgroup ro,nosuid,nodev,noexec,relatime,pids 0 0
+",
+        );
+
+        let (mp, bytes_read) = read_with_counting(&content, find_cgroup_v1_mount_points)?;
+        assert_eq!(*mp.memory(), Some("/sys/fs/cgroup/memory".into()));
+        assert_eq!(*mp.pids(), Some("/sys/fs/cgroup/pids".into()));
+        assert!(
+            bytes_read < content.len() * 3 / 4,
+            "{bytes_read}/{}",
+            content.len()
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_v2_mount_point() -> io::Result<()> {
         let v2_content = "\
@@ -178,6 +367,66 @@ tmpfs /dev tmpfs rw,nosuid,size=65536k,mode=755,inode64 0 0";
         Ok(())
     }
 
+    #[test]
+    fn test_v2_bind_mounted_mount_point() -> io::Result<()> {
+        let bind_mounted_content = "\
+overlay / overlay rw,relatime 0 0
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+overlay /sys/fs/cgroup overlay ro,relatime,lowerdir=/var/lib/runtime/cgroup 0 0
+tmpfs /dev tmpfs rw,nosuid,size=65536k,mode=755,inode64 0 0";
+
+        let mut bind_mounted_file = NamedTempFile::new()?;
+        bind_mounted_file.write_all(bind_mounted_content.as_bytes())?;
+
+        let mount_point = get_cgroup_v2_mount_point(bind_mounted_file)?;
+        assert_eq!(mount_point, PathBuf::from("/sys/fs/cgroup"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_v2_prefers_cgroup2_over_fallback() -> io::Result<()> {
+        let content = "\
+overlay /sys/fs/cgroup overlay ro,relatime 0 0
+cgroup2 /sys/fs/cgroup/unified cgroup2 rw,nosuid,nodev,noexec,relatime 0 0";
+
+        let mut file = NamedTempFile::new()?;
+        file.write_all(content.as_bytes())?;
+
+        let mount_point = get_cgroup_v2_mount_point(file)?;
+        assert_eq!(mount_point, PathBuf::from("/sys/fs/cgroup/unified"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_v2_fallback_ignores_v1_tmpfs_root() -> io::Result<()> {
+        let content = "\
+tmpfs /sys/fs/cgroup tmpfs rw,nosuid,nodev,noexec,relatime,mode=755 0 0
+cgroup /sys/fs/cgroup/memory cgroup ro,nosuid,nodev,noexec,relatime,memory 0 0";
+
+        let mut file = NamedTempFile::new()?;
+        file.write_all(content.as_bytes())?;
+
+        assert!(get_cgroup_v2_mount_point(file).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_v1_bind_mounted_mount_points() -> io::Result<()> {
+        let bind_mounted_content = "\
+tmpfs /sys/fs/cgroup tmpfs rw,nosuid,nodev,noexec,relatime,mode=755 0 0
+/dev/sda1 /sys/fs/cgroup/cpu,cpuacct ext4 ro,relatime 0 0
+/dev/sda1 /sys/fs/cgroup/memory ext4 ro,relatime 0 0";
+
+        let mut bind_mounted_file = NamedTempFile::new()?;
+        bind_mounted_file.write_all(bind_mounted_content.as_bytes())?;
+
+        let mp = get_cgroup_v1_mount_points(bind_mounted_file)?;
+        assert_eq!(*mp.cpu(), Some("/sys/fs/cgroup/cpu,cpuacct".into()));
+        assert_eq!(*mp.cpuacct(), Some("/sys/fs/cgroup/cpu,cpuacct".into()));
+        assert_eq!(*mp.memory(), Some("/sys/fs/cgroup/memory".into()));
+        Ok(())
+    }
+
     #[test]
     fn test_v1_mount_points() -> io::Result<()> {
         let v1_content = "\
@@ -225,6 +474,34 @@ tmpfs /sys/firmware tmpfs ro,relatime 0 0";
         assert_eq!(*mp.cpu(), Some("/sys/fs/cgroup/cpu,cpuacct".into()));
         assert_eq!(*mp.cpuacct(), Some("/sys/fs/cgroup/cpu,cpuacct".into()));
         assert_eq!(*mp.memory(), Some("/sys/fs/cgroup/memory".into()));
+        assert_eq!(*mp.pids(), Some("/sys/fs/cgroup/pids".into()));
+        assert_eq!(*mp.cpuset(), Some("/sys/fs/cgroup/cpuset".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rootfs_readonly() -> io::Result<()> {
+        let rw_content = "\
+overlay / overlay rw,relatime,lowerdir=/var/lib/containerd/snapshots/3753/fs,upperdir=/var/lib/containerd/snapshots/3760/fs 0 0
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+cgroup2 /sys/fs/cgroup cgroup2 ro,nosuid,nodev,noexec,relatime 0 0";
+        let mut rw_file = NamedTempFile::new()?;
+        rw_file.write_all(rw_content.as_bytes())?;
+        assert!(!is_rootfs_readonly(rw_file)?);
+
+        // e.g. `readOnlyRootFilesystem: true` in the container's security context
+        let ro_content = "\
+overlay / overlay ro,relatime,lowerdir=/var/lib/containerd/snapshots/3753/fs,upperdir=/var/lib/containerd/snapshots/3760/fs 0 0
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+tmpfs /tmp tmpfs rw,nosuid,nodev 0 0";
+        let mut ro_file = NamedTempFile::new()?;
+        ro_file.write_all(ro_content.as_bytes())?;
+        assert!(is_rootfs_readonly(ro_file)?);
+
+        let no_root_content = "proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0";
+        let mut no_root_file = NamedTempFile::new()?;
+        no_root_file.write_all(no_root_content.as_bytes())?;
+        assert!(is_rootfs_readonly(no_root_file).is_err());
         Ok(())
     }
 
@@ -311,4 +588,32 @@ tmpfs /dev tmpfs rw,nosuid,size=65536k,mode=755 0 0";
     fn test_detect_probably_not_cgroup_managed() {
         assert!(detect_cgroup_version("/this/do/not/exist").is_err());
     }
+
+    #[test]
+    fn test_v2_self_path() -> io::Result<()> {
+        let mut temp_file = NamedTempFile::new()?;
+        writeln!(
+            temp_file,
+            "0::/kubepods.slice/kubepods-pod1234.slice/cri-containerd-abcd.scope"
+        )?;
+
+        let self_path = get_cgroup_v2_self_path(temp_file.path())?;
+        assert_eq!(
+            self_path,
+            PathBuf::from("/kubepods.slice/kubepods-pod1234.slice/cri-containerd-abcd.scope")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_v2_self_path_v1_only() -> io::Result<()> {
+        let mut temp_file = NamedTempFile::new()?;
+        writeln!(
+            temp_file,
+            "4:memory:/docker/abcd\n3:cpu,cpuacct:/docker/abcd"
+        )?;
+
+        assert!(get_cgroup_v2_self_path(temp_file.path()).is_err());
+        Ok(())
+    }
 }
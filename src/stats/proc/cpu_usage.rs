@@ -1,13 +1,16 @@
-use crate::stats::CpuUsageValue;
 use crate::stats::proc::ProcProvider;
+use crate::stats::{CpuTimeReading, CpuUsageValue};
 use std::io;
 use std::time::Duration;
 use tracing::{debug, warn};
 
 /// Get CPU usage (% of all available CPUS) from the `/proc` filesystem (host-wide)
+///
+/// With `exclude_steal`, time the hypervisor gave to other guests counts as vacant too.
 pub fn get_cpu_usage<R: ProcProvider>(
     provider: &R,
     sample_interval: Duration,
+    exclude_steal: bool,
 ) -> io::Result<CpuUsageValue> {
     // CPU measurements from `procfs` are in "jiffies".
     // Jiffy "duration" depends on the kernel configuration, so we sidestep needing to resolve that
@@ -20,22 +23,107 @@ pub fn get_cpu_usage<R: ProcProvider>(
     std::thread::sleep(sample_interval);
     let current = get_total_cpu_jiffies(provider)?;
 
-    let cpu_usage = calculate_cpu_usage(&initial, &current);
+    let cpu_usage = calculate_cpu_usage(&initial, &current, exclude_steal);
+    debug!("Using proc for CPU usage");
     Ok(CpuUsageValue::FromProc(cpu_usage))
 }
 
+/// Get the usage of each CPU core (1.0 being the core fully busy) from the `/proc` filesystem (host-wide)
+///
+/// Cores are in the order `/proc/stat` lists them; a core that went offline or came online during
+/// the sample has no usage to report and is left out.
+pub fn get_per_core_cpu_usage<R: ProcProvider>(
+    provider: &R,
+    sample_interval: Duration,
+    exclude_steal: bool,
+) -> io::Result<Vec<f64>> {
+    let initial = get_per_core_cpu_jiffies(provider)?;
+    std::thread::sleep(sample_interval);
+    let current = get_per_core_cpu_jiffies(provider)?;
+
+    let per_core_usage = current
+        .iter()
+        .filter_map(|(core, current_jiffies)| {
+            let (_, initial_jiffies) = initial.iter().find(|(other, _)| other == core)?;
+            Some(calculate_cpu_usage(
+                initial_jiffies,
+                current_jiffies,
+                exclude_steal,
+            ))
+        })
+        .collect();
+    debug!("Using proc for per-core CPU usage");
+    Ok(per_core_usage)
+}
+
+/// Get the CPU time the host has spent busy from the `/proc` filesystem
+///
+/// With `exclude_steal`, time the hypervisor gave to other guests isn't counted as busy, as with the usage.
+pub fn get_cpu_time<R: ProcProvider>(
+    provider: &R,
+    exclude_steal: bool,
+) -> io::Result<CpuTimeReading> {
+    // From: https://man7.org/linux/man-pages/man5/proc_stat.5.html
+    // user, nice, system, irq and softirq; the guest fields are already included in user and nice,
+    // and idle and iowait are time the CPUs weren't running anything for us
+    const BUSY_IDXS: [usize; 5] = [0, 1, 2, 5, 6];
+    const STEAL_IDX: usize = 7; // missing before Linux 2.6.11, which is as good as none stolen
+
+    let jiffies = get_total_cpu_jiffies(provider)?;
+    let steal_jiffies = if exclude_steal {
+        0
+    } else {
+        jiffies.get(STEAL_IDX).copied().unwrap_or(0)
+    };
+    let busy_jiffies = BUSY_IDXS
+        .iter()
+        .map(|&idx| jiffies.get(idx).copied())
+        .sum::<Option<u64>>()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Expected at least 7 CPU fields in /proc/stat, got {}",
+                    jiffies.len()
+                ),
+            )
+        })?;
+    Ok(CpuTimeReading::now(jiffies_to_duration(
+        busy_jiffies + steal_jiffies,
+        clock_ticks_per_sec()?,
+    )))
+}
+
+/// The length of a jiffy as reported in `/proc/stat`, i.e. `USER_HZ`
+fn clock_ticks_per_sec() -> io::Result<u64> {
+    // SAFETY: sysconf has no preconditions, it only returns -1 for unknown names
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    u64::try_from(ticks)
+        .ok()
+        .filter(|&ticks| ticks > 0)
+        .ok_or_else(|| io::Error::other("Failed to get the clock tick rate"))
+}
+
+fn jiffies_to_duration(jiffies: u64, ticks_per_sec: u64) -> Duration {
+    let whole_secs = jiffies / ticks_per_sec;
+    let remainder_nanos = (jiffies % ticks_per_sec) * 1_000_000_000 / ticks_per_sec;
+    Duration::new(whole_secs, remainder_nanos as u32)
+}
+
 fn get_total_cpu_jiffies<R: ProcProvider>(provider: &R) -> io::Result<Vec<u64>> {
     let lines = provider.get_proc_stat()?;
-    if lines.is_empty() {
+
+    // we only care about the `cpu` line, which is the total CPU stats, the `cpuN` lines being
+    // per core; it comes first, but find it by name like every other stat
+    let Some(total_cpu_line) = lines
+        .iter()
+        .find(|line| line.split_whitespace().next() == Some("cpu"))
+    else {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
-            "No stat data from proc provider",
+            "No total CPU line in stat data from proc provider",
         ));
-    }
-
-    // we only care about the first line, which is the total CPU stats
-    // as we don't report CPU stats per core
-    let total_cpu_line = &lines[0];
+    };
     let jiffies: Vec<u64> = total_cpu_line
         .split_whitespace()
         .skip(1) // skip the "cpu*" prefix
@@ -45,11 +133,39 @@ fn get_total_cpu_jiffies<R: ProcProvider>(provider: &R) -> io::Result<Vec<u64>>
     Ok(jiffies)
 }
 
+/// The jiffies of each `cpuN` line, keyed by `N`
+fn get_per_core_cpu_jiffies<R: ProcProvider>(provider: &R) -> io::Result<Vec<(u32, Vec<u64>)>> {
+    let lines = provider.get_proc_stat()?;
+
+    let per_core_jiffies: Vec<(u32, Vec<u64>)> = lines
+        .iter()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let core = fields.next()?.strip_prefix("cpu")?.parse::<u32>().ok()?;
+            let jiffies = fields.filter_map(|s| s.parse::<u64>().ok()).collect();
+            Some((core, jiffies))
+        })
+        .collect();
+    if per_core_jiffies.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No per-core CPU lines in stat data from proc provider",
+        ));
+    }
+
+    Ok(per_core_jiffies)
+}
+
 /// Calculate CPU usage based on two sequential readings from `/proc/stat`
-fn calculate_cpu_usage(initial_jiffies: &[u64], current_jiffies: &[u64]) -> f64 {
+fn calculate_cpu_usage(
+    initial_jiffies: &[u64],
+    current_jiffies: &[u64],
+    exclude_steal: bool,
+) -> f64 {
     // From: https://man7.org/linux/man-pages/man5/proc_stat.5.html
     const IDLE_IDX: usize = 3; // idle is the 4th field
     const IOWAIT_IDX: usize = 4; // iowait is the 5th field
+    const STEAL_IDX: usize = 7; // steal is the 8th field, missing before Linux 2.6.11
     const MIN_REQUIRED_LEN: usize = IOWAIT_IDX + 1;
 
     if initial_jiffies.len() < MIN_REQUIRED_LEN {
@@ -87,12 +203,19 @@ fn calculate_cpu_usage(initial_jiffies: &[u64], current_jiffies: &[u64]) -> f64
         return 0.0;
     }
 
-    // calculate "vacant" time (idle + iowait)
-    let initial_vacancy = initial_jiffies[IDLE_IDX] + initial_jiffies[IOWAIT_IDX];
-    let current_vacancy = current_jiffies[IDLE_IDX] + current_jiffies[IOWAIT_IDX];
+    // calculate "vacant" time (idle + iowait, and steal if asked to)
+    let vacancy = |jiffies: &[u64]| {
+        let steal = if exclude_steal {
+            jiffies.get(STEAL_IDX).copied().unwrap_or(0)
+        } else {
+            0
+        };
+        jiffies[IDLE_IDX] + jiffies[IOWAIT_IDX] + steal
+    };
+    let initial_vacancy = vacancy(initial_jiffies);
+    let current_vacancy = vacancy(current_jiffies);
     let vacant_delta = current_vacancy.saturating_sub(initial_vacancy);
 
-    debug!("Using proc for CPU usage");
     1.0 - (vacant_delta as f64 / total_delta as f64)
 }
 
@@ -100,6 +223,7 @@ fn calculate_cpu_usage(initial_jiffies: &[u64], current_jiffies: &[u64]) -> f64
 mod tests {
     use super::*;
     use crate::stats::proc::MockProcProvider;
+    use mockall::Sequence;
 
     #[test]
     fn test_get_total_cpu_jiffies() {
@@ -123,7 +247,7 @@ mod tests {
         let initial = vec![100, 200, 300, 400, 500]; // = 1500 jiffies
         let current = vec![110, 220, 330, 440, 550]; // = 1650 jiffies
 
-        let usage = calculate_cpu_usage(&initial, &current);
+        let usage = calculate_cpu_usage(&initial, &current, false);
 
         // 1650 - 1500 =    150 total time spent (delta)
         //  440 - 400  =     40 time spent idle (delta)
@@ -132,4 +256,156 @@ mod tests {
         //  1.0 - 0.6 =     0.4 usage%
         assert_eq!(usage, 0.4);
     }
+
+    #[test]
+    fn test_calculate_cpu_usage_excluding_steal() {
+        //                              | idle        | steal
+        //                              v    v iowait v
+        let initial = vec![100, 0, 100, 400, 0, 0, 0, 1000];
+        let current = vec![125, 0, 125, 400, 0, 0, 0, 1150]; // = 200 jiffies more
+
+        // 150 of the 200 jiffies were stolen by the hypervisor, which otherwise looks like usage
+        assert_eq!(calculate_cpu_usage(&initial, &current, false), 1.0);
+        // leaving 50 jiffies of actual work
+        assert_eq!(calculate_cpu_usage(&initial, &current, true), 0.25);
+    }
+
+    #[test]
+    fn test_calculate_cpu_usage_excluding_steal_without_steal_field() {
+        let initial = vec![100, 200, 300, 400, 500];
+        let current = vec![110, 220, 330, 440, 550];
+
+        assert_eq!(calculate_cpu_usage(&initial, &current, true), 0.4);
+    }
+
+    #[test]
+    fn test_jiffies_to_duration() {
+        assert_eq!(jiffies_to_duration(250, 100), Duration::from_millis(2500));
+        assert_eq!(jiffies_to_duration(250, 250), Duration::from_secs(1));
+        assert_eq!(jiffies_to_duration(1, 300), Duration::from_nanos(3_333_333));
+        assert_eq!(jiffies_to_duration(0, 100), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_cpu_seconds_from_jiffies_delta() {
+        let ticks_per_sec = 100; // the USER_HZ of practically every Linux build
+        let previous = CpuTimeReading::now(jiffies_to_duration(1_344_392, ticks_per_sec));
+        let current = CpuTimeReading::now(jiffies_to_duration(1_344_842, ticks_per_sec));
+
+        // 450 jiffies at 100 Hz is 4.5 CPU-seconds, e.g. 0.9 CPUs busy over a 5 second interval
+        assert_eq!(current.cpu_seconds_since(&previous), 4.5);
+        assert_eq!(previous.cpu_seconds_since(&current), 0.0);
+    }
+
+    #[test]
+    fn test_get_cpu_time_counts_busy_jiffies() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider.expect_get_proc_stat().returning(|| {
+            Ok(vec![
+                "cpu  1000 200 300 13457001 28111 40 60 500 0 0".to_string(),
+            ])
+        });
+
+        let reading = get_cpu_time(&mock_provider, false)?;
+        let expected_jiffies = 1000 + 200 + 300 + 40 + 60 + 500; // idle and iowait left out
+        assert_eq!(
+            reading.cpu_time,
+            jiffies_to_duration(expected_jiffies, clock_ticks_per_sec()?)
+        );
+
+        let reading = get_cpu_time(&mock_provider, true)?;
+        let expected_jiffies = 1000 + 200 + 300 + 40 + 60; // and steal too
+        assert_eq!(
+            reading.cpu_time,
+            jiffies_to_duration(expected_jiffies, clock_ticks_per_sec()?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_total_cpu_jiffies_finds_cpu_line_by_name() {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider.expect_get_proc_stat().returning(|| {
+            Ok(vec![
+                "cpu0 198607 6779 63175 1870456 4023 0 4291 0 0 0".to_string(),
+                "ctxt 146138886".to_string(),
+                "cpu  1016173 37036 291183 13457001 28111 0 9511 0 0 0".to_string(),
+            ])
+        });
+
+        let jiffies = get_total_cpu_jiffies(&mock_provider).unwrap();
+        assert_eq!(jiffies[0], 1016173);
+    }
+
+    #[test]
+    fn test_get_per_core_cpu_usage() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        let mut sequence = Sequence::new();
+        for (cpu0, cpu1) in [
+            ("100 0 100 200", "100 0 100 200"),
+            ("200 0 100 200", "100 0 100 300"),
+        ] {
+            mock_provider
+                .expect_get_proc_stat()
+                .times(1)
+                .in_sequence(&mut sequence)
+                .returning(move || {
+                    Ok(vec![
+                        "cpu  200 0 200 400 0 0 0 0 0 0".to_string(),
+                        format!("cpu0 {cpu0} 0 0 0 0 0 0"),
+                        format!("cpu1 {cpu1} 0 0 0 0 0 0"),
+                        "intr 1234".to_string(),
+                    ])
+                });
+        }
+
+        let usage = get_per_core_cpu_usage(&mock_provider, Duration::ZERO, false)?;
+        // cpu0 was busy for all of its 100 jiffies, cpu1 idle for all of them
+        assert_eq!(usage, vec![1.0, 0.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_per_core_cpu_usage_skips_cores_missing_from_a_reading() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        let mut sequence = Sequence::new();
+        for lines in [
+            vec!["cpu0 100 0 100 200 0", "cpu1 100 0 100 200 0"],
+            vec!["cpu1 150 0 150 300 0"],
+        ] {
+            mock_provider
+                .expect_get_proc_stat()
+                .times(1)
+                .in_sequence(&mut sequence)
+                .returning(move || Ok(lines.iter().map(|line| line.to_string()).collect()));
+        }
+
+        let usage = get_per_core_cpu_usage(&mock_provider, Duration::ZERO, false)?;
+        assert_eq!(usage, vec![0.5]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_per_core_cpu_jiffies_without_core_lines() {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider.expect_get_proc_stat().returning(|| {
+            Ok(vec![
+                "cpu  1016173 37036 291183 13457001 28111 0 9511 0 0 0".to_string(),
+            ])
+        });
+
+        let result = get_per_core_cpu_jiffies(&mock_provider);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_get_total_cpu_jiffies_without_cpu_line() {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider
+            .expect_get_proc_stat()
+            .returning(|| Ok(vec!["ctxt 146138886".to_string()]));
+
+        let result = get_total_cpu_jiffies(&mock_provider);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
 }
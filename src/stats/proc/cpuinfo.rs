@@ -0,0 +1,117 @@
+use crate::stats::CpuInfo;
+use crate::stats::proc::ProcProvider;
+use std::collections::HashSet;
+use std::io;
+
+/// Get the CPU model and core counts from `/proc/cpuinfo` (host-wide)
+pub fn get_cpu_info<R: ProcProvider>(provider: &R) -> io::Result<CpuInfo> {
+    // a block of `key<tabs>: value` lines per logical CPU, each starting with `processor`;
+    // `physical id` and `core id` are missing e.g. on ARM, where the model name is missing too
+    let lines = provider.get_proc_cpuinfo()?;
+
+    let mut model_name = None;
+    let mut logical_cores = 0;
+    let mut physical_id = None;
+    let mut cores = HashSet::new();
+    for line in &lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "processor" => {
+                logical_cores += 1;
+                physical_id = None;
+            }
+            "model name" if model_name.is_none() => model_name = Some(value.to_string()),
+            "physical id" => physical_id = Some(value.to_string()),
+            // hyperthreads of the same core share a core id within the socket
+            "core id" => {
+                cores.insert((physical_id.clone(), value.to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    if logical_cores == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No processors in /proc/cpuinfo",
+        ));
+    }
+    Ok(CpuInfo {
+        model_name,
+        logical_cores,
+        physical_cores: (!cores.is_empty()).then_some(cores.len()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::proc::MockProcProvider;
+
+    fn cpuinfo_block(processor: u32, physical_id: u32, core_id: u32) -> Vec<String> {
+        vec![
+            format!("processor\t: {processor}"),
+            "vendor_id\t: GenuineIntel".to_string(),
+            "model name\t: Intel(R) Xeon(R) Gold 6154 CPU @ 3.00GHz".to_string(),
+            format!("physical id\t: {physical_id}"),
+            "siblings\t: 4".to_string(),
+            format!("core id\t\t: {core_id}"),
+            "cpu cores\t: 2".to_string(),
+            "flags\t\t: fpu vme de pse tsc msr pae mce cx8".to_string(),
+            "".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_get_cpu_info() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider.expect_get_proc_cpuinfo().returning(|| {
+            // two sockets with two hyperthreaded cores each
+            Ok([
+                cpuinfo_block(0, 0, 0),
+                cpuinfo_block(1, 0, 1),
+                cpuinfo_block(2, 0, 0),
+                cpuinfo_block(3, 0, 1),
+                cpuinfo_block(4, 1, 0),
+                cpuinfo_block(5, 1, 1),
+                cpuinfo_block(6, 1, 0),
+                cpuinfo_block(7, 1, 1),
+            ]
+            .concat())
+        });
+
+        let cpu_info = get_cpu_info(&mock_provider)?;
+        assert_eq!(
+            cpu_info.model_name.as_deref(),
+            Some("Intel(R) Xeon(R) Gold 6154 CPU @ 3.00GHz")
+        );
+        assert_eq!(cpu_info.logical_cores, 8);
+        assert_eq!(cpu_info.physical_cores, Some(4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_cpu_info_without_topology() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider.expect_get_proc_cpuinfo().returning(|| {
+            Ok(vec![
+                "processor\t: 0".to_string(),
+                "BogoMIPS\t: 50.00".to_string(),
+                "CPU implementer\t: 0x41".to_string(),
+                "".to_string(),
+                "processor\t: 1".to_string(),
+                "BogoMIPS\t: 50.00".to_string(),
+                "CPU implementer\t: 0x41".to_string(),
+            ])
+        });
+
+        let cpu_info = get_cpu_info(&mock_provider)?;
+        assert_eq!(cpu_info.model_name, None);
+        assert_eq!(cpu_info.logical_cores, 2);
+        assert_eq!(cpu_info.physical_cores, None);
+        Ok(())
+    }
+}
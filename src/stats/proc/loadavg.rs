@@ -0,0 +1,53 @@
+use crate::stats::LoadAverage;
+use crate::stats::proc::ProcProvider;
+use std::io;
+use tracing::debug;
+
+/// Get the host's 1, 5 and 15 minute load averages from `/proc/loadavg`
+pub fn get_load_average<P: ProcProvider>(provider: &P) -> io::Result<LoadAverage> {
+    let line = provider.get_proc_loadavg()?;
+    parse_loadavg(&line)
+}
+
+/// Parse the load averages from the start of a `/proc/loadavg` line, e.g. `0.52 0.58 0.59 1/1234 56789`
+fn parse_loadavg(line: &str) -> io::Result<LoadAverage> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid /proc/loadavg format: {line:?}"),
+        )
+    };
+    let mut averages = line
+        .split_whitespace()
+        .map(|average| average.parse::<f64>().map_err(|_| invalid()));
+    let mut next = || averages.next().unwrap_or_else(|| Err(invalid()));
+    let load_average = LoadAverage {
+        one: next()?,
+        five: next()?,
+        fifteen: next()?,
+    };
+
+    debug!("Using proc for load average");
+    Ok(load_average)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_loadavg() -> io::Result<()> {
+        let load_average = parse_loadavg("0.52 0.58 0.59 1/1234 56789\n")?;
+        assert_eq!(load_average.one, 0.52);
+        assert_eq!(load_average.five, 0.58);
+        assert_eq!(load_average.fifteen, 0.59);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_loadavg_invalid() {
+        assert!(parse_loadavg("0.52 0.58").is_err());
+        assert!(parse_loadavg("").is_err());
+        assert!(parse_loadavg("a b c 1/1234 56789").is_err());
+    }
+}
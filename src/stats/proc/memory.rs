@@ -1,27 +1,57 @@
 use crate::stats::proc::ProcProvider;
 use std::io;
 
-/// Get currently used and total available memory from the `/proc` filesystem (host-wide)
-pub fn get_memory_usage_and_total_kb<R: ProcProvider>(provider: &R) -> io::Result<(u64, u64)> {
+/// Host-wide memory and swap from `/proc/meminfo`
+pub struct Meminfo {
+    pub memory_usage_kb: u64,
+    pub memory_total_kb: u64,
+    pub swap_usage_kb: Option<u64>,
+    pub swap_total_kb: Option<u64>,
+}
+
+/// Get currently used and total available memory and swap from the `/proc` filesystem (host-wide)
+pub fn get_meminfo<R: ProcProvider>(provider: &R) -> io::Result<Meminfo> {
     let lines = provider.get_proc_meminfo()?;
 
-    let mut memory_total_kb = 0;
-    let mut available_kb = 0;
+    // found or not rather than zero or not, as no swap (or all of it in use) is a real zero
+    let mut memory_total_kb = None;
+    let mut available_kb = None;
+    let mut swap_total_kb = None;
+    let mut swap_free_kb = None;
 
     for line in &lines {
-        if line.starts_with("MemAvailable:") {
-            available_kb = parse_proc_meminfo_value(line);
+        let field = if line.starts_with("MemAvailable:") {
+            &mut available_kb
         } else if line.starts_with("MemTotal:") {
-            memory_total_kb = parse_proc_meminfo_value(line);
-        }
-
-        if memory_total_kb > 0 && available_kb > 0 {
+            &mut memory_total_kb
+        } else if line.starts_with("SwapTotal:") {
+            &mut swap_total_kb
+        } else if line.starts_with("SwapFree:") {
+            &mut swap_free_kb
+        } else {
+            continue;
+        };
+        *field = Some(parse_proc_meminfo_value(line));
+
+        if memory_total_kb.is_some()
+            && available_kb.is_some()
+            && swap_total_kb.is_some()
+            && swap_free_kb.is_some()
+        {
             break;
         }
     }
 
-    let memory_usage_kb = memory_total_kb.saturating_sub(available_kb);
-    Ok((memory_usage_kb, memory_total_kb))
+    let memory_total_kb = memory_total_kb.unwrap_or(0);
+    let swap_usage_kb = swap_total_kb
+        .zip(swap_free_kb)
+        .map(|(total_kb, free_kb)| total_kb.saturating_sub(free_kb));
+    Ok(Meminfo {
+        memory_usage_kb: memory_total_kb.saturating_sub(available_kb.unwrap_or(0)),
+        memory_total_kb,
+        swap_usage_kb,
+        swap_total_kb,
+    })
 }
 
 fn parse_proc_meminfo_value(line: &str) -> u64 {
@@ -49,9 +79,53 @@ mod tests {
             ])
         });
 
-        let (memory_usage_kb, memory_total_kb) = get_memory_usage_and_total_kb(&mock_provider)?;
-        assert_eq!(memory_total_kb, 8048836);
-        assert_eq!(memory_usage_kb, 4029418);
+        let meminfo = get_meminfo(&mock_provider)?;
+        assert_eq!(meminfo.memory_total_kb, 8048836);
+        assert_eq!(meminfo.memory_usage_kb, 4029418);
+        assert_eq!(meminfo.swap_usage_kb, None);
+        assert_eq!(meminfo.swap_total_kb, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_memory_stats_with_swap() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider.expect_get_proc_meminfo().returning(|| {
+            Ok(vec![
+                "MemTotal:        8048836 kB".to_string(),
+                "MemFree:         2000000 kB".to_string(),
+                "MemAvailable:    4019418 kB".to_string(),
+                "Buffers:          123456 kB".to_string(),
+                "Cached:          1234567 kB".to_string(),
+                "SwapCached:         1024 kB".to_string(),
+                "SwapTotal:       2097148 kB".to_string(),
+                "SwapFree:        1572860 kB".to_string(),
+                "Dirty:               128 kB".to_string(),
+            ])
+        });
+
+        let meminfo = get_meminfo(&mock_provider)?;
+        assert_eq!(meminfo.memory_usage_kb, 4029418);
+        assert_eq!(meminfo.swap_total_kb, Some(2097148));
+        assert_eq!(meminfo.swap_usage_kb, Some(524288));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_memory_stats_without_swap() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider.expect_get_proc_meminfo().returning(|| {
+            Ok(vec![
+                "MemTotal:        8048836 kB".to_string(),
+                "MemAvailable:    4019418 kB".to_string(),
+                "SwapTotal:             0 kB".to_string(),
+                "SwapFree:              0 kB".to_string(),
+            ])
+        });
+
+        let meminfo = get_meminfo(&mock_provider)?;
+        assert_eq!(meminfo.swap_total_kb, Some(0));
+        assert_eq!(meminfo.swap_usage_kb, Some(0));
         Ok(())
     }
 
@@ -66,9 +140,9 @@ mod tests {
             ])
         });
 
-        let (memory_usage_kb, memory_total_kb) = get_memory_usage_and_total_kb(&mock_provider)?;
-        assert_eq!(memory_total_kb, 8048836);
-        assert_eq!(memory_usage_kb, 8048836);
+        let meminfo = get_meminfo(&mock_provider)?;
+        assert_eq!(meminfo.memory_total_kb, 8048836);
+        assert_eq!(meminfo.memory_usage_kb, 8048836);
         Ok(())
     }
 
@@ -83,9 +157,9 @@ mod tests {
             ])
         });
 
-        let (memory_usage_kb, memory_total_kb) = get_memory_usage_and_total_kb(&mock_provider)?;
-        assert_eq!(memory_total_kb, 0);
-        assert_eq!(memory_usage_kb, 0);
+        let meminfo = get_meminfo(&mock_provider)?;
+        assert_eq!(meminfo.memory_total_kb, 0);
+        assert_eq!(meminfo.memory_usage_kb, 0);
         Ok(())
     }
 
@@ -96,9 +170,9 @@ mod tests {
             .expect_get_proc_meminfo()
             .returning(|| Ok(vec![]));
 
-        let (memory_usage_kb, memory_total_kb) = get_memory_usage_and_total_kb(&mock_provider)?;
-        assert_eq!(memory_total_kb, 0);
-        assert_eq!(memory_usage_kb, 0);
+        let meminfo = get_meminfo(&mock_provider)?;
+        assert_eq!(meminfo.memory_total_kb, 0);
+        assert_eq!(meminfo.memory_usage_kb, 0);
         Ok(())
     }
 
@@ -112,9 +186,9 @@ mod tests {
             ])
         });
 
-        let (memory_usage_kb, memory_total_kb) = get_memory_usage_and_total_kb(&mock_provider)?;
-        assert_eq!(memory_total_kb, 8000000);
-        assert_eq!(memory_usage_kb, 0);
+        let meminfo = get_meminfo(&mock_provider)?;
+        assert_eq!(meminfo.memory_total_kb, 8000000);
+        assert_eq!(meminfo.memory_usage_kb, 0);
         Ok(())
     }
 
@@ -125,7 +199,7 @@ mod tests {
             .expect_get_proc_meminfo()
             .returning(|| Err(io::Error::new(io::ErrorKind::NotFound, "File not found")));
 
-        assert!(get_memory_usage_and_total_kb(&mock_provider).is_err());
+        assert!(get_meminfo(&mock_provider).is_err());
     }
 
     #[test]
@@ -1,9 +1,21 @@
 mod cpu_usage;
+mod cpuinfo;
+mod loadavg;
 mod memory;
+mod net_dev;
 mod num_cpus;
+mod oom_score;
+mod pid_status;
+mod pressure;
+mod scheduler_counters;
+mod uptime;
+mod vmstat;
 
-use crate::stats::{CpuUsageValue, SystemStatsSource};
-use crate::utils::read_all_lines;
+use crate::stats::{
+    CpuInfo, CpuTimeReading, CpuUsageValue, LoadAverage, Metric, NetworkBytes, OomScore,
+    SchedulerCounters, SourceKind, SwapCounters, SwapUsage, SystemStatsSource,
+};
+use crate::utils::{read_all_lines, read_first_line};
 #[cfg(test)]
 use mockall::automock;
 use std::io::{self};
@@ -14,33 +26,136 @@ use tracing::debug;
 /// A source of system stats that reads values like `/proc` provides.
 pub struct ProcSource<P: ProcProvider> {
     provider: P,
+    exclude_steal: bool,
 }
 
 impl<P: ProcProvider> ProcSource<P> {
     fn new(provider: P) -> Self {
-        Self { provider }
+        Self {
+            provider,
+            exclude_steal: false,
+        }
+    }
+
+    /// Count CPU time stolen by the hypervisor as vacant rather than as usage
+    pub fn excluding_steal(mut self, exclude_steal: bool) -> Self {
+        self.exclude_steal = exclude_steal;
+        self
     }
 }
 
 impl<P: ProcProvider> SystemStatsSource for ProcSource<P> {
+    fn kind(&self) -> SourceKind {
+        SourceKind::Proc
+    }
+
+    fn supports(&self, metric: Metric) -> bool {
+        match metric {
+            Metric::NumCpus
+            | Metric::CpuUsage
+            | Metric::MemoryUsage
+            | Metric::MemoryTotal
+            | Metric::SchedulerCounters
+            | Metric::SwapActivity
+            | Metric::CpuPressure
+            | Metric::LoadAverage
+            | Metric::Uptime
+            | Metric::SwapUsage
+            | Metric::PerCoreCpuUsage
+            | Metric::NetworkThroughput => true,
+            Metric::CpuBurst
+            | Metric::CpuThrottling
+            | Metric::DiskIoLatency
+            | Metric::MajorFaults
+            | Metric::Pids
+            | Metric::MemorySwap
+            | Metric::MemoryPressure
+            | Metric::IoPressure
+            | Metric::DiskThroughput
+            | Metric::Cpuset
+            | Metric::MemoryBreakdown
+            | Metric::OomKills => false,
+        }
+    }
+
     fn get_num_cpus(&self) -> io::Result<f64> {
         num_cpus::get_num_cpus(&self.provider)
     }
 
     fn get_cpu_usage(&self, sample_interval: Duration) -> io::Result<CpuUsageValue> {
-        cpu_usage::get_cpu_usage(&self.provider, sample_interval)
+        cpu_usage::get_cpu_usage(&self.provider, sample_interval, self.exclude_steal)
+    }
+
+    fn get_per_core_cpu_usage(&self, sample_interval: Duration) -> io::Result<Vec<f64>> {
+        cpu_usage::get_per_core_cpu_usage(&self.provider, sample_interval, self.exclude_steal)
+    }
+
+    fn get_cpu_time(&self) -> io::Result<CpuTimeReading> {
+        cpu_usage::get_cpu_time(&self.provider, self.exclude_steal)
     }
 
     fn get_memory_usage_kb(&self) -> io::Result<u64> {
-        let (memory_usage_kb, _) = memory::get_memory_usage_and_total_kb(&self.provider)?;
+        let meminfo = memory::get_meminfo(&self.provider)?;
         debug!("Using proc for memory usage");
-        Ok(memory_usage_kb)
+        Ok(meminfo.memory_usage_kb)
     }
 
     fn get_memory_total_kb(&self) -> io::Result<u64> {
-        let (_, memory_total_kb) = memory::get_memory_usage_and_total_kb(&self.provider)?;
+        let meminfo = memory::get_meminfo(&self.provider)?;
         debug!("Using proc for memory max");
-        Ok(memory_total_kb)
+        Ok(meminfo.memory_total_kb)
+    }
+
+    fn get_swap_usage(&self) -> io::Result<SwapUsage> {
+        let meminfo = memory::get_meminfo(&self.provider)?;
+        let (Some(usage_kb), Some(limit_kb)) = (meminfo.swap_usage_kb, meminfo.swap_total_kb)
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Could not find SwapTotal and SwapFree in /proc/meminfo",
+            ));
+        };
+        debug!("Using proc for swap");
+        Ok(SwapUsage {
+            usage_kb,
+            limit_kb: Some(limit_kb),
+        })
+    }
+
+    fn get_cpu_pressure_some_avg10(&self) -> io::Result<f64> {
+        pressure::get_cpu_pressure_some_avg10(&self.provider)
+    }
+
+    fn get_network_bytes(&self) -> io::Result<NetworkBytes> {
+        net_dev::get_network_bytes(&self.provider)
+    }
+
+    fn get_load_average(&self) -> io::Result<LoadAverage> {
+        loadavg::get_load_average(&self.provider)
+    }
+
+    fn get_uptime_secs(&self) -> io::Result<f64> {
+        uptime::get_uptime_secs(&self.provider)
+    }
+
+    fn get_scheduler_counters(&self) -> io::Result<SchedulerCounters> {
+        scheduler_counters::get_scheduler_counters(&self.provider)
+    }
+
+    fn get_swap_counters(&self) -> io::Result<SwapCounters> {
+        vmstat::get_swap_counters(&self.provider)
+    }
+
+    fn get_cpu_info(&self) -> io::Result<CpuInfo> {
+        cpuinfo::get_cpu_info(&self.provider)
+    }
+
+    fn get_thread_count(&self, pid: u32) -> io::Result<u64> {
+        pid_status::get_thread_count(&self.provider, pid)
+    }
+
+    fn get_oom_score(&self, pid: u32) -> io::Result<OomScore> {
+        oom_score::get_oom_score(&self.provider, pid)
     }
 }
 
@@ -67,6 +182,42 @@ impl ProcFilesystemReader {
     fn proc_meminfo_path(&self) -> PathBuf {
         self.proc_path.join("meminfo")
     }
+
+    fn proc_cpuinfo_path(&self) -> PathBuf {
+        self.proc_path.join("cpuinfo")
+    }
+
+    fn proc_loadavg_path(&self) -> PathBuf {
+        self.proc_path.join("loadavg")
+    }
+
+    fn proc_uptime_path(&self) -> PathBuf {
+        self.proc_path.join("uptime")
+    }
+
+    fn proc_vmstat_path(&self) -> PathBuf {
+        self.proc_path.join("vmstat")
+    }
+
+    fn proc_net_dev_path(&self) -> PathBuf {
+        self.proc_path.join("net/dev")
+    }
+
+    fn proc_pressure_cpu_path(&self) -> PathBuf {
+        self.proc_path.join("pressure/cpu")
+    }
+
+    fn proc_pid_status_path(&self, pid: u32) -> PathBuf {
+        self.proc_path.join(pid.to_string()).join("status")
+    }
+
+    fn proc_pid_oom_score_path(&self, pid: u32) -> PathBuf {
+        self.proc_path.join(pid.to_string()).join("oom_score")
+    }
+
+    fn proc_pid_oom_score_adj_path(&self, pid: u32) -> PathBuf {
+        self.proc_path.join(pid.to_string()).join("oom_score_adj")
+    }
 }
 
 impl ProcProvider for ProcFilesystemReader {
@@ -77,6 +228,42 @@ impl ProcProvider for ProcFilesystemReader {
     fn get_proc_meminfo(&self) -> io::Result<Vec<String>> {
         read_all_lines(self.proc_meminfo_path())
     }
+
+    fn get_proc_cpuinfo(&self) -> io::Result<Vec<String>> {
+        read_all_lines(self.proc_cpuinfo_path())
+    }
+
+    fn get_proc_loadavg(&self) -> io::Result<String> {
+        read_first_line(self.proc_loadavg_path())
+    }
+
+    fn get_proc_uptime(&self) -> io::Result<String> {
+        read_first_line(self.proc_uptime_path())
+    }
+
+    fn get_proc_vmstat(&self) -> io::Result<Vec<String>> {
+        read_all_lines(self.proc_vmstat_path())
+    }
+
+    fn get_proc_net_dev(&self) -> io::Result<Vec<String>> {
+        read_all_lines(self.proc_net_dev_path())
+    }
+
+    fn get_proc_pressure_cpu(&self) -> io::Result<Vec<String>> {
+        read_all_lines(self.proc_pressure_cpu_path())
+    }
+
+    fn get_proc_pid_status(&self, pid: u32) -> io::Result<Vec<String>> {
+        read_all_lines(self.proc_pid_status_path(pid))
+    }
+
+    fn get_proc_pid_oom_score(&self, pid: u32) -> io::Result<String> {
+        read_first_line(self.proc_pid_oom_score_path(pid))
+    }
+
+    fn get_proc_pid_oom_score_adj(&self, pid: u32) -> io::Result<String> {
+        read_first_line(self.proc_pid_oom_score_adj_path(pid))
+    }
 }
 
 /// The implementer provides proc values from somewhere, useful for mocking in tests
@@ -84,4 +271,13 @@ impl ProcProvider for ProcFilesystemReader {
 pub trait ProcProvider {
     fn get_proc_stat(&self) -> io::Result<Vec<String>>;
     fn get_proc_meminfo(&self) -> io::Result<Vec<String>>;
+    fn get_proc_cpuinfo(&self) -> io::Result<Vec<String>>;
+    fn get_proc_loadavg(&self) -> io::Result<String>;
+    fn get_proc_uptime(&self) -> io::Result<String>;
+    fn get_proc_vmstat(&self) -> io::Result<Vec<String>>;
+    fn get_proc_net_dev(&self) -> io::Result<Vec<String>>;
+    fn get_proc_pressure_cpu(&self) -> io::Result<Vec<String>>;
+    fn get_proc_pid_status(&self, pid: u32) -> io::Result<Vec<String>>;
+    fn get_proc_pid_oom_score(&self, pid: u32) -> io::Result<String>;
+    fn get_proc_pid_oom_score_adj(&self, pid: u32) -> io::Result<String>;
 }
@@ -0,0 +1,116 @@
+use crate::stats::NetworkBytes;
+use crate::stats::proc::ProcProvider;
+use std::io;
+use tracing::debug;
+
+/// Get the bytes received and transmitted so far by the network interfaces we can see (loopback left out)
+/// from `/proc/net/dev`; the throughput is the rate between consecutive readings
+pub fn get_network_bytes<P: ProcProvider>(provider: &P) -> io::Result<NetworkBytes> {
+    let lines = provider.get_proc_net_dev()?;
+    let network_bytes = parse_net_dev(&lines);
+
+    debug!("Using proc for network throughput");
+    Ok(network_bytes)
+}
+
+/// Parse the interface lines of `/proc/net/dev`, e.g. `  eth0: 1234 10 0 0 0 0 0 0 5678 20 0 0 0 0 0 0`;
+/// received bytes are the 1st column after the colon, transmitted bytes the 9th
+fn parse_net_dev(lines: &[String]) -> NetworkBytes {
+    const RX_BYTES_IDX: usize = 0;
+    const TX_BYTES_IDX: usize = 8;
+
+    lines
+        .iter()
+        .filter_map(|line| {
+            // the two header lines have no colon, and the counters may follow it without a space
+            let (interface, counters) = line.split_once(':')?;
+            let interface = interface.trim();
+            if interface == "lo" {
+                return None;
+            }
+            let counters: Vec<&str> = counters.split_whitespace().collect();
+            let bytes = |idx: usize| counters.get(idx)?.parse::<u64>().ok();
+            Some((
+                interface.to_string(),
+                (bytes(RX_BYTES_IDX)?, bytes(TX_BYTES_IDX)?),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::SummedCounterRates;
+    use crate::stats::proc::MockProcProvider;
+    use std::time::{Duration, Instant};
+
+    const HEADER: [&str; 2] = [
+        "Inter-|   Receive                                                |  Transmit",
+        " face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed",
+    ];
+
+    fn net_dev(interfaces: &[&str]) -> Vec<String> {
+        HEADER
+            .iter()
+            .chain(interfaces)
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_net_dev_skips_header_and_loopback() {
+        let lines = net_dev(&[
+            "    lo: 9999999    1000    0    0    0     0          0         0  9999999    1000    0    0    0     0       0          0",
+            "  eth0: 1048576     800    0    0    0     0          0         0   524288     400    0    0    0     0       0          0",
+        ]);
+
+        let interfaces = parse_net_dev(&lines);
+        assert_eq!(interfaces.len(), 1);
+        assert_eq!(interfaces["eth0"], (1048576, 524288));
+    }
+
+    #[test]
+    fn test_parse_net_dev_without_space_after_colon() {
+        let lines = net_dev(&["eth0:12345678901 800 0 0 0 0 0 0 524288 400 0 0 0 0 0 0"]);
+
+        assert_eq!(parse_net_dev(&lines)["eth0"], (12345678901, 524288));
+    }
+
+    #[test]
+    fn test_get_network_bytes() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider.expect_get_proc_net_dev().returning(|| {
+            Ok(net_dev(&[
+                "    lo: 9999999 1 0 0 0 0 0 0 9999999 1 0 0 0 0 0 0",
+                "  eth0: 2048 1 0 0 0 0 0 0 1024 1 0 0 0 0 0 0",
+                " veth1: 4096 1 0 0 0 0 0 0 512 1 0 0 0 0 0 0",
+            ]))
+        });
+
+        let network_bytes = get_network_bytes(&mock_provider)?;
+        assert_eq!(network_bytes.len(), 2);
+        assert_eq!(network_bytes["eth0"], (2048, 1024));
+        assert_eq!(network_bytes["veth1"], (4096, 512));
+        Ok(())
+    }
+
+    #[test]
+    fn test_throughput_between_only_counts_interfaces_in_both() {
+        let initial = NetworkBytes::from([
+            ("eth0".to_string(), (0, 0)),
+            ("veth1".to_string(), (4096, 4096)),
+        ]);
+        let current = NetworkBytes::from([
+            ("eth0".to_string(), (2048, 1024)),
+            ("eth1".to_string(), (1_000_000, 1_000_000)),
+            ("veth1".to_string(), (0, 0)), // recreated, counters reset
+        ]);
+        let start = Instant::now();
+        let mut rates = SummedCounterRates::new();
+
+        assert_eq!(rates.update(start, initial), None);
+        let rates = rates.update(start + Duration::from_secs(2), current);
+        assert_eq!(rates, Some((1024.0, 512.0)));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::stats::OomScore;
+use crate::stats::proc::ProcProvider;
+use std::io;
+
+/// Get how likely the kernel is to pick a process when out of memory, from `/proc/<pid>/oom_score{,_adj}`
+pub fn get_oom_score<R: ProcProvider>(provider: &R, pid: u32) -> io::Result<OomScore> {
+    // `oom_score` is 0-1000 (up to 2000 on older kernels) and includes the adjustment, which
+    // ranges from -1000 (never kill) to 1000 (kill first)
+    let score = parse_score(&provider.get_proc_pid_oom_score(pid)?, "oom_score", pid)?;
+    let adj = parse_score(
+        &provider.get_proc_pid_oom_score_adj(pid)?,
+        "oom_score_adj",
+        pid,
+    )?;
+    Ok(OomScore { score, adj })
+}
+
+fn parse_score(text: &str, file: &str, pid: u32) -> io::Result<i32> {
+    text.trim().parse::<i32>().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid /proc/{pid}/{file} format: {e}"),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::proc::MockProcProvider;
+    use mockall::predicate::eq;
+
+    #[test]
+    fn test_get_oom_score() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider
+            .expect_get_proc_pid_oom_score()
+            .with(eq(42))
+            .returning(|_| Ok("1666\n".to_string()));
+        mock_provider
+            .expect_get_proc_pid_oom_score_adj()
+            .with(eq(42))
+            .returning(|_| Ok("-998\n".to_string()));
+
+        let oom_score = get_oom_score(&mock_provider, 42)?;
+        assert_eq!(oom_score.score, 1666);
+        assert_eq!(oom_score.adj, -998);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_oom_score_invalid() {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider
+            .expect_get_proc_pid_oom_score()
+            .returning(|_| Ok("".to_string()));
+        mock_provider
+            .expect_get_proc_pid_oom_score_adj()
+            .returning(|_| Ok("0\n".to_string()));
+
+        let result = get_oom_score(&mock_provider, 42);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
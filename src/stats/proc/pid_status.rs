@@ -0,0 +1,58 @@
+use crate::stats::proc::ProcProvider;
+use std::io;
+
+/// Get the number of threads in a process from `/proc/<pid>/status`
+pub fn get_thread_count<R: ProcProvider>(provider: &R, pid: u32) -> io::Result<u64> {
+    let lines = provider.get_proc_pid_status(pid)?;
+
+    // e.g. `Threads:	42`
+    lines
+        .iter()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Could not find Threads in /proc/{pid}/status"),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::proc::MockProcProvider;
+    use mockall::predicate::eq;
+
+    #[test]
+    fn test_get_thread_count() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider
+            .expect_get_proc_pid_status()
+            .with(eq(42))
+            .returning(|_| {
+                Ok(vec![
+                    "Name:\tpython3".to_string(),
+                    "State:\tS (sleeping)".to_string(),
+                    "Pid:\t42".to_string(),
+                    "VmRSS:\t  524288 kB".to_string(),
+                    "Threads:\t17".to_string(),
+                    "SigQ:\t0/63704".to_string(),
+                ])
+            });
+
+        assert_eq!(get_thread_count(&mock_provider, 42)?, 17);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_thread_count_without_threads_line() {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider
+            .expect_get_proc_pid_status()
+            .returning(|_| Ok(vec!["Name:\tpython3".to_string()]));
+
+        let result = get_thread_count(&mock_provider, 42);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
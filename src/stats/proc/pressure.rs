@@ -0,0 +1,51 @@
+use crate::stats::proc::ProcProvider;
+use crate::stats::{parse_pressure_avg10, unsupported};
+use std::io;
+use tracing::debug;
+
+/// Get the share of the last 10 seconds that some of the host's tasks were waiting for CPU, in percent,
+/// from the `some` line of `/proc/pressure/cpu`
+pub fn get_cpu_pressure_some_avg10<P: ProcProvider>(provider: &P) -> io::Result<f64> {
+    let lines = provider
+        .get_proc_pressure_cpu()
+        .map_err(|e| match e.kind() {
+            // kernels without PSI, or booted with `psi=0`
+            io::ErrorKind::NotFound => unsupported("CPU pressure without PSI"),
+            _ => e,
+        })?;
+    let some_avg10 = parse_pressure_avg10(&lines, "some", "/proc/pressure/cpu")?;
+
+    debug!("Using proc for CPU pressure");
+    Ok(some_avg10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::proc::MockProcProvider;
+
+    #[test]
+    fn test_get_cpu_pressure_some_avg10() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider.expect_get_proc_pressure_cpu().returning(|| {
+            Ok(vec![
+                "some avg10=12.34 avg60=8.00 avg300=2.50 total=987654321".to_string(),
+                "full avg10=0.00 avg60=0.00 avg300=0.00 total=0".to_string(),
+            ])
+        });
+
+        assert_eq!(get_cpu_pressure_some_avg10(&mock_provider)?, 12.34);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_cpu_pressure_without_psi() {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider
+            .expect_get_proc_pressure_cpu()
+            .returning(|| Err(io::Error::from(io::ErrorKind::NotFound)));
+
+        let error = get_cpu_pressure_some_avg10(&mock_provider).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+}
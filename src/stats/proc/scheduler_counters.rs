@@ -0,0 +1,107 @@
+use crate::stats::SchedulerCounters;
+use crate::stats::proc::ProcProvider;
+use crate::utils::find_stat_value;
+use std::io;
+use tracing::debug;
+
+/// Get the cumulative context switch and interrupt counts from `/proc/stat` (host-wide)
+pub fn get_scheduler_counters<R: ProcProvider>(provider: &R) -> io::Result<SchedulerCounters> {
+    let lines = provider.get_proc_stat()?;
+
+    // `ctxt 146138886` and `intr 60444506 7 0 0 ...`, where the first number is the total
+    // and the rest are per interrupt source
+    let find = |key: &str| {
+        find_stat_value(&lines, key).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Could not find {key} in /proc/stat"),
+            )
+        })
+    };
+    let context_switches = find("ctxt")?;
+    let interrupts = find("intr")?;
+
+    debug!("Using proc for context switches and interrupts");
+    Ok(SchedulerCounters {
+        context_switches,
+        interrupts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::CounterRate;
+    use crate::stats::proc::MockProcProvider;
+    use std::time::{Duration, Instant};
+
+    fn proc_stat(intr: u64, ctxt: u64) -> Vec<String> {
+        vec![
+            "cpu  1016173 37036 291183 13457001 28111 0 9511 0 0 0".to_string(),
+            "cpu0 198607 6779 63175 1870456 4023 0 4291 0 0 0".to_string(),
+            format!("intr {intr} 7 0 0 0 4517864 0 0 0 1 0 0 0 0 0"),
+            format!("ctxt {ctxt}"),
+            "btime 1708345562".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_get_scheduler_counters() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider
+            .expect_get_proc_stat()
+            .returning(|| Ok(proc_stat(60444506, 146138886)));
+
+        let counters = get_scheduler_counters(&mock_provider)?;
+        assert_eq!(counters.context_switches, 146138886);
+        assert_eq!(counters.interrupts, 60444506);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scheduler_counter_rates_from_two_readings() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        let mut readings = vec![
+            proc_stat(60444506, 146138886),
+            proc_stat(60454506, 146188886),
+        ];
+        mock_provider
+            .expect_get_proc_stat()
+            .returning(move || Ok(readings.remove(0)));
+        let mut context_switches_rate = CounterRate::new();
+        let mut interrupts_rate = CounterRate::new();
+        let start = Instant::now();
+
+        let counters = get_scheduler_counters(&mock_provider)?;
+        assert_eq!(
+            context_switches_rate.update(start, counters.context_switches),
+            None
+        );
+        assert_eq!(interrupts_rate.update(start, counters.interrupts), None);
+
+        let later = start + Duration::from_secs(5);
+        let counters = get_scheduler_counters(&mock_provider)?;
+        assert_eq!(
+            context_switches_rate.update(later, counters.context_switches),
+            Some(10000.0)
+        );
+        assert_eq!(
+            interrupts_rate.update(later, counters.interrupts),
+            Some(2000.0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_scheduler_counters_without_ctxt() {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider.expect_get_proc_stat().returning(|| {
+            Ok(vec![
+                "cpu  1016173 37036 291183 13457001 28111 0 9511 0 0 0".to_string(),
+            ])
+        });
+
+        let result = get_scheduler_counters(&mock_provider);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
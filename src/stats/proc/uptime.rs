@@ -0,0 +1,39 @@
+use crate::stats::proc::ProcProvider;
+use std::io;
+use tracing::debug;
+
+/// Get the seconds since the host booted from `/proc/uptime`
+pub fn get_uptime_secs<P: ProcProvider>(provider: &P) -> io::Result<f64> {
+    let line = provider.get_proc_uptime()?;
+    parse_uptime(&line)
+}
+
+/// Parse the uptime from the start of a `/proc/uptime` line, e.g. `12345.67 89012.34`;
+/// the second value is the idle time summed over the CPUs
+fn parse_uptime(line: &str) -> io::Result<f64> {
+    let uptime_secs = line
+        .split_whitespace()
+        .next()
+        .and_then(|uptime| uptime.parse::<f64>().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid /proc/uptime format: {line:?}"),
+            )
+        })?;
+
+    debug!("Using proc for uptime");
+    Ok(uptime_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uptime() -> io::Result<()> {
+        assert_eq!(parse_uptime("12345.67 89012.34\n")?, 12345.67);
+        assert!(parse_uptime("").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use crate::stats::SwapCounters;
+use crate::stats::proc::ProcProvider;
+use crate::utils::find_stat_value;
+use std::io;
+use tracing::debug;
+
+/// Get the cumulative numbers of pages swapped in and out from `/proc/vmstat` (host-wide)
+pub fn get_swap_counters<R: ProcProvider>(provider: &R) -> io::Result<SwapCounters> {
+    let lines = provider.get_proc_vmstat()?;
+
+    // `pswpin 1234` and `pswpout 5678`; both are there even without any swap configured
+    let find = |key: &str| {
+        find_stat_value(&lines, key).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Could not find {key} in /proc/vmstat"),
+            )
+        })
+    };
+    let pages_in = find("pswpin")?;
+    let pages_out = find("pswpout")?;
+
+    debug!("Using proc for swap activity");
+    Ok(SwapCounters {
+        pages_in,
+        pages_out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::CounterRate;
+    use crate::stats::proc::MockProcProvider;
+    use std::time::{Duration, Instant};
+
+    fn proc_vmstat(pswpin: u64, pswpout: u64) -> Vec<String> {
+        vec![
+            "nr_free_pages 3020565".to_string(),
+            "pgpgin 10541452".to_string(),
+            "pgpgout 43217416".to_string(),
+            format!("pswpin {pswpin}"),
+            format!("pswpout {pswpout}"),
+            "pgalloc_dma 0".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_get_swap_counters() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider
+            .expect_get_proc_vmstat()
+            .returning(|| Ok(proc_vmstat(1200, 3400)));
+
+        let counters = get_swap_counters(&mock_provider)?;
+        assert_eq!(counters.pages_in, 1200);
+        assert_eq!(counters.pages_out, 3400);
+        Ok(())
+    }
+
+    #[test]
+    fn test_swap_rates_from_two_readings() -> io::Result<()> {
+        let mut mock_provider = MockProcProvider::new();
+        let mut readings = vec![proc_vmstat(1200, 3400), proc_vmstat(1250, 4400)];
+        mock_provider
+            .expect_get_proc_vmstat()
+            .returning(move || Ok(readings.remove(0)));
+        let mut swap_in_rate = CounterRate::new();
+        let mut swap_out_rate = CounterRate::new();
+        let start = Instant::now();
+
+        let counters = get_swap_counters(&mock_provider)?;
+        assert_eq!(swap_in_rate.update(start, counters.pages_in), None);
+        assert_eq!(swap_out_rate.update(start, counters.pages_out), None);
+
+        let later = start + Duration::from_secs(5);
+        let counters = get_swap_counters(&mock_provider)?;
+        assert_eq!(swap_in_rate.update(later, counters.pages_in), Some(10.0));
+        assert_eq!(swap_out_rate.update(later, counters.pages_out), Some(200.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_swap_counters_without_pswpin() {
+        let mut mock_provider = MockProcProvider::new();
+        mock_provider
+            .expect_get_proc_vmstat()
+            .returning(|| Ok(vec!["nr_free_pages 3020565".to_string()]));
+
+        let result = get_swap_counters(&mock_provider);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
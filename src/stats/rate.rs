@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Instant;
+
+/// Turns readings of a cumulative counter into a per-second rate between consecutive readings.
+#[derive(Default)]
+pub struct CounterRate {
+    previous: Option<(Instant, u64)>,
+}
+
+impl CounterRate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a new reading, returning the rate since the previous one (if there was one).
+    pub fn update(&mut self, now: Instant, value: u64) -> Option<f64> {
+        let previous = self.previous.replace((now, value));
+        let (previous_time, previous_value) = previous?;
+
+        let elapsed_secs = now.duration_since(previous_time).as_secs_f64();
+        if elapsed_secs <= 0.0 || value < previous_value {
+            // counters can go backwards if e.g. the cgroup was recreated; skip this round
+            return None;
+        }
+        Some((value - previous_value) as f64 / elapsed_secs)
+    }
+}
+
+/// Turns readings of a pair of cumulative counters per key, e.g. bytes read and written by device, into per-second
+/// rates summed across the keys.
+///
+/// A key that comes or goes between readings only counts if it's in both, and one whose counters went backwards
+/// (e.g. removed and re-added) counts for nothing, rather than the whole sum spiking or going backwards.
+pub struct SummedCounterRates<K> {
+    previous_at: Option<Instant>,
+    previous: HashMap<K, (u64, u64)>,
+}
+
+impl<K: Eq + Hash> Default for SummedCounterRates<K> {
+    fn default() -> Self {
+        Self {
+            previous_at: None,
+            previous: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash> SummedCounterRates<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a new reading, returning the summed rates since the previous one (if there was one).
+    pub fn update(&mut self, now: Instant, values: HashMap<K, (u64, u64)>) -> Option<(f64, f64)> {
+        let rates = self.previous_at.and_then(|previous_at| {
+            let elapsed_secs = now.duration_since(previous_at).as_secs_f64();
+            if elapsed_secs <= 0.0 {
+                return None;
+            }
+            let (mut first, mut second) = (0u64, 0u64);
+            for (key, (value_first, value_second)) in &values {
+                if let Some((previous_first, previous_second)) = self.previous.get(key) {
+                    first += value_first.saturating_sub(*previous_first);
+                    second += value_second.saturating_sub(*previous_second);
+                }
+            }
+            Some((first as f64 / elapsed_secs, second as f64 / elapsed_secs))
+        });
+        self.previous_at = Some(now);
+        self.previous = values;
+        rates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rate_from_two_readings() {
+        let start = Instant::now();
+        let mut rate = CounterRate::new();
+
+        assert_eq!(rate.update(start, 1000), None); // nothing to compare to yet
+        assert_eq!(
+            rate.update(start + Duration::from_secs(5), 1500),
+            Some(100.0)
+        );
+        assert_eq!(rate.update(start + Duration::from_secs(7), 1500), Some(0.0));
+    }
+
+    #[test]
+    fn test_rate_with_counter_reset() {
+        let start = Instant::now();
+        let mut rate = CounterRate::new();
+
+        rate.update(start, 1000);
+        assert_eq!(rate.update(start + Duration::from_secs(1), 10), None);
+        assert_eq!(rate.update(start + Duration::from_secs(2), 30), Some(20.0));
+    }
+}
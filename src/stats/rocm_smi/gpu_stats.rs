@@ -0,0 +1,183 @@
+use super::RocmSmiProvider;
+use crate::stats::gpu_fields::{BYTES_PER_KIB, KIB_PER_MIB, parse_field};
+use crate::stats::{GpuStats, GpuUsage};
+use std::io;
+use tracing::debug;
+
+const USAGE_COLUMN: &str = "GPU use (%)";
+const MEMORY_TOTAL_COLUMN: &str = "VRAM Total Memory (B)";
+const MEMORY_USED_COLUMN: &str = "VRAM Total Used Memory (B)";
+
+pub fn get_gpu_stats<P: RocmSmiProvider>(provider: &P) -> io::Result<GpuStats> {
+    // Format: a header naming the columns, then a line per GPU, e.g.
+    //   device,GPU use (%),GPU memory use (%),VRAM Total Memory (B),VRAM Total Used Memory (B)
+    //   card0,75,12,68702699520,8388608000
+    // the columns are looked up by name, as their order and the ones in between vary by rocm-smi version
+    let output = provider.get_rocm_gpu_stats()?;
+
+    // rocm-smi may print warnings before the table
+    let mut lines = output
+        .lines()
+        .skip_while(|line| !line.starts_with("device,"));
+    let Some(header) = lines.next() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No GPU table in rocm-smi output",
+        ));
+    };
+    let columns: Vec<&str> = header.split(',').map(|s| s.trim()).collect();
+    let column = |name: &str| columns.iter().position(|column| *column == name);
+    let (Some(usage_column), Some(memory_total_column), Some(memory_used_column)) = (
+        column(USAGE_COLUMN),
+        column(MEMORY_TOTAL_COLUMN),
+        column(MEMORY_USED_COLUMN),
+    ) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Missing columns in rocm-smi output: {header}"),
+        ));
+    };
+
+    let mut num_gpus = 0;
+    let mut total_gpu_usage = 0.0;
+    let mut total_memory_usage_kb = 0;
+    let mut total_memory_kb = 0;
+    let mut gpus = vec![];
+    let mut unparsed_fields = 0;
+
+    for line in lines {
+        let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
+        if parts.len() < columns.len() {
+            debug!("Skipping malformed line: {}", line);
+            continue;
+        }
+
+        num_gpus += 1;
+
+        let usage =
+            parse_field::<f64>(parts[usage_column], "utilization").map(|usage| usage / 100.0);
+        if let Some(usage) = usage {
+            total_gpu_usage += usage;
+        } else {
+            unparsed_fields += 1;
+        }
+
+        let memory_usage_kb = parse_field::<u64>(parts[memory_used_column], "memory used")
+            .map(|bytes| bytes / BYTES_PER_KIB);
+        if let Some(memory_usage_kb) = memory_usage_kb {
+            total_memory_usage_kb += memory_usage_kb;
+        } else {
+            unparsed_fields += 1;
+        }
+
+        let memory_total_kb = parse_field::<u64>(parts[memory_total_column], "total memory")
+            .map(|bytes| bytes / BYTES_PER_KIB);
+        if let Some(memory_total_kb) = memory_total_kb {
+            total_memory_kb += memory_total_kb;
+        } else {
+            unparsed_fields += 1;
+        }
+
+        gpus.push(GpuUsage {
+            // the devices are named e.g. `card0`
+            index: parts[0]
+                .trim_start_matches("card")
+                .parse()
+                .unwrap_or(gpus.len() as u32),
+            mig_index: None,
+            uuid: None, // only nvidia-smi's per-process stats need it
+            usage,
+            memory_usage_kb,
+            memory_total_kb,
+        });
+    }
+
+    Ok(GpuStats {
+        num_gpus,
+        gpu_usage: total_gpu_usage,
+        memory_usage_kb: total_memory_usage_kb,
+        memory_total_kb: total_memory_kb,
+        memory_usage_mib: total_memory_usage_kb / KIB_PER_MIB,
+        memory_total_mib: total_memory_kb / KIB_PER_MIB,
+        temperature_c: None,
+        power_watts: None,
+        gpus,
+        unparsed_fields,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::rocm_smi::MockRocmSmiProvider;
+
+    const HEADER: &str =
+        "device,GPU use (%),GPU memory use (%),VRAM Total Memory (B),VRAM Total Used Memory (B)";
+
+    #[test]
+    fn test_get_gpu_stats_when_available() {
+        let mut mock_provider = MockRocmSmiProvider::new();
+        mock_provider.expect_get_rocm_gpu_stats().returning(|| {
+            Ok(format!(
+                "{HEADER}\ncard0,75,12,68702699520,8388608000\ncard1,50,6,68702699520,4194304000\n"
+            ))
+        });
+
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.num_gpus, 2);
+        assert_eq!(stats.gpu_usage, 1.25); // 75% + 50% = 125% total
+        assert_eq!(stats.memory_usage_kb, 12_288_000); // (8000+4000) MiB in KiB
+        assert_eq!(stats.memory_total_kb, 134_184_960); // 2 * 64 GiB in KiB
+        assert_eq!(stats.memory_usage_mib, 12_000);
+        assert_eq!(stats.memory_total_mib, 131_040);
+        assert_eq!(stats.gpus[1].usage, Some(0.5));
+        assert_eq!(stats.gpus[1].memory_usage_kb, Some(4_096_000));
+        assert_eq!(stats.gpus[1].index, 1);
+        assert_eq!(stats.gpus[1].memory_total_kb, Some(67_092_480));
+    }
+
+    #[test]
+    fn test_get_gpu_stats_with_warnings_and_other_column_order() {
+        let mut mock_provider = MockRocmSmiProvider::new();
+        mock_provider.expect_get_rocm_gpu_stats().returning(|| {
+            Ok([
+                "WARNING: Unlocked monitor_devices lock; it should have already been unlocked.",
+                "",
+                "device,VRAM Total Memory (B),VRAM Total Used Memory (B),GPU use (%)",
+                "card0,68702699520,1048576,N/A",
+            ]
+            .join("\n"))
+        });
+
+        let stats = get_gpu_stats(&mock_provider).unwrap();
+        assert_eq!(stats.num_gpus, 1);
+        assert_eq!(stats.gpu_usage, 0.0);
+        assert_eq!(stats.memory_usage_kb, 1024);
+        assert_eq!(stats.unparsed_fields, 1); // the usage
+    }
+
+    #[test]
+    fn test_get_gpu_stats_without_table() {
+        let mut mock_provider = MockRocmSmiProvider::new();
+        mock_provider
+            .expect_get_rocm_gpu_stats()
+            .returning(|| Ok("No AMD GPUs specified\n".to_string()));
+
+        let result = get_gpu_stats(&mock_provider);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_get_gpu_stats_when_not_available() {
+        let mut mock_provider = MockRocmSmiProvider::new();
+        mock_provider.expect_get_rocm_gpu_stats().returning(|| {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Failed to run rocm-smi",
+            ))
+        });
+
+        let result = get_gpu_stats(&mock_provider);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,62 @@
+mod gpu_stats;
+
+use crate::stats::{GpuStats, GpuStatsSource};
+pub use gpu_stats::get_gpu_stats;
+use std::io;
+use std::process::Command;
+
+#[cfg(test)]
+use mockall::automock;
+use tracing::debug;
+
+#[cfg_attr(test, automock)]
+pub trait RocmSmiProvider {
+    fn get_rocm_gpu_stats(&self) -> io::Result<String>;
+}
+
+pub struct RocmSmiExecutor;
+
+impl RocmSmiExecutor {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl RocmSmiProvider for RocmSmiExecutor {
+    fn get_rocm_gpu_stats(&self) -> io::Result<String> {
+        let output = run_rocm_smi(&[
+            "--showuse",
+            "--showmemuse",
+            "--showmeminfo",
+            "vram",
+            "--csv",
+        ])?;
+        debug!("Using rocm-smi for GPU stats"); // report use here as we don't check for rocm-smi availability
+        Ok(output)
+    }
+}
+
+impl GpuStatsSource for RocmSmiExecutor {
+    fn get_gpu_stats(&self) -> io::Result<GpuStats> {
+        get_gpu_stats(self)
+    }
+}
+
+fn run_rocm_smi(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("rocm-smi").args(args).output().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Failed to run rocm-smi: {e}"),
+        )
+    })?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "rocm-smi exited with non-zero status: {}. stderr: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
@@ -1,30 +1,294 @@
-use crate::config::{Config, StatsDirConfig};
-use serde::Serialize;
-use std::fs::{self, File};
+use crate::config::{Envelope, JsonlToStdoutConfig, StatsDirConfig};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fs::{self, OpenOptions};
 use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
 use tracing::{debug, error};
 
-#[derive(Serialize, Debug)]
+/// Decimal places `time` is rounded to when serialized, `u32::MAX` for full precision; process-wide
+/// as it's set once from the config, and `serialize_with` can't be handed any state
+static TIME_PRECISION: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// Round the serialized `time` of every entry to the given number of decimal places, to keep it short
+pub fn set_time_precision(decimals: Option<u32>) {
+    TIME_PRECISION.store(decimals.unwrap_or(u32::MAX), Ordering::Relaxed);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StatsEntry {
+    // only rounded when serialized, so file names, windows and the other outputs get the exact time
+    #[serde(serialize_with = "serialize_time")]
     pub time: f64,
+    /// When the entry was taken, exactly, which `time` can't hold to the microsecond; not written out
+    #[serde(skip)]
+    sampled_at: Option<SystemTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub monotonic_ns: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rootfs_readonly: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available_controllers: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cgroup_age_secs: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub num_cpus: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_quota_cores: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_cpuset_count: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_unlimited: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub cpu_usage: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_usage_max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_seconds: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_core_usage: Option<Vec<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_nr_throttled: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_throttled_usec: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_starved: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_freq_mhz: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub memory_usage_kb: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_usage_max_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub memory_total_kb: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_over_limit: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_unlimited: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_swap_usage_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_swap_limit_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_swap_ratio: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_anon_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_file_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_usage_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_total_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_pressure_some_avg10: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_pressure_full_avg10: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_max_free_order: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub num_gpus: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gpu_usage: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_usage_weighted: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub gpu_memory_usage_kb: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gpu_memory_total_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_memory_usage_mib: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_memory_total_mib: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_temperature_c: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_power_watts: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpus: Option<Vec<PerGpuStats>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_burst_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_burst_usec: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_pressure_some_avg10: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_io_latency_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io_pressure_full_avg10: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_read_kb_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_write_kb_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub net_rx_kb_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub net_tx_kb_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub major_faults_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_switches_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interrupts_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_avg_1: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_avg_5: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_avg_15: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_uptime_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_in_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_out_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pids_current: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pids_max: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pids_ratio: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oom_kill_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oom_score: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oom_score_adj: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acolyte_bytes_written_total: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub containers: Option<Vec<ContainerStats>>,
+}
+
+/// Stats for one of the containers in the pod when running in pod mode
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContainerStats {
+    pub name: String, // name of the container's cgroup directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_cpus: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_usage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_usage_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_total_kb: Option<u64>,
+}
+
+/// Stats for one GPU, to tell a saturated card apart among idle ones that the totals would hide
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PerGpuStats {
+    pub index: u32,
+    /// Which of the card's MIG instances this is; they share the card's `index`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mig_index: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<f64>, // 0.0 - 1.0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_usage_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_total_kb: Option<u64>,
+}
+
+/// Aggregates over the whole run, written once on shutdown for job accounting
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub started_at: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ended_at: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime_secs: Option<f64>,
+    pub samples: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_usage_max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_usage_max_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_seconds_total: Option<f64>,
+    pub memory_over_limit_samples: u64,
+    /// OOM kills during the run, the last `oom_kill_count` less the first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oom_kills: Option<u64>,
+    #[serde(skip)]
+    first_oom_kill_count: Option<u64>,
+}
+
+impl Default for RunSummary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunSummary {
+    /// Start summarizing a run starting now
+    pub fn new() -> Self {
+        RunSummary {
+            started_at: unix_time_now(),
+            ended_at: None,
+            uptime_secs: None,
+            samples: 0,
+            cpu_usage_max: None,
+            memory_usage_max_kb: None,
+            cpu_seconds_total: None,
+            memory_over_limit_samples: 0,
+            oom_kills: None,
+            first_oom_kill_count: None,
+        }
+    }
+
+    /// Take a sampled entry into account, along with the CPU time used since the previous one,
+    /// which is counted even when the entry doesn't report `cpu_seconds`
+    pub fn record(&mut self, entry: &StatsEntry, cpu_seconds: Option<f64>) {
+        self.samples += 1;
+        if let Some(cpu_usage) = entry.cpu_usage {
+            self.cpu_usage_max = Some(
+                self.cpu_usage_max
+                    .map_or(cpu_usage, |max| max.max(cpu_usage)),
+            );
+        }
+        if let Some(memory_usage_kb) = entry.memory_usage_kb {
+            self.memory_usage_max_kb = self.memory_usage_max_kb.max(Some(memory_usage_kb));
+        }
+        if let Some(cpu_seconds) = cpu_seconds {
+            self.cpu_seconds_total = Some(self.cpu_seconds_total.unwrap_or(0.0) + cpu_seconds);
+        }
+        if entry.memory_over_limit == Some(true) {
+            self.memory_over_limit_samples += 1;
+        }
+        if let Some(oom_kill_count) = entry.oom_kill_count {
+            let first = *self.first_oom_kill_count.get_or_insert(oom_kill_count);
+            self.oom_kills = Some(oom_kill_count.saturating_sub(first));
+        }
+    }
+
+    pub fn finish(&mut self, uptime: Duration) {
+        self.ended_at = Some(unix_time_now());
+        self.uptime_secs = Some(uptime.as_secs_f64());
+    }
+}
+
+fn unix_time_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
 }
 
 impl Default for StatsEntry {
@@ -35,85 +299,502 @@ impl Default for StatsEntry {
 
 impl StatsEntry {
     pub fn new() -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs_f64();
+        Self::at(SystemTime::now())
+    }
 
+    /// An entry taken at the given time, with nothing in it yet
+    pub fn at(sampled_at: SystemTime) -> Self {
         StatsEntry {
-            time: now,
+            time: sampled_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            sampled_at: Some(sampled_at),
+            timestamp: None,
+            monotonic_ns: None,
+            node_name: None,
+            container_name: None,
+            container_image: None,
+            rootfs_readonly: None,
+            available_controllers: None,
+            cgroup_age_secs: None,
             num_cpus: None,
+            cpu_quota_cores: None,
+            cpu_cpuset_count: None,
+            cpu_unlimited: None,
             cpu_usage: None,
+            cpu_usage_max: None,
+            cpu_seconds: None,
+            per_core_usage: None,
+            cpu_nr_throttled: None,
+            cpu_throttled_usec: None,
+            cpu_starved: None,
+            cpu_freq_mhz: None,
             memory_usage_kb: None,
+            memory_usage_max_kb: None,
             memory_total_kb: None,
+            memory_over_limit: None,
+            memory_unlimited: None,
+            memory_swap_usage_kb: None,
+            memory_swap_limit_kb: None,
+            memory_swap_ratio: None,
+            memory_anon_kb: None,
+            memory_file_kb: None,
+            swap_usage_kb: None,
+            swap_total_kb: None,
+            memory_pressure_some_avg10: None,
+            memory_pressure_full_avg10: None,
+            memory_max_free_order: None,
             num_gpus: None,
             gpu_usage: None,
+            gpu_usage_weighted: None,
             gpu_memory_usage_kb: None,
             gpu_memory_total_kb: None,
+            gpu_memory_usage_mib: None,
+            gpu_memory_total_mib: None,
+            gpu_temperature_c: None,
+            gpu_power_watts: None,
+            gpus: None,
+            cpu_burst_count: None,
+            cpu_burst_usec: None,
+            cpu_pressure_some_avg10: None,
+            disk_io_latency_ms: None,
+            io_pressure_full_avg10: None,
+            disk_read_kb_per_sec: None,
+            disk_write_kb_per_sec: None,
+            net_rx_kb_per_sec: None,
+            net_tx_kb_per_sec: None,
+            major_faults_per_sec: None,
+            context_switches_per_sec: None,
+            interrupts_per_sec: None,
+            load_avg_1: None,
+            load_avg_5: None,
+            load_avg_15: None,
+            host_uptime_secs: None,
+            swap_in_per_sec: None,
+            swap_out_per_sec: None,
+            pids_current: None,
+            pids_max: None,
+            pids_ratio: None,
+            thread_count: None,
+            oom_kill_count: None,
+            oom_score: None,
+            oom_score_adj: None,
+            acolyte_bytes_written_total: None,
+            errors: None,
+            containers: None,
         }
     }
+
+    /// Set `timestamp` to when the entry was taken as an RFC 3339 date and time in UTC to the microsecond,
+    /// e.g. `2024-02-19T12:26:02.5Z`, for humans
+    pub fn stamp_rfc3339(&mut self) {
+        // going by `time` for a read back entry, which the float holds to a fraction of a microsecond
+        let micros = match self.sampled_at {
+            Some(sampled_at) => sampled_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros() as i128,
+            None => (self.time * 1e6).round() as i128,
+        };
+        self.timestamp = OffsetDateTime::from_unix_timestamp_nanos(micros * 1000)
+            .ok()
+            .and_then(|time| time.format(&Rfc3339).ok());
+    }
+
+    /// Set `monotonic_ns` to the time since `anchor`, which unlike `time` never jumps when the wall clock is adjusted
+    pub fn stamp_monotonic(&mut self, anchor: Instant) {
+        self.monotonic_ns = Some(anchor.elapsed().as_nanos() as u64);
+    }
+
+    /// A copy of the entry with only the given fields (and `time`) set
+    pub fn with_only_fields(&self, fields: &[String]) -> io::Result<StatsEntry> {
+        let mut value = serde_json::to_value(self)?;
+        if let serde_json::Value::Object(map) = &mut value {
+            map.retain(|field, _| field == "time" || fields.contains(field));
+        }
+        let mut entry: StatsEntry = serde_json::from_value(value)?;
+        // the round trip went through the serialized, possibly rounded time
+        entry.time = self.time;
+        entry.sampled_at = self.sampled_at;
+        Ok(entry)
+    }
+
+    /// The fields with a value that's a number, booleans as 0 and 1, for the metrics outputs;
+    /// `time` is left out, as is anything else, e.g. names and the per-container stats
+    pub fn numeric_fields(&self) -> io::Result<Vec<(String, f64)>> {
+        let serde_json::Value::Object(fields) = serde_json::to_value(self)? else {
+            return Ok(vec![]);
+        };
+        Ok(fields
+            .into_iter()
+            .filter(|(field, _)| field != "time")
+            .filter_map(|(field, value)| {
+                let value = match value {
+                    serde_json::Value::Number(number) => number.as_f64(),
+                    serde_json::Value::Bool(flag) => Some(if flag { 1.0 } else { 0.0 }),
+                    _ => None,
+                };
+                Some((field, value?))
+            })
+            .collect())
+    }
+
+    /// Whether memory usage is above the limit, which cgroup v2 can briefly show during reclaim
+    pub fn is_memory_over_limit(&self) -> Option<bool> {
+        Some(self.memory_usage_kb? > self.memory_total_kb?)
+    }
 }
 
-pub fn write_stats_entry(entry: StatsEntry, config: &Config) -> io::Result<()> {
-    match &config.output_mode {
-        crate::config::OutputMode::JsonlToStdout(jsonl_config) => {
-            let prefix = &jsonl_config.prefix;
-            let as_json = serde_json::to_string(&entry)?;
-            println!("{prefix}{as_json}");
-            Ok(())
+/// An entry as it's written out, nested in the envelope if one is configured
+pub struct Enveloped<'a> {
+    entry: WithFsSpace<'a>,
+    envelope: Option<&'a Envelope>,
+}
+
+impl<'a> Enveloped<'a> {
+    pub fn new(entry: &'a StatsEntry, envelope: Option<&'a Envelope>) -> Self {
+        Self {
+            entry: WithFsSpace {
+                entry,
+                fs_space: None,
+            },
+            envelope,
         }
-        crate::config::OutputMode::StatsDir(stats_dir_config) => {
-            write_stats_dir_entry(entry, stats_dir_config)
+    }
+
+    /// Write out the stats filesystem's space in `stats_fs_free_kb` and `stats_fs_total_kb` too
+    pub fn with_fs_space(mut self, fs_space: Option<FsSpace>) -> Self {
+        self.entry.fs_space = fs_space;
+        self
+    }
+}
+
+impl Serialize for Enveloped<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Some(envelope) = self.envelope else {
+            return self.entry.serialize(serializer);
+        };
+        let mut map = serializer.serialize_map(Some(envelope.metadata.len() + 1))?;
+        for (name, value) in &envelope.metadata {
+            map.serialize_entry(name, value)?;
         }
+        map.serialize_entry(&envelope.key, &self.entry)?;
+        map.end()
     }
 }
 
-fn write_stats_dir_entry(entry: StatsEntry, stats_dir_config: &StatsDirConfig) -> io::Result<()> {
+/// An entry with the stats filesystem's space filled in as it's serialized, rather than in a copy of it
+struct WithFsSpace<'a> {
+    entry: &'a StatsEntry,
+    fs_space: Option<FsSpace>,
+}
+
+impl Serialize for WithFsSpace<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Flattened<'a> {
+            #[serde(flatten)]
+            entry: &'a StatsEntry,
+            stats_fs_free_kb: u64,
+            stats_fs_total_kb: u64,
+        }
+        match self.fs_space {
+            None => self.entry.serialize(serializer),
+            Some(space) => Flattened {
+                entry: self.entry,
+                stats_fs_free_kb: space.free_kb,
+                stats_fs_total_kb: space.total_kb,
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+/// Write the entry as a line of JSON to `out`, i.e. standard output, returning the number of bytes written
+pub fn write_jsonl_entry(
+    out: &mut impl Write,
+    entry: &StatsEntry,
+    jsonl_config: &JsonlToStdoutConfig,
+) -> io::Result<usize> {
+    let prefix = &jsonl_config.prefix;
+    let as_json = serde_json::to_string(&Enveloped::new(entry, jsonl_config.envelope.as_ref()))?;
+    // unlike `println!`, a closed standard output is an error to report rather than a panic
+    writeln!(out, "{prefix}{as_json}")?;
+    Ok(prefix.len() + as_json.len() + 1)
+}
+
+/// The run summary as it's written in among the entries, under a `run_summary` key to tell it apart from them
+#[derive(Serialize)]
+pub struct TaggedRunSummary<'a> {
+    pub run_summary: &'a RunSummary,
+}
+
+/// Write the run summary as a line like the entries
+pub fn write_jsonl_run_summary(
+    out: &mut impl Write,
+    summary: &RunSummary,
+    jsonl_config: &JsonlToStdoutConfig,
+) -> io::Result<usize> {
+    let prefix = &jsonl_config.prefix;
+    let as_json = serde_json::to_string(&TaggedRunSummary {
+        run_summary: summary,
+    })?;
+    writeln!(out, "{prefix}{as_json}")?;
+    Ok(prefix.len() + as_json.len() + 1)
+}
+
+/// Below this share of the filesystem free, fewer stat files are kept
+const LOW_FS_FREE_RATIO: f64 = 0.1;
+
+/// The file entries are appended to with `ACOLYTE_STATS_JSONL`, and the one it's rotated to
+const JSONL_FILE_NAME: &str = "stats.jsonl";
+const ROTATED_JSONL_FILE_NAME: &str = "stats.jsonl.1";
+
+/// Free and total space of a filesystem
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FsSpace {
+    pub free_kb: u64,
+    pub total_kb: u64,
+}
+
+/// Free and total space of the filesystem the path is on
+pub fn stat_fs(path: &Path) -> io::Result<FsSpace> {
+    let c_path = std::ffi::CString::new(path.as_os_str().as_encoded_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fragment_size = stat.f_frsize as u64;
+    Ok(FsSpace {
+        // what's available to an unprivileged process, not counting the blocks reserved for root
+        free_kb: stat.f_bavail as u64 * fragment_size / 1024,
+        total_kb: stat.f_blocks as u64 * fragment_size / 1024,
+    })
+}
+
+/// The number of stat files to keep given the space left, cut down in proportion as the filesystem
+/// runs low so acolyte isn't what fills it up; always at least one
+pub fn stats_entries_to_keep(max_entries: usize, space: &FsSpace) -> usize {
+    match low_fs_space_scale(space) {
+        Some(scale) => ((max_entries as f64 * scale) as usize).max(1),
+        None => max_entries,
+    }
+}
+
+/// The size `stats.jsonl` is rotated at given the space left, cut down the same way as the number of stat files
+pub fn jsonl_bytes_to_keep(max_bytes: u64, space: &FsSpace) -> u64 {
+    match low_fs_space_scale(space) {
+        Some(scale) => ((max_bytes as f64 * scale) as u64).max(1),
+        None => max_bytes,
+    }
+}
+
+/// How much of the stats to keep once the filesystem runs low, from just under 1 down to 0 as it runs out
+fn low_fs_space_scale(space: &FsSpace) -> Option<f64> {
+    if space.total_kb == 0 {
+        return None;
+    }
+    let free_ratio = space.free_kb as f64 / space.total_kb as f64;
+    (free_ratio < LOW_FS_FREE_RATIO).then_some(free_ratio / LOW_FS_FREE_RATIO)
+}
+
+/// Write the entry to a new file in the stats directory, keeping at most `max_entries` of them,
+/// returning the number of bytes written
+pub fn write_stats_dir_entry(
+    entry: &StatsEntry,
+    stats_dir_config: &StatsDirConfig,
+    max_entries: usize,
+    fs_space: Option<FsSpace>,
+) -> io::Result<usize> {
     let dir_path = &stats_dir_config.dir;
-    ensure_dir_exists(dir_path)?;
+    ensure_dir_exists(dir_path, stats_dir_config.file_mode)?;
+    let enveloped =
+        Enveloped::new(entry, stats_dir_config.envelope.as_ref()).with_fs_space(fs_space);
+    if let Some(max_bytes) = stats_dir_config.jsonl_max_bytes {
+        let max_bytes = fs_space.map_or(max_bytes, |space| jsonl_bytes_to_keep(max_bytes, &space));
+        return append_stats_jsonl_entry(&enveloped, stats_dir_config, max_bytes);
+    }
 
     let timestamp_ms = (entry.time * 1000.0) as u64;
-    let filename = format!("stats-{timestamp_ms}.json");
+    // whatever reads compressed files decompresses them anyway, so no point in pretty-printing
+    let (filename, contents) = if stats_dir_config.compress {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        serde_json::to_writer(&mut encoder, &enveloped)?;
+        (format!("stats-{timestamp_ms}.json.gz"), encoder.finish()?)
+    } else {
+        let as_json = serde_json::to_string_pretty(&enveloped)?;
+        (format!("stats-{timestamp_ms}.json"), as_json.into_bytes())
+    };
+    // written next to it first and renamed into place, so readers never see a half-written file;
+    // the leading dot keeps it out of `stats-*` globs and the cleanup
+    let temp_path = dir_path.join(format!(".{filename}.tmp"));
     let file_path = dir_path.join(filename);
 
-    let as_json = serde_json::to_string_pretty(&entry)?;
-    let mut json_file = File::create(file_path)?;
-    json_file.write_all(as_json.as_bytes())?;
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    let mut stats_file = open_stats_file(&temp_path, options, stats_dir_config.file_mode)?;
+    if let Err(e) = stats_file
+        .write_all(&contents)
+        .and_then(|()| fs::rename(&temp_path, &file_path))
+    {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    let oldest_kept_ms = stats_dir_config
+        .max_age
+        .map(|max_age| timestamp_ms.saturating_sub(max_age.as_millis() as u64));
+    clean_up_old_stats_entries(dir_path, max_entries, oldest_kept_ms)?;
+    Ok(contents.len())
+}
+
+/// Append the entry as a line to `stats.jsonl`, first rotating the file to `stats.jsonl.1` if the line
+/// would take it past `max_bytes`; a single file growing in place is easier on log shippers and inodes
+fn append_stats_jsonl_entry(
+    enveloped: &Enveloped,
+    stats_dir_config: &StatsDirConfig,
+    max_bytes: u64,
+) -> io::Result<usize> {
+    let file_path = stats_dir_config.dir.join(JSONL_FILE_NAME);
+    let mut line = serde_json::to_string(enveloped)?;
+    line.push('\n');
+
+    let size = match fs::metadata(&file_path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e),
+    };
+    // an entry bigger than the limit still gets a file of its own rather than being dropped
+    if size > 0 && size + line.len() as u64 > max_bytes {
+        let rotated_path = stats_dir_config.dir.join(ROTATED_JSONL_FILE_NAME);
+        debug!("Rotating {:?} to {:?}", file_path, rotated_path);
+        fs::rename(&file_path, rotated_path)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.append(true).create(true);
+    let mut jsonl_file = open_stats_file(&file_path, options, stats_dir_config.file_mode)?;
+    jsonl_file.write_all(line.as_bytes())?;
+    Ok(line.len())
+}
+
+fn open_stats_file(
+    file_path: &Path,
+    mut options: OpenOptions,
+    file_mode: Option<u32>,
+) -> io::Result<fs::File> {
+    if let Some(file_mode) = file_mode {
+        // only affects newly created files and is still subject to the umask,
+        // hence the explicit `set_permissions` below
+        options.mode(file_mode);
+    }
+    let file = options.open(file_path)?;
+    if let Some(file_mode) = file_mode {
+        file.set_permissions(fs::Permissions::from_mode(file_mode))?;
+    }
+    Ok(file)
+}
+
+/// Write the run summary to `stats-summary-<timestamp>.json` in the stats directory, where cleaning up old
+/// entries leaves it be
+pub fn write_run_summary(
+    summary: &RunSummary,
+    stats_dir_config: &StatsDirConfig,
+) -> io::Result<()> {
+    let dir_path = &stats_dir_config.dir;
+    ensure_dir_exists(dir_path, stats_dir_config.file_mode)?;
 
-    clean_up_old_stats_entries(dir_path, stats_dir_config.max_stats_entries)?;
+    let timestamp_ms = (summary.ended_at.unwrap_or(summary.started_at) * 1000.0) as u64;
+    let file_path = dir_path.join(format!("stats-summary-{timestamp_ms}.json"));
+    fs::write(&file_path, serde_json::to_string_pretty(summary)?)?;
+    if let Some(file_mode) = stats_dir_config.file_mode {
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(file_mode))?;
+    }
     Ok(())
 }
 
-fn ensure_dir_exists(dir_path: &Path) -> io::Result<()> {
+/// Write node inventory to `node_info.json` in the stats directory; it isn't a stats entry,
+/// so cleaning up old entries leaves it be
+pub fn write_node_info<T: Serialize>(
+    node_info: &T,
+    stats_dir_config: &StatsDirConfig,
+) -> io::Result<()> {
+    let dir_path = &stats_dir_config.dir;
+    ensure_dir_exists(dir_path, stats_dir_config.file_mode)?;
+
+    let file_path = dir_path.join("node_info.json");
+    fs::write(&file_path, serde_json::to_string_pretty(node_info)?)?;
+    if let Some(file_mode) = stats_dir_config.file_mode {
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(file_mode))?;
+    }
+    Ok(())
+}
+
+fn ensure_dir_exists(dir_path: &Path, file_mode: Option<u32>) -> io::Result<()> {
     if !dir_path.exists() {
         debug!("Creating stats directory: {:?}", dir_path);
         fs::create_dir_all(dir_path)?;
+        if let Some(file_mode) = file_mode {
+            fs::set_permissions(dir_path, fs::Permissions::from_mode(dir_mode(file_mode)))?;
+        }
     }
     Ok(())
 }
 
-fn clean_up_old_stats_entries(dir_path: &Path, max_entries: usize) -> io::Result<()> {
-    let mut entries: Vec<PathBuf> = fs::read_dir(dir_path)?
+/// The directory mode matching a file mode; whoever may read the files must also be able to
+/// list and enter the directory, e.g. 0640 -> 0750
+fn dir_mode(file_mode: u32) -> u32 {
+    file_mode | ((file_mode & 0o444) >> 2)
+}
+
+/// Remove all but the newest `max_entries` stat files, and any with a timestamp before `oldest_kept_ms`, along
+/// with the temporary files of writes that never got renamed into place, e.g. for a crash halfway through
+fn clean_up_old_stats_entries(
+    dir_path: &Path,
+    max_entries: usize,
+    oldest_kept_ms: Option<u64>,
+) -> io::Result<()> {
+    let files: Vec<PathBuf> = fs::read_dir(dir_path)?
         .filter_map(|entry| entry.ok())
         .map(|entry| entry.path())
-        .filter(|path| {
-            path.is_file()
-                && path.extension().is_some_and(|ext| ext == "json")
-                && path
-                    .file_name()
-                    .is_some_and(|name| name.to_string_lossy().starts_with("stats-"))
-        })
+        .filter(|path| path.is_file())
         .collect();
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
 
-    if entries.len() <= max_entries {
-        return Ok(());
+    // the entry being written has been renamed by now, so any left are stale
+    for path in files
+        .iter()
+        .filter(|path| is_stats_entry_temp_file_name(&file_name(path)))
+    {
+        debug!("Removing stale temporary stats entry: {:?}", path);
+        if let Err(e) = fs::remove_file(path) {
+            error!("Failed to remove stale temporary stats entry: {:?}", e);
+        }
     }
 
+    let mut entries: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|path| is_stats_entry_file_name(&file_name(path)))
+        .collect();
+
     // Unix timestamp is in the name, so we can sort by that
     entries.sort();
 
-    let to_remove = entries.len() - max_entries;
-    for path in entries.into_iter().take(to_remove) {
+    let over_count = entries.len().saturating_sub(max_entries);
+    let is_too_old = |path: &Path| {
+        oldest_kept_ms.is_some_and(|oldest_kept_ms| {
+            stats_entry_timestamp_ms(path).is_some_and(|timestamp_ms| timestamp_ms < oldest_kept_ms)
+        })
+    };
+    for (i, path) in entries.into_iter().enumerate() {
+        if i >= over_count && !is_too_old(&path) {
+            continue;
+        }
         debug!("Removing old stats entry: {:?}", path);
         if let Err(e) = fs::remove_file(&path) {
             error!("Failed to remove old stats entry: {:?}", e);
@@ -122,3 +803,508 @@ fn clean_up_old_stats_entries(dir_path: &Path, max_entries: usize) -> io::Result
 
     Ok(())
 }
+
+/// The timestamp in a stat file's name, e.g. `1708345562500` for `stats-1708345562500.json`
+fn stats_entry_timestamp_ms(path: &Path) -> Option<u64> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_prefix("stats-")?.split('.').next()?.parse().ok()
+}
+
+fn is_stats_entry_file_name(name: &str) -> bool {
+    // compressed or not, so switching `ACOLYTE_COMPRESS` on or off still cleans up the other kind
+    name.starts_with("stats-")
+        && !name.starts_with("stats-summary-")
+        && (name.ends_with(".json") || name.ends_with(".json.gz"))
+}
+
+/// e.g. `.stats-1708345562500.json.tmp`, a stat file yet to be renamed into place
+fn is_stats_entry_temp_file_name(name: &str) -> bool {
+    name.strip_prefix('.')
+        .and_then(|name| name.strip_suffix(".tmp"))
+        .is_some_and(is_stats_entry_file_name)
+}
+
+fn serialize_time<S: Serializer>(time: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    match TIME_PRECISION.load(Ordering::Relaxed) {
+        u32::MAX => serializer.serialize_f64(*time),
+        decimals => serializer.serialize_f64(round_time(*time, decimals)),
+    }
+}
+
+fn round_time(time: f64, decimals: u32) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    (time * scale).round() / scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_write_stats_dir_entry_with_file_mode() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let stats_dir = temp_dir.path().join("stats");
+        let config = StatsDirConfig {
+            dir: stats_dir.clone(),
+            max_stats_entries: 12,
+            max_age: None,
+            file_mode: Some(0o640),
+            envelope: None,
+            watch_fs_space: false,
+            compress: false,
+            jsonl_max_bytes: None,
+        };
+
+        write_stats_dir_entry(&StatsEntry::new(), &config, config.max_stats_entries, None)?;
+
+        let dir_mode = fs::metadata(&stats_dir)?.permissions().mode();
+        assert_eq!(dir_mode & 0o7777, 0o750);
+        let file_path = fs::read_dir(&stats_dir)?.next().unwrap()?.path();
+        let file_mode = fs::metadata(file_path)?.permissions().mode();
+        assert_eq!(file_mode & 0o7777, 0o640);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_stats_dir_entry_leaves_only_complete_file() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config = StatsDirConfig {
+            dir: temp_dir.path().to_path_buf(),
+            max_stats_entries: 12,
+            max_age: None,
+            file_mode: None,
+            envelope: None,
+            watch_fs_space: false,
+            compress: false,
+            jsonl_max_bytes: None,
+        };
+        let mut entry = StatsEntry::new();
+        entry.time = 1708345562.5;
+        entry.cpu_usage = Some(1.5);
+
+        write_stats_dir_entry(&entry, &config, config.max_stats_entries, None)?;
+
+        let files: Vec<_> = fs::read_dir(temp_dir.path())?
+            .map(|file| file.map(|file| file.path()))
+            .collect::<io::Result<_>>()?;
+        assert_eq!(
+            files,
+            vec![temp_dir.path().join("stats-1708345562500.json")]
+        );
+        let written: StatsEntry = serde_json::from_slice(&fs::read(&files[0])?)?;
+        assert_eq!(written.cpu_usage, Some(1.5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_up_old_stats_entries_by_age_and_count() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let file_names = |dir: &Path| -> io::Result<Vec<String>> {
+            let mut names: Vec<String> = fs::read_dir(dir)?
+                .map(|file| file.map(|file| file.file_name().to_string_lossy().into_owned()))
+                .collect::<io::Result<_>>()?;
+            names.sort();
+            Ok(names)
+        };
+        for timestamp_ms in [1000, 2000, 3000, 4000, 5000] {
+            fs::write(
+                temp_dir.path().join(format!("stats-{timestamp_ms}.json")),
+                "{}",
+            )?;
+        }
+        fs::write(temp_dir.path().join("stats-summary-500.json"), "{}")?;
+
+        // the count cap alone leaves the newest 4
+        clean_up_old_stats_entries(temp_dir.path(), 4, None)?;
+        assert_eq!(file_names(temp_dir.path())?.len(), 4 + 1);
+
+        // the age cap removes more than the count cap would
+        clean_up_old_stats_entries(temp_dir.path(), 4, Some(3000))?;
+        assert_eq!(
+            file_names(temp_dir.path())?,
+            vec![
+                "stats-3000.json",
+                "stats-4000.json",
+                "stats-5000.json",
+                "stats-summary-500.json"
+            ]
+        );
+
+        // and the count cap still applies to files new enough to keep
+        clean_up_old_stats_entries(temp_dir.path(), 1, Some(3000))?;
+        assert_eq!(
+            file_names(temp_dir.path())?,
+            vec!["stats-5000.json", "stats-summary-500.json"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_up_removes_stale_temp_files() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        fs::write(temp_dir.path().join(".stats-1000.json.tmp"), "{")?;
+        fs::write(temp_dir.path().join(".stats-2000.json.gz.tmp"), "")?;
+        fs::write(temp_dir.path().join("stats-3000.json"), "{}")?;
+        fs::write(temp_dir.path().join(".other.tmp"), "")?;
+
+        clean_up_old_stats_entries(temp_dir.path(), 4, None)?;
+
+        let mut names: Vec<String> = fs::read_dir(temp_dir.path())?
+            .map(|file| file.map(|file| file.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<_>>()?;
+        names.sort();
+        assert_eq!(names, vec![".other.tmp", "stats-3000.json"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_entry_timestamp_ms() {
+        let timestamp = |name: &str| stats_entry_timestamp_ms(Path::new(name));
+        assert_eq!(
+            timestamp("/tmp/stats-1708345562500.json"),
+            Some(1708345562500)
+        );
+        assert_eq!(
+            timestamp("stats-1708345562500.json.gz"),
+            Some(1708345562500)
+        );
+        assert_eq!(timestamp("stats-summary-1708345562500.json"), None);
+    }
+
+    #[test]
+    fn test_write_compressed_stats_dir_entries() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let mut config = StatsDirConfig {
+            dir: temp_dir.path().to_path_buf(),
+            max_stats_entries: 2,
+            max_age: None,
+            file_mode: None,
+            envelope: None,
+            watch_fs_space: false,
+            compress: false,
+            jsonl_max_bytes: None,
+        };
+        let mut entry = StatsEntry::new();
+        entry.time = 1708345562.5;
+        entry.cpu_usage = Some(1.5);
+        write_stats_dir_entry(&entry, &config, config.max_stats_entries, None)?;
+
+        config.compress = true;
+        for i in 1..3 {
+            entry.time += i as f64;
+            write_stats_dir_entry(&entry, &config, config.max_stats_entries, None)?;
+        }
+
+        // the uncompressed one is the oldest, and counts towards the limit
+        let mut files: Vec<_> = fs::read_dir(temp_dir.path())?
+            .map(|file| file.map(|file| file.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<_>>()?;
+        files.sort();
+        assert_eq!(
+            files,
+            vec!["stats-1708345563500.json.gz", "stats-1708345565500.json.gz"]
+        );
+
+        let compressed = fs::File::open(temp_dir.path().join(&files[1]))?;
+        let mut as_json = String::new();
+        flate2::read::GzDecoder::new(compressed).read_to_string(&mut as_json)?;
+        assert!(!as_json.contains('\n')); // compact
+        let decompressed: StatsEntry = serde_json::from_str(&as_json)?;
+        assert_eq!(decompressed.cpu_usage, Some(1.5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_stats_jsonl_entry_rotates() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let mut entry = StatsEntry::new();
+        entry.time = 1708345562.5;
+        let line_len = serde_json::to_string(&entry)?.len() as u64 + 1;
+        let config = StatsDirConfig {
+            dir: temp_dir.path().to_path_buf(),
+            max_stats_entries: 12,
+            max_age: None,
+            file_mode: None,
+            envelope: None,
+            watch_fs_space: false,
+            compress: false,
+            jsonl_max_bytes: Some(line_len * 2),
+        };
+
+        for _ in 0..3 {
+            write_stats_dir_entry(&entry, &config, config.max_stats_entries, None)?;
+        }
+
+        let current = fs::read_to_string(temp_dir.path().join(JSONL_FILE_NAME))?;
+        let rotated = fs::read_to_string(temp_dir.path().join(ROTATED_JSONL_FILE_NAME))?;
+        assert_eq!(current.lines().count(), 1);
+        assert_eq!(rotated.lines().count(), 2);
+        let line: StatsEntry = serde_json::from_str(current.lines().next().unwrap())?;
+        assert_eq!(line.time, 1708345562.5);
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 2); // no per-entry files
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_stats_jsonl_entry_rotates_sooner_when_low_on_space() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let entry = StatsEntry::new();
+        let config = StatsDirConfig {
+            dir: temp_dir.path().to_path_buf(),
+            max_stats_entries: 12,
+            max_age: None,
+            file_mode: None,
+            envelope: None,
+            watch_fs_space: true,
+            compress: false,
+            jsonl_max_bytes: Some(10 * 1024 * 1024),
+        };
+        let low_space = FsSpace {
+            free_kb: 0,
+            total_kb: 1000,
+        };
+
+        for _ in 0..3 {
+            write_stats_dir_entry(&entry, &config, 1, Some(low_space))?;
+        }
+
+        // each line rotated out as soon as there's another, rather than growing to the 10 MB
+        let current = fs::read_to_string(temp_dir.path().join(JSONL_FILE_NAME))?;
+        let rotated = fs::read_to_string(temp_dir.path().join(ROTATED_JSONL_FILE_NAME))?;
+        assert_eq!(current.lines().count(), 1);
+        assert_eq!(rotated.lines().count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stamp_rfc3339() -> io::Result<()> {
+        let at = |nanos| UNIX_EPOCH + Duration::new(1708345562, nanos);
+        let mut entry = StatsEntry::at(at(500_000_000));
+        entry.stamp_rfc3339();
+        assert_eq!(entry.timestamp.as_deref(), Some("2024-02-19T12:26:02.5Z"));
+
+        let mut entry = StatsEntry::at(at(0));
+        entry.stamp_rfc3339();
+        assert_eq!(entry.timestamp.as_deref(), Some("2024-02-19T12:26:02Z"));
+
+        // cut to the microsecond, without the float's noise in the digits after it
+        let mut entry = StatsEntry::at(at(123_456_789));
+        entry.stamp_rfc3339();
+        assert_eq!(
+            entry.timestamp.as_deref(),
+            Some("2024-02-19T12:26:02.123456Z")
+        );
+
+        let mut entry: StatsEntry = serde_json::from_str(r#"{"time": 1708345562.123}"#)?;
+        entry.stamp_rfc3339();
+        assert_eq!(entry.timestamp.as_deref(), Some("2024-02-19T12:26:02.123Z"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_time() {
+        // the process-wide precision is left alone, as other tests serialize entries in parallel
+        let time = 1708345562.123456;
+        assert_eq!(round_time(time, 3), 1708345562.123);
+        assert_eq!(round_time(time, 1), 1708345562.1);
+        assert_eq!(round_time(time, 0), 1708345562.0);
+    }
+
+    #[test]
+    fn test_monotonic_ns_increases_across_entries() {
+        let started = Instant::now();
+        let mut previous = 0;
+        for _ in 0..3 {
+            let mut entry = StatsEntry::new();
+            entry.stamp_monotonic(started);
+            let monotonic_ns = entry.monotonic_ns.unwrap();
+            assert!(monotonic_ns > previous);
+            previous = monotonic_ns;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_stats_entries_to_keep() {
+        let space = |free_kb| FsSpace {
+            free_kb,
+            total_kb: 1000,
+        };
+        assert_eq!(stats_entries_to_keep(12, &space(500)), 12);
+        assert_eq!(stats_entries_to_keep(12, &space(100)), 12);
+        assert_eq!(stats_entries_to_keep(12, &space(50)), 6);
+        assert_eq!(stats_entries_to_keep(12, &space(0)), 1);
+        // a filesystem that reports no size at all is left be
+        assert_eq!(
+            stats_entries_to_keep(
+                12,
+                &FsSpace {
+                    free_kb: 0,
+                    total_kb: 0
+                }
+            ),
+            12
+        );
+    }
+
+    #[test]
+    fn test_jsonl_bytes_to_keep() {
+        let space = |free_kb| FsSpace {
+            free_kb,
+            total_kb: 1000,
+        };
+        assert_eq!(jsonl_bytes_to_keep(1000, &space(500)), 1000);
+        assert_eq!(jsonl_bytes_to_keep(1000, &space(50)), 500);
+        assert_eq!(jsonl_bytes_to_keep(1000, &space(0)), 1);
+    }
+
+    #[test]
+    fn test_run_summary_aggregates_samples() {
+        let mut summary = RunSummary::new();
+        for (cpu_usage, memory_usage_kb, memory_total_kb, cpu_seconds, oom_kill_count) in [
+            (0.5, 1000, 2000, None, 3),
+            (1.5, 2500, 2000, Some(1.5), 4),
+            (1.0, 1500, 2000, Some(1.0), 5),
+        ] {
+            let mut entry = StatsEntry::new();
+            entry.oom_kill_count = Some(oom_kill_count);
+            entry.cpu_usage = Some(cpu_usage);
+            entry.memory_usage_kb = Some(memory_usage_kb);
+            entry.memory_total_kb = Some(memory_total_kb);
+            entry.memory_over_limit = entry.is_memory_over_limit();
+            summary.record(&entry, cpu_seconds);
+        }
+        summary.finish(Duration::from_secs(30));
+
+        assert_eq!(summary.samples, 3);
+        assert_eq!(summary.cpu_usage_max, Some(1.5));
+        assert_eq!(summary.memory_usage_max_kb, Some(2500));
+        assert_eq!(summary.cpu_seconds_total, Some(2.5));
+        assert_eq!(summary.memory_over_limit_samples, 1);
+        assert_eq!(summary.oom_kills, Some(2)); // the kills from before the run don't count
+        assert_eq!(summary.uptime_secs, Some(30.0));
+        assert!(summary.ended_at.is_some());
+    }
+
+    #[test]
+    fn test_cleanup_leaves_run_summary_be() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config = StatsDirConfig {
+            dir: temp_dir.path().to_path_buf(),
+            max_stats_entries: 1,
+            max_age: None,
+            file_mode: None,
+            envelope: None,
+            watch_fs_space: false,
+            compress: false,
+            jsonl_max_bytes: None,
+        };
+        let mut summary = RunSummary::new();
+        summary.finish(Duration::from_secs(30));
+        write_run_summary(&summary, &config)?;
+        let mut entry = StatsEntry::new();
+        for _ in 0..2 {
+            entry.time += 1.0;
+            write_stats_dir_entry(&entry, &config, config.max_stats_entries, None)?;
+        }
+
+        let mut names: Vec<String> = fs::read_dir(temp_dir.path())?
+            .map(|file| Ok(file?.file_name().to_string_lossy().to_string()))
+            .collect::<io::Result<_>>()?;
+        names.sort();
+        assert_eq!(names.len(), 2);
+        assert!(names[1].starts_with("stats-summary-"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dir_mode() {
+        assert_eq!(dir_mode(0o600), 0o700);
+        assert_eq!(dir_mode(0o644), 0o755);
+        assert_eq!(dir_mode(0o640), 0o750);
+    }
+
+    #[test]
+    fn test_is_memory_over_limit() {
+        let mut entry = StatsEntry::new();
+        entry.memory_usage_kb = Some(1_048_640);
+        assert_eq!(entry.is_memory_over_limit(), None);
+
+        entry.memory_total_kb = Some(1_048_576);
+        assert_eq!(entry.is_memory_over_limit(), Some(true));
+
+        entry.memory_usage_kb = Some(1_048_576);
+        assert_eq!(entry.is_memory_over_limit(), Some(false));
+    }
+
+    #[test]
+    fn test_write_jsonl_entry() -> io::Result<()> {
+        let jsonl_config = JsonlToStdoutConfig {
+            prefix: "acolyte: ".to_string(),
+            envelope: None,
+        };
+        let mut entry = StatsEntry::new();
+        entry.time = 1708345562.5;
+        entry.cpu_usage = Some(1.5);
+
+        let mut out = vec![];
+        let written = write_jsonl_entry(&mut out, &entry, &jsonl_config)?;
+        write_jsonl_entry(&mut out, &entry, &jsonl_config)?;
+
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(written * 2, out.len());
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "acolyte: {\"time\":1708345562.5,\"cpu_usage\":1.5}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_jsonl_run_summary() -> io::Result<()> {
+        let jsonl_config = JsonlToStdoutConfig {
+            prefix: "acolyte: ".to_string(),
+            envelope: None,
+        };
+        let mut summary = RunSummary::new();
+        summary.started_at = 1708345562.5;
+
+        let mut out = vec![];
+        let written = write_jsonl_run_summary(&mut out, &summary, &jsonl_config)?;
+
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(written, out.len());
+        assert_eq!(
+            out,
+            "acolyte: {\"run_summary\":{\"started_at\":1708345562.5,\"samples\":0,\"memory_over_limit_samples\":0}}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_enveloped_entry() -> io::Result<()> {
+        let envelope = Envelope {
+            key: "data".to_string(),
+            metadata: vec![("type".to_string(), "acolyte_stats".to_string())],
+        };
+        let mut entry = StatsEntry::new();
+        entry.time = 1708345562.5;
+        entry.cpu_usage = Some(1.5);
+
+        let as_json = serde_json::to_value(Enveloped::new(&entry, Some(&envelope)))?;
+        assert_eq!(
+            as_json,
+            serde_json::json!({
+                "type": "acolyte_stats",
+                "data": {"time": 1708345562.5, "cpu_usage": 1.5},
+            })
+        );
+
+        let as_json = serde_json::to_value(Enveloped::new(&entry, None))?;
+        assert_eq!(as_json, serde_json::to_value(&entry)?);
+        Ok(())
+    }
+}
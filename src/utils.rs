@@ -1,6 +1,9 @@
+use std::collections::hash_map::RandomState;
 use std::fs::File;
+use std::hash::BuildHasher;
 use std::io::{self, BufRead, BufReader};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 pub fn read_first_line<P: AsRef<Path>>(path: P) -> io::Result<String> {
     let file = File::open(path)?;
@@ -23,3 +26,71 @@ pub fn get_path_or_croak<'a>(
     path.as_ref()
         .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{thing} file not found")))
 }
+
+/// Find a `key value` pair from flat-keyed stat files like `cpu.stat` or `memory.stat`.
+///
+/// Fields are located by name, so the order of lines in the file doesn't matter.
+pub fn find_stat_value(lines: &[String], key: &str) -> Option<u64> {
+    lines.iter().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        if parts.next() != Some(key) {
+            return None;
+        }
+        parts.next().and_then(|value| value.parse::<u64>().ok())
+    })
+}
+
+/// A random number that's good enough for spreading things out, not for anything secret.
+pub fn random_u64() -> u64 {
+    // every `RandomState` is seeded differently, which saves pulling in a `rand` dependency
+    RandomState::new().hash_one(0u8)
+}
+
+/// Shift `interval` by up to `jitter` in either direction, picking the offset from `random`.
+///
+/// The intervals average out to `interval`, so the sampling rate stays the same over time.
+pub fn jitter_interval(interval: Duration, jitter: Duration, random: u64) -> Duration {
+    let jitter_ms = jitter.as_millis() as u64;
+    if jitter_ms == 0 {
+        return interval;
+    }
+    let offset_ms = random % (2 * jitter_ms + 1);
+    (interval + Duration::from_millis(offset_ms)).saturating_sub(jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jitter_interval_stays_within_bounds() {
+        let interval = Duration::from_millis(5000);
+        let jitter = Duration::from_millis(250);
+        for random in [0, 1, 250, 500, 501, u64::MAX, random_u64(), random_u64()] {
+            let jittered = jitter_interval(interval, jitter, random);
+            assert!(jittered >= Duration::from_millis(4750), "{jittered:?}");
+            assert!(jittered <= Duration::from_millis(5250), "{jittered:?}");
+        }
+        assert_eq!(
+            jitter_interval(interval, jitter, 0),
+            Duration::from_millis(4750)
+        );
+        assert_eq!(
+            jitter_interval(interval, jitter, 500),
+            Duration::from_millis(5250)
+        );
+    }
+
+    #[test]
+    fn test_jitter_interval_without_jitter() {
+        let interval = Duration::from_millis(5000);
+        assert_eq!(jitter_interval(interval, Duration::ZERO, 1234), interval);
+    }
+
+    #[test]
+    fn test_jitter_interval_larger_than_interval() {
+        let interval = Duration::from_millis(100);
+        let jittered = jitter_interval(interval, Duration::from_millis(500), 0);
+        assert_eq!(jittered, Duration::ZERO);
+    }
+}